@@ -0,0 +1,313 @@
+use std::{env, path::PathBuf};
+
+use amqprs::{connection::OpenConnectionArguments, security::SecurityCredentials, tls::TlsAdaptor};
+use serde::Deserialize;
+use url::Url;
+
+use crate::{Encoding, EnvelopeError, QueueError, MESSAGE_VERSION, MIN_MESSAGE_VERSION};
+
+const DEFAULT_PORT: u16 = 5672;
+
+/// How the client authenticates with the broker
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMechanism {
+    /// Username and password
+    #[default]
+    Plain,
+    /// Identity taken from the TLS client certificate
+    External,
+}
+
+/// Connection to the RabbitMQ broker and format of the published messages, shared by all queues of a service.
+/// Loaded from the `RABBITMQ_*` env variables, services with a config file can embed it.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct BrokerConfig {
+    /// e.g. `amqp://localhost:5672`, `amqps` enables TLS
+    pub endpoint: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub auth_mechanism: AuthMechanism,
+    /// Defaults to `/`
+    pub virtual_host: Option<String>,
+    /// Heartbeat timeout negotiated with the broker, defaults to 60s
+    pub heartbeat_sec: Option<u16>,
+    /// Prefix of the exchanges, queues and routing keys, so deployments can share a broker
+    pub namespace: Option<String>,
+    /// CA bundle verifying the broker certificate, defaults to the public CAs
+    pub ca_file: Option<PathBuf>,
+    /// Client certificate and key, enable mutual TLS
+    pub client_cert_file: Option<PathBuf>,
+    pub client_key_file: Option<PathBuf>,
+    /// Version of the published messages, lower it during rolling upgrades
+    pub message_version: u32,
+    /// Encoding of the published messages, switch to binary once all consumers read it
+    pub message_encoding: Encoding,
+}
+
+impl Default for BrokerConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: String::new(),
+            username: None,
+            password: None,
+            auth_mechanism: AuthMechanism::default(),
+            virtual_host: None,
+            heartbeat_sec: None,
+            namespace: None,
+            ca_file: None,
+            client_cert_file: None,
+            client_key_file: None,
+            message_version: MESSAGE_VERSION,
+            message_encoding: Encoding::default(),
+        }
+    }
+}
+
+impl BrokerConfig {
+    /// Load the configuration from the env variables only
+    pub fn from_env() -> Result<Self, QueueError> {
+        let mut config = Self::default();
+        config.apply_env()?;
+        Ok(config)
+    }
+
+    /// Override the values with the env variables that are set
+    pub fn apply_env(&mut self) -> Result<(), QueueError> {
+        if let Ok(endpoint) = env::var("RABBITMQ_ENDPOINT") {
+            self.endpoint = endpoint;
+        }
+        if let Ok(username) = env::var("RABBITMQ_USERNAME") {
+            self.username = Some(username);
+        }
+        if let Ok(password) = env::var("RABBITMQ_PASSWORD") {
+            self.password = Some(password);
+        }
+        if let Ok(mechanism) = env::var("RABBITMQ_AUTH_MECHANISM") {
+            self.auth_mechanism = match mechanism.to_lowercase().as_str() {
+                "plain" => AuthMechanism::Plain,
+                "external" => AuthMechanism::External,
+                _ => {
+                    return Err(config_error(format!(
+                        "Invalid RABBITMQ_AUTH_MECHANISM: {}",
+                        mechanism
+                    )))
+                }
+            };
+        }
+        if let Ok(virtual_host) = env::var("RABBITMQ_VHOST") {
+            self.virtual_host = Some(virtual_host);
+        }
+        if let Ok(heartbeat) = env::var("RABBITMQ_HEARTBEAT_SEC") {
+            self.heartbeat_sec = Some(
+                heartbeat
+                    .parse()
+                    .map_err(|_| config_error("Invalid RABBITMQ_HEARTBEAT_SEC value"))?,
+            );
+        }
+        if let Ok(namespace) = env::var("RABBITMQ_NAMESPACE") {
+            self.namespace = Some(namespace).filter(|namespace| !namespace.is_empty());
+        }
+        if let Ok(ca_file) = env::var("RABBITMQ_CA_FILE") {
+            self.ca_file = Some(PathBuf::from(ca_file));
+        }
+        if let Ok(cert_file) = env::var("RABBITMQ_CLIENT_CERT_FILE") {
+            self.client_cert_file = Some(PathBuf::from(cert_file));
+        }
+        if let Ok(key_file) = env::var("RABBITMQ_CLIENT_KEY_FILE") {
+            self.client_key_file = Some(PathBuf::from(key_file));
+        }
+        if let Ok(version) = env::var("RABBITMQ_MESSAGE_VERSION") {
+            self.message_version = version
+                .parse()
+                .map_err(|_| config_error("Invalid RABBITMQ_MESSAGE_VERSION value"))?;
+        }
+        if let Ok(encoding) = env::var("RABBITMQ_MESSAGE_ENCODING") {
+            self.message_encoding = encoding
+                .parse()
+                .map_err(|e: EnvelopeError| config_error(e.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Prepended to the exchange, queue and topic names, empty without a namespace
+    pub fn namespace_prefix(&self) -> Result<String, QueueError> {
+        let Some(namespace) = &self.namespace else {
+            return Ok(String::new());
+        };
+
+        // Dots separate the words of topic routing keys
+        let is_valid = !namespace.is_empty()
+            && namespace
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !is_valid {
+            return Err(config_error(format!(
+                "Invalid RABBITMQ_NAMESPACE: {}, use letters, digits, - and _",
+                namespace
+            )));
+        }
+
+        Ok(format!("{}.", namespace))
+    }
+
+    /// Validate the configuration and build the connection arguments
+    pub(crate) fn connection_args(&self) -> Result<OpenConnectionArguments, QueueError> {
+        if !(MIN_MESSAGE_VERSION..=MESSAGE_VERSION).contains(&self.message_version) {
+            return Err(config_error(
+                EnvelopeError::UnsupportedVersion(self.message_version).to_string(),
+            ));
+        }
+        if self.endpoint.is_empty() {
+            return Err(config_error("RABBITMQ_ENDPOINT must be set"));
+        }
+        let parsed_url = Url::parse(&self.endpoint).map_err(|e| {
+            config_error(format!("Invalid URL format for RABBITMQ_ENDPOINT: {}", e))
+        })?;
+
+        let addr = parsed_url
+            .host_str()
+            .ok_or_else(|| config_error("RABBITMQ_ENDPOINT must contain a host"))?;
+
+        let is_ssl = match parsed_url.scheme() {
+            "amqp" | "http" => false,
+            "amqps" | "amqps+ssl" | "amqps+tls" | "https" => true,
+            scheme => {
+                return Err(config_error(format!(
+                    "Invalid scheme for RABBITMQ_ENDPOINT: {}",
+                    scheme
+                )))
+            }
+        };
+
+        let port = parsed_url.port().unwrap_or(DEFAULT_PORT);
+
+        let client_auth =
+            match (&self.client_cert_file, &self.client_key_file) {
+                (Some(cert_file), Some(key_file)) => Some((cert_file, key_file)),
+                (None, None) => None,
+                _ => return Err(config_error(
+                    "RABBITMQ_CLIENT_CERT_FILE and RABBITMQ_CLIENT_KEY_FILE must be set together",
+                )),
+            };
+        if client_auth.is_some() && !is_ssl {
+            return Err(config_error(
+                "Client certificate requires an amqps RABBITMQ_ENDPOINT",
+            ));
+        }
+
+        let mut args = match self.auth_mechanism {
+            AuthMechanism::Plain => {
+                let username = self
+                    .username
+                    .as_deref()
+                    .ok_or_else(|| config_error("RABBITMQ_USERNAME must be set"))?;
+                let password = self
+                    .password
+                    .as_deref()
+                    .ok_or_else(|| config_error("RABBITMQ_PASSWORD must be set"))?;
+                OpenConnectionArguments::new(addr, port, username, password)
+            }
+            AuthMechanism::External => {
+                if client_auth.is_none() {
+                    return Err(config_error(
+                        "EXTERNAL authentication requires a client certificate",
+                    ));
+                }
+                OpenConnectionArguments::new(addr, port, "", "")
+                    .credentials(SecurityCredentials::new_external())
+                    .finish()
+            }
+        };
+
+        if is_ssl {
+            let ca_file = self.ca_file.as_deref();
+            let tls_adaptor = match client_auth {
+                Some((cert_file, key_file)) => {
+                    TlsAdaptor::with_client_auth(ca_file, cert_file, key_file, addr.to_string())
+                }
+                None => TlsAdaptor::without_client_auth(ca_file, addr.to_string()),
+            }
+            .map_err(|e| config_error(format!("Failed to load TLS certificates: {}", e)))?;
+            args.tls_adaptor(tls_adaptor);
+        }
+        if let Some(virtual_host) = &self.virtual_host {
+            args.virtual_host(virtual_host);
+        }
+        if let Some(heartbeat_sec) = self.heartbeat_sec {
+            args.heartbeat(heartbeat_sec);
+        }
+
+        Ok(args)
+    }
+}
+
+fn config_error(reason: impl Into<String>) -> QueueError {
+    QueueError::Config(reason.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(endpoint: &str) -> BrokerConfig {
+        BrokerConfig {
+            endpoint: endpoint.to_string(),
+            username: Some("guest".to_string()),
+            password: Some("guest".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn builds_plain_connection() {
+        assert!(config("amqp://localhost:5672").connection_args().is_ok());
+        assert!(config("ftp://localhost").connection_args().is_err());
+
+        let without_password = BrokerConfig {
+            password: None,
+            ..config("amqp://localhost")
+        };
+        assert!(without_password.connection_args().is_err());
+
+        let unsupported_version = BrokerConfig {
+            message_version: MESSAGE_VERSION + 1,
+            ..config("amqp://localhost")
+        };
+        assert!(unsupported_version.connection_args().is_err());
+    }
+
+    #[test]
+    fn validates_namespace() {
+        let mut config = config("amqp://localhost");
+        assert_eq!(config.namespace_prefix().unwrap(), "");
+
+        config.namespace = Some("staging".to_string());
+        assert_eq!(config.namespace_prefix().unwrap(), "staging.");
+
+        config.namespace = Some("staging.eu".to_string());
+        assert!(config.namespace_prefix().is_err());
+    }
+
+    #[test]
+    fn requires_client_certificate_for_external_auth() {
+        let external = BrokerConfig {
+            username: None,
+            password: None,
+            auth_mechanism: AuthMechanism::External,
+            ..config("amqps://localhost:5671")
+        };
+        assert!(external.connection_args().is_err());
+
+        // Client certificates are sent only over TLS
+        let plain_text = BrokerConfig {
+            endpoint: "amqp://localhost".to_string(),
+            client_cert_file: Some(PathBuf::from("client.pem")),
+            client_key_file: Some(PathBuf::from("client.key")),
+            ..external
+        };
+        assert!(plain_text.connection_args().is_err());
+    }
+}
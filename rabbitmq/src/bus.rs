@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::{ConnectionState, Envelope, Message};
+
+pub type BusError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Message received from the bus
+#[derive(Debug, Clone)]
+pub struct Delivery {
+    /// Id of the message, the same on every redelivery
+    pub message_id: Option<String>,
+    pub body: Vec<u8>,
+    pub content_type: Option<String>,
+    /// Delivered before, but not acknowledged
+    pub redelivered: bool,
+    /// Set when the message was dead-lettered from another queue
+    pub dead_letter: Option<DeadLetter>,
+    /// Ed25519 signature of the body, set when the publisher signs its messages
+    pub signature: Option<Vec<u8>>,
+}
+
+/// Where and why the message was dead-lettered
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    /// Queue the message was dead-lettered from
+    pub queue: String,
+    /// e.g. `expired` or `rejected`
+    pub reason: String,
+}
+
+/// What the bus should do with the delivery once handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Acknowledgement {
+    Ack,
+    /// Requeued messages are delivered again, others are dropped
+    Nack {
+        requeue: bool,
+    },
+}
+
+/// Consumer of the messages delivered to the bus queue
+#[async_trait]
+pub trait MessageHandler: Send + Sync {
+    async fn handle(&self, delivery: Delivery) -> Acknowledgement;
+}
+
+/// Publish/subscribe access to an exchange and the queue of this service bound to it
+#[async_trait]
+pub trait MessageBus: Send + Sync {
+    /// Name of the service publishing the messages, used in the envelope
+    fn sender(&self) -> &str;
+
+    /// Publish the already wrapped message, keeps its id when retried.
+    /// Fails with `PublishError::Unroutable` if no queue is bound to the routing key.
+    async fn publish_envelope(
+        &self,
+        envelope: &Envelope,
+        routing_key: &str,
+    ) -> Result<(), BusError>;
+
+    /// Start delivering messages from the queue to the handler
+    async fn subscribe(&self, handler: Arc<dyn MessageHandler>) -> Result<(), BusError>;
+
+    /// Start receiving messages published with the topic
+    async fn bind_topic(&self, topic: &str) -> Result<(), BusError>;
+
+    /// Stop receiving messages published with the topic
+    async fn unbind_topic(&self, topic: &str) -> Result<(), BusError>;
+
+    /// Current state of the connection to the broker
+    fn connection_state(&self) -> ConnectionState {
+        ConnectionState::Connected
+    }
+
+    /// Wrap the message in an envelope and publish it
+    async fn publish(&self, message: &Message, routing_key: &str) -> Result<(), BusError> {
+        let envelope = Envelope::new(message.clone(), self.sender());
+        self.publish_envelope(&envelope, routing_key).await
+    }
+}
@@ -0,0 +1,266 @@
+use std::{borrow::Cow, fmt};
+
+use amqprs::error::Error as AmqpError;
+
+use crate::BrokerConfig;
+
+/// Failure to set up or close the connection to the broker
+#[derive(Debug)]
+pub enum QueueError {
+    /// Invalid or incomplete configuration
+    Config(String),
+    /// Broker refused the connection or one of the declarations
+    Amqp(AmqpError),
+}
+
+impl fmt::Display for QueueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueueError::Config(reason) => write!(f, "Invalid queue configuration: {}", reason),
+            QueueError::Amqp(e) => write!(f, "Broker error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for QueueError {}
+
+impl From<AmqpError> for QueueError {
+    fn from(e: AmqpError) -> Self {
+        QueueError::Amqp(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExchangeType {
+    Direct,
+    Topic,
+    Fanout,
+}
+
+impl ExchangeType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExchangeType::Direct => "direct",
+            ExchangeType::Topic => "topic",
+            ExchangeType::Fanout => "fanout",
+        }
+    }
+}
+
+/// Exchange, queue and consumer settings of a `QueueHandler`.
+/// Start from one of the `CONFIG_QUEUE_*` constants and adjust it with the `with_*` methods.
+#[derive(Debug, Clone)]
+pub struct QueueConfig {
+    pub(crate) exchange_name: Cow<'static, str>,
+    pub(crate) exchange_type: ExchangeType,
+    // Declared by another service, setup fails if it doesn't exist
+    pub(crate) existing_exchange: bool,
+    // Publish-only handlers don't declare a queue
+    pub(crate) queue_name: Option<Cow<'static, str>>,
+    pub(crate) routing_key: Option<Cow<'static, str>>,
+    // Expired and rejected messages from the queue are routed there
+    pub(crate) dead_letter_exchange: Option<Cow<'static, str>>,
+    // Bound on setup in addition to the routing key, used only with topic exchanges
+    pub(crate) topics: Vec<String>,
+    // Unacknowledged messages delivered to the consumer at once, 0 means unlimited
+    pub(crate) prefetch_count: u16,
+    // Generated by the broker when not set
+    pub(crate) consumer_tag: Option<String>,
+    pub(crate) broker: Option<BrokerConfig>,
+}
+
+impl QueueConfig {
+    pub const fn new(exchange_name: &'static str, exchange_type: ExchangeType) -> Self {
+        Self {
+            exchange_name: Cow::Borrowed(exchange_name),
+            exchange_type,
+            existing_exchange: false,
+            queue_name: None,
+            routing_key: None,
+            dead_letter_exchange: None,
+            topics: Vec::new(),
+            prefetch_count: 0,
+            consumer_tag: None,
+            broker: None,
+        }
+    }
+
+    /// Only check that the exchange exists instead of declaring it, so setup fails
+    /// when the service owning the exchange doesn't run in the same namespace
+    pub fn with_existing_exchange(mut self) -> Self {
+        self.existing_exchange = true;
+        self
+    }
+
+    /// Queue declared on setup and bound to the exchange with the routing key
+    pub fn with_queue(
+        mut self,
+        queue_name: impl Into<Cow<'static, str>>,
+        routing_key: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        self.queue_name = Some(queue_name.into());
+        self.routing_key = Some(routing_key.into());
+        self
+    }
+
+    /// Topics the queue is bound to on setup, used only with topic exchanges
+    pub fn with_topics(mut self, topics: Vec<String>) -> Self {
+        self.topics = topics;
+        self
+    }
+
+    /// Route expired and rejected messages of the queue to the exchange
+    pub fn with_dead_letter_exchange(
+        mut self,
+        dead_letter_exchange: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        self.dead_letter_exchange = Some(dead_letter_exchange.into());
+        self
+    }
+
+    /// Limit the unacknowledged messages delivered to the consumer
+    pub fn with_prefetch_count(mut self, prefetch_count: u16) -> Self {
+        self.prefetch_count = prefetch_count;
+        self
+    }
+
+    pub fn with_consumer_tag(mut self, consumer_tag: impl Into<String>) -> Self {
+        self.consumer_tag = Some(consumer_tag.into());
+        self
+    }
+
+    /// Broker to connect to, required by `QueueHandler::setup`
+    pub fn with_broker(mut self, broker: BrokerConfig) -> Self {
+        self.broker = Some(broker);
+        self
+    }
+
+    pub fn exchange_name(&self) -> &str {
+        &self.exchange_name
+    }
+
+    pub fn queue_name(&self) -> Option<&str> {
+        self.queue_name.as_deref()
+    }
+
+    pub fn routing_key(&self) -> Option<&str> {
+        self.routing_key.as_deref()
+    }
+
+    /// Prefix the exchange, queue and routing key names
+    pub(crate) fn namespaced(mut self, prefix: &str) -> Result<Self, QueueError> {
+        // Namespaces can't contain dots, an exchange name with one was prefixed for a namespace already
+        let exchanges = [
+            Some(&self.exchange_name),
+            self.dead_letter_exchange.as_ref(),
+        ];
+        if let Some(exchange) = exchanges
+            .into_iter()
+            .flatten()
+            .find(|name| name.contains('.'))
+        {
+            return Err(QueueError::Config(format!(
+                "Exchange {} is namespaced, but RABBITMQ_NAMESPACE is {}, use unprefixed names",
+                exchange,
+                prefix.strip_suffix('.').unwrap_or("not set")
+            )));
+        }
+
+        if prefix.is_empty() {
+            return Ok(self);
+        }
+
+        let prefixed = |name: &str| Cow::Owned(format!("{}{}", prefix, name));
+        self.exchange_name = Cow::Owned(format!("{}{}", prefix, self.exchange_name));
+        self.queue_name = self.queue_name.as_deref().map(prefixed);
+        self.routing_key = self.routing_key.as_deref().map(prefixed);
+        self.dead_letter_exchange = self.dead_letter_exchange.as_deref().map(prefixed);
+        Ok(self)
+    }
+
+    pub(crate) fn validate(&self) -> Result<&BrokerConfig, QueueError> {
+        if self.queue_name.as_deref().is_some_and(str::is_empty) {
+            return Err(QueueError::Config("Queue name cannot be empty".to_string()));
+        }
+        if self.routing_key.as_deref().is_some_and(str::is_empty) {
+            return Err(QueueError::Config(
+                "Routing key cannot be empty".to_string(),
+            ));
+        }
+        if !self.topics.is_empty() && self.exchange_type != ExchangeType::Topic {
+            return Err(QueueError::Config(format!(
+                "Topics require a topic exchange, {} is {}",
+                self.exchange_name,
+                self.exchange_type.as_str()
+            )));
+        }
+
+        self.broker
+            .as_ref()
+            .ok_or_else(|| QueueError::Config("Broker not configured".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CONFIG_QUEUE_JOB, CONFIG_QUEUE_RESULT};
+
+    #[test]
+    fn requires_broker() {
+        assert!(CONFIG_QUEUE_RESULT.validate().is_err());
+        assert!(CONFIG_QUEUE_RESULT
+            .with_broker(BrokerConfig::default())
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn prefixes_names_with_namespace() {
+        let config = CONFIG_QUEUE_JOB
+            .with_queue("worker-1", "worker-1")
+            .namespaced("staging.")
+            .unwrap();
+
+        assert_eq!(config.exchange_name(), "staging.job_exchange");
+        assert_eq!(config.queue_name(), Some("staging.worker-1"));
+        assert_eq!(config.routing_key(), Some("staging.worker-1"));
+        assert_eq!(
+            config.dead_letter_exchange.as_deref(),
+            Some("staging.job_dead_letter_exchange")
+        );
+    }
+
+    #[test]
+    fn rejects_namespaced_exchange_names() {
+        let stale =
+            CONFIG_QUEUE_RESULT.with_dead_letter_exchange("production.dead_letter_exchange");
+        assert!(stale.clone().namespaced("staging.").is_err());
+        assert!(stale.namespaced("").is_err());
+
+        // Routing keys are free to contain dots
+        assert!(CONFIG_QUEUE_JOB
+            .with_queue("worker.eu", "worker.eu")
+            .namespaced("staging.")
+            .is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_bindings() {
+        let worker = CONFIG_QUEUE_JOB
+            .with_queue("worker-1", "worker-1")
+            .with_topics(vec!["all".to_string()])
+            .with_broker(BrokerConfig::default());
+        assert!(worker.validate().is_ok());
+        assert!(worker
+            .clone()
+            .with_queue("", "worker-1")
+            .validate()
+            .is_err());
+
+        let topics_on_direct_exchange = CONFIG_QUEUE_RESULT
+            .with_topics(vec!["all".to_string()])
+            .with_broker(BrokerConfig::default());
+        assert!(topics_on_direct_exchange.validate().is_err());
+    }
+}
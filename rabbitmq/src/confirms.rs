@@ -0,0 +1,311 @@
+use std::{collections::BTreeMap, fmt, sync::Arc, time::Duration};
+
+use amqprs::{
+    callbacks::ChannelCallback, channel::Channel, error::Error as AmqpError, Ack, BasicProperties,
+    Cancel, CloseChannel, Nack, Return,
+};
+use async_trait::async_trait;
+use tokio::{
+    sync::{oneshot, Mutex},
+    time::timeout,
+};
+use tracing::{error, warn};
+
+/// Reason the broker didn't accept a published message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublishError {
+    /// No queue is bound to the routing key, the message was returned
+    Unroutable {
+        routing_key: String,
+        reply_text: String,
+    },
+    /// Broker failed to handle the message
+    Nacked,
+    /// Broker didn't confirm the message in time
+    Timeout,
+    /// Connection was lost before the message was confirmed
+    ConnectionLost,
+}
+
+impl fmt::Display for PublishError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PublishError::Unroutable {
+                routing_key,
+                reply_text,
+            } => write!(
+                f,
+                "No queue bound to routing key {} ({})",
+                routing_key, reply_text
+            ),
+            PublishError::Nacked => write!(f, "Message rejected by the broker"),
+            PublishError::Timeout => write!(f, "Message not confirmed by the broker in time"),
+            PublishError::ConnectionLost => {
+                write!(f, "Connection lost before the message was confirmed")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PublishError {}
+
+pub(crate) type ConfirmReceiver = oneshot::Receiver<Result<(), PublishError>>;
+
+struct PendingPublish {
+    message_id: String,
+    routing_key: String,
+    returned: Option<String>,
+    sender: oneshot::Sender<Result<(), PublishError>>,
+}
+
+/// Messages published on the channel waiting for the broker confirmation, keyed by delivery tag
+#[derive(Default)]
+pub(crate) struct Confirms {
+    last_tag: u64,
+    pending: BTreeMap<u64, PendingPublish>,
+}
+
+impl Confirms {
+    /// Track the next published message, must be called in the same order as the messages are published
+    pub(crate) fn register(
+        &mut self,
+        message_id: String,
+        routing_key: &str,
+    ) -> (u64, ConfirmReceiver) {
+        let (sender, receiver) = oneshot::channel();
+
+        // Delivery tags start at 1 and increase with every message published on the channel
+        self.last_tag += 1;
+        self.pending.insert(
+            self.last_tag,
+            PendingPublish {
+                message_id,
+                routing_key: routing_key.to_string(),
+                returned: None,
+                sender,
+            },
+        );
+
+        (self.last_tag, receiver)
+    }
+
+    /// Stop tracking the message, e.g. when the publish itself failed
+    pub(crate) fn discard(&mut self, delivery_tag: u64) {
+        self.pending.remove(&delivery_tag);
+    }
+
+    fn settle(&mut self, delivery_tag: u64, multiple: bool, acked: bool) {
+        let tags: Vec<u64> = if multiple {
+            self.pending
+                .range(..=delivery_tag)
+                .map(|(tag, _)| *tag)
+                .collect()
+        } else {
+            vec![delivery_tag]
+        };
+
+        for tag in tags {
+            let Some(pending) = self.pending.remove(&tag) else {
+                continue;
+            };
+
+            let result = match (acked, pending.returned) {
+                (false, _) => Err(PublishError::Nacked),
+                (true, Some(reply_text)) => Err(PublishError::Unroutable {
+                    routing_key: pending.routing_key,
+                    reply_text,
+                }),
+                (true, None) => Ok(()),
+            };
+
+            // Publisher may have given up waiting already
+            pending.sender.send(result).ok();
+        }
+    }
+
+    /// Broker sends the return before the ack of the same message
+    fn mark_returned(&mut self, message_id: &str, reply_text: String) {
+        match self
+            .pending
+            .values_mut()
+            .find(|pending| pending.message_id == message_id)
+        {
+            Some(pending) => pending.returned = Some(reply_text),
+            None => warn!("Returned message {} is not tracked", message_id),
+        }
+    }
+}
+
+/// Wait for the broker to confirm the registered message, it's no longer tracked after `wait`
+pub(crate) async fn wait_for_confirm(
+    confirms: &Mutex<Confirms>,
+    delivery_tag: u64,
+    confirmation: ConfirmReceiver,
+    wait: Duration,
+) -> Result<(), PublishError> {
+    match timeout(wait, confirmation).await {
+        Ok(Ok(result)) => result,
+        // Pending publishes are dropped with the session
+        Ok(Err(_)) => Err(PublishError::ConnectionLost),
+        Err(_) => {
+            confirms.lock().await.discard(delivery_tag);
+            Err(PublishError::Timeout)
+        }
+    }
+}
+
+/// Channel callback resolving the pending publishes
+pub(crate) struct ConfirmsCallback {
+    pub(crate) confirms: Arc<Mutex<Confirms>>,
+}
+
+#[async_trait]
+impl ChannelCallback for ConfirmsCallback {
+    async fn close(&mut self, channel: &Channel, close: CloseChannel) -> Result<(), AmqpError> {
+        error!("Channel {} closed by the broker: {}", channel, close);
+        Ok(())
+    }
+
+    async fn cancel(&mut self, channel: &Channel, cancel: Cancel) -> Result<(), AmqpError> {
+        warn!(
+            "Consumer {} cancelled by the broker on channel {}",
+            cancel.consumer_tag(),
+            channel
+        );
+        Ok(())
+    }
+
+    async fn flow(&mut self, _channel: &Channel, active: bool) -> Result<bool, AmqpError> {
+        Ok(active)
+    }
+
+    async fn publish_ack(&mut self, _channel: &Channel, ack: Ack) {
+        self.confirms
+            .lock()
+            .await
+            .settle(ack.delivery_tag(), ack.mutiple(), true);
+    }
+
+    async fn publish_nack(&mut self, _channel: &Channel, nack: Nack) {
+        self.confirms
+            .lock()
+            .await
+            .settle(nack.delivery_tag(), nack.multiple(), false);
+    }
+
+    async fn publish_return(
+        &mut self,
+        _channel: &Channel,
+        ret: Return,
+        basic_properties: BasicProperties,
+        _content: Vec<u8>,
+    ) {
+        match basic_properties.message_id() {
+            Some(message_id) => self
+                .confirms
+                .lock()
+                .await
+                .mark_returned(message_id, ret.reply_text().clone()),
+            None => warn!("Returned message without message id: {}", ret),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAIT: Duration = Duration::from_secs(1);
+
+    async fn confirm(
+        confirms: &Mutex<Confirms>,
+        (delivery_tag, confirmation): (u64, ConfirmReceiver),
+    ) -> Result<(), PublishError> {
+        wait_for_confirm(confirms, delivery_tag, confirmation, WAIT).await
+    }
+
+    #[tokio::test]
+    async fn acks_confirm_messages() {
+        let confirms = Mutex::new(Confirms::default());
+        let first = confirms.lock().await.register("m1".to_string(), "all");
+        let second = confirms.lock().await.register("m2".to_string(), "all");
+        let third = confirms.lock().await.register("m3".to_string(), "all");
+        assert_eq!((first.0, second.0, third.0), (1, 2, 3));
+
+        // Multiple acks settle every message up to the tag
+        confirms.lock().await.settle(2, true, true);
+        confirms.lock().await.settle(3, false, true);
+
+        assert_eq!(confirm(&confirms, first).await, Ok(()));
+        assert_eq!(confirm(&confirms, second).await, Ok(()));
+        assert_eq!(confirm(&confirms, third).await, Ok(()));
+        assert!(confirms.lock().await.pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn nacks_fail_only_their_messages() {
+        let confirms = Mutex::new(Confirms::default());
+        let first = confirms.lock().await.register("m1".to_string(), "all");
+        let second = confirms.lock().await.register("m2".to_string(), "all");
+
+        confirms.lock().await.settle(2, false, false);
+        assert_eq!(confirm(&confirms, second).await, Err(PublishError::Nacked));
+
+        confirms.lock().await.settle(1, false, true);
+        assert_eq!(confirm(&confirms, first).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn returned_messages_are_unroutable() {
+        let confirms = Mutex::new(Confirms::default());
+        let returned = confirms.lock().await.register("m1".to_string(), "europe");
+        let delivered = confirms.lock().await.register("m2".to_string(), "all");
+
+        // The return arrives before the ack of the same message
+        confirms
+            .lock()
+            .await
+            .mark_returned("m1", "NO_ROUTE".to_string());
+        confirms.lock().await.settle(2, true, true);
+
+        assert_eq!(
+            confirm(&confirms, returned).await,
+            Err(PublishError::Unroutable {
+                routing_key: "europe".to_string(),
+                reply_text: "NO_ROUTE".to_string(),
+            })
+        );
+        assert_eq!(confirm(&confirms, delivered).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn unconfirmed_messages_time_out() {
+        let confirms = Mutex::new(Confirms::default());
+        let (delivery_tag, confirmation) = confirms.lock().await.register("m1".to_string(), "all");
+
+        let result = wait_for_confirm(
+            &confirms,
+            delivery_tag,
+            confirmation,
+            Duration::from_millis(10),
+        )
+        .await;
+        assert_eq!(result, Err(PublishError::Timeout));
+        assert!(confirms.lock().await.pending.is_empty());
+
+        // A late ack of the discarded message is ignored
+        confirms.lock().await.settle(delivery_tag, false, true);
+    }
+
+    #[tokio::test]
+    async fn dropped_session_loses_messages() {
+        let confirms = Mutex::new(Confirms::default());
+        let pending = confirms.lock().await.register("m1".to_string(), "all");
+
+        *confirms.lock().await = Confirms::default();
+        assert_eq!(
+            confirm(&confirms, pending).await,
+            Err(PublishError::ConnectionLost)
+        );
+    }
+}
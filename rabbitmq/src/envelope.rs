@@ -0,0 +1,468 @@
+//! Versioned wire format of the messages.
+//!
+//! Compatibility rules:
+//! - Version 1 is the bare `Message` JSON sent before the envelope existed.
+//! - Version 2 wraps the message in an `Envelope` with id, sender and timestamp.
+//! - Unknown fields are ignored, new fields must be optional (`#[serde(default)]`).
+//! - Messages of older versions are up-converted on decode, newer versions are decoded
+//!   on best effort, relying on the two rules above.
+//! - Publishers can down-convert to an older version (`RABBITMQ_MESSAGE_VERSION`)
+//!   until every consumer in the fleet understands the current one.
+//!
+//! The envelope is encoded as JSON or CBOR, signalled by the AMQP `content_type` property.
+//! Consumers read both, messages without a content type are JSON. Version 1 is JSON only.
+
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::{Delivery, Message};
+
+/// Version written by this build
+pub const MESSAGE_VERSION: u32 = 2;
+/// Oldest version this build can read and write
+pub const MIN_MESSAGE_VERSION: u32 = 1;
+
+/// Wire encoding of the envelope
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    Json,
+    Cbor,
+}
+
+impl Encoding {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Encoding::Json => "application/json",
+            Encoding::Cbor => "application/cbor",
+        }
+    }
+
+    /// Messages published before the content type was set are JSON.
+    /// Parameters of the media type, e.g. `charset`, are ignored.
+    pub fn from_content_type(content_type: Option<&str>) -> Result<Self, EnvelopeError> {
+        let Some(content_type) = content_type else {
+            return Ok(Encoding::Json);
+        };
+
+        let media_type = content_type.split(';').next().unwrap_or_default().trim();
+        if media_type.eq_ignore_ascii_case("application/json") {
+            Ok(Encoding::Json)
+        } else if media_type.eq_ignore_ascii_case("application/cbor") {
+            Ok(Encoding::Cbor)
+        } else {
+            Err(EnvelopeError::UnsupportedEncoding(content_type.to_string()))
+        }
+    }
+}
+
+impl std::str::FromStr for Encoding {
+    type Err = EnvelopeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Encoding::Json),
+            "cbor" => Ok(Encoding::Cbor),
+            other => Err(EnvelopeError::UnsupportedEncoding(other.to_string())),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Envelope {
+    pub version: u32,
+    pub message_id: Uuid,
+    #[serde(default)]
+    pub sender: String,
+    pub timestamp: DateTime<Utc>,
+    pub message: Message,
+}
+
+#[derive(Debug)]
+pub enum EnvelopeError {
+    Json(serde_json::Error),
+    Cbor(String),
+    UnsupportedVersion(u32),
+    UnsupportedEncoding(String),
+    /// Message can't be expressed in the requested version
+    NotRepresentable {
+        version: u32,
+        reason: String,
+    },
+}
+
+impl fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvelopeError::Json(e) => write!(f, "Invalid message: {}", e),
+            EnvelopeError::Cbor(e) => write!(f, "Invalid CBOR message: {}", e),
+            EnvelopeError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported message version {}", version)
+            }
+            EnvelopeError::UnsupportedEncoding(encoding) => {
+                write!(f, "Unsupported message encoding {}", encoding)
+            }
+            EnvelopeError::NotRepresentable { version, reason } => {
+                write!(
+                    f,
+                    "Message not representable in version {}: {}",
+                    version, reason
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for EnvelopeError {}
+
+impl From<serde_json::Error> for EnvelopeError {
+    fn from(e: serde_json::Error) -> Self {
+        EnvelopeError::Json(e)
+    }
+}
+
+impl Envelope {
+    pub fn new(message: Message, sender: &str) -> Self {
+        Self {
+            version: MESSAGE_VERSION,
+            message_id: Uuid::new_v4(),
+            sender: sender.to_string(),
+            timestamp: Utc::now(),
+            message,
+        }
+    }
+
+    /// Decode the delivered message in the encoding signalled by its content type
+    pub fn from_delivery(delivery: &Delivery) -> Result<Self, EnvelopeError> {
+        let encoding = Encoding::from_content_type(delivery.content_type.as_deref())?;
+        Self::decode(&delivery.body, encoding)
+    }
+
+    /// Decode a message of any known version, older versions are up-converted
+    pub fn decode(content: &[u8], encoding: Encoding) -> Result<Self, EnvelopeError> {
+        let mut value: Value = match encoding {
+            Encoding::Json => serde_json::from_slice(content)?,
+            // CBOR was introduced with version 2, there is nothing to up-convert
+            Encoding::Cbor => {
+                let envelope: Envelope = ciborium::from_reader(content)
+                    .map_err(|e| EnvelopeError::Cbor(e.to_string()))?;
+                return envelope.checked();
+            }
+        };
+
+        if value.get("version").is_some() && value.get("message").is_some() {
+            let envelope: Envelope = serde_json::from_value(value)?;
+            return envelope.checked();
+        }
+
+        // Version 1 has no envelope, the message id and timestamp are assigned on receive
+        upgrade_v1(&mut value);
+        Ok(Self {
+            version: 1,
+            message_id: Uuid::new_v4(),
+            sender: String::new(),
+            timestamp: Utc::now(),
+            message: serde_json::from_value(value)?,
+        })
+    }
+
+    fn checked(mut self) -> Result<Self, EnvelopeError> {
+        if self.version < 2 {
+            return Err(EnvelopeError::UnsupportedVersion(self.version));
+        }
+        // Decoded into the current schema, newer fields were dropped
+        self.version = self.version.min(MESSAGE_VERSION);
+        Ok(self)
+    }
+
+    /// Encode the message in the given version, down-converting if needed
+    pub fn encode(&self, version: u32, encoding: Encoding) -> Result<Vec<u8>, EnvelopeError> {
+        match (version, encoding) {
+            (1, Encoding::Json) => {
+                let mut value = serde_json::to_value(&self.message)?;
+                downgrade_v1(&mut value)?;
+                Ok(serde_json::to_vec(&value)?)
+            }
+            (1, Encoding::Cbor) => Err(EnvelopeError::NotRepresentable {
+                version: 1,
+                reason: "version 1 is JSON only".to_string(),
+            }),
+            (2..=MESSAGE_VERSION, Encoding::Json) => {
+                let mut value = serde_json::to_value(self)?;
+                value["version"] = json!(version);
+                Ok(serde_json::to_vec(&value)?)
+            }
+            (2..=MESSAGE_VERSION, Encoding::Cbor) => {
+                let mut content = Vec::new();
+                ciborium::into_writer(
+                    &Envelope {
+                        version,
+                        ..self.clone()
+                    },
+                    &mut content,
+                )
+                .map_err(|e| EnvelopeError::Cbor(e.to_string()))?;
+                Ok(content)
+            }
+            _ => Err(EnvelopeError::UnsupportedVersion(version)),
+        }
+    }
+}
+
+/// Version 1 heartbeat was a unit variant without host metrics
+fn upgrade_v1(value: &mut Value) {
+    if let Some(status) = value.pointer_mut("/WorkerStatus/status/status") {
+        if status == "Heartbeat" {
+            *status = json!({ "Heartbeat": null });
+        }
+    }
+}
+
+fn downgrade_v1(value: &mut Value) -> Result<(), EnvelopeError> {
+    if value.get("WorkerControl").is_some() {
+        return Err(EnvelopeError::NotRepresentable {
+            version: 1,
+            reason: "control messages were added in version 2".to_string(),
+        });
+    }
+
+    if value
+        .pointer("/WorkerStatus/status/status/Progress")
+        .is_some()
+    {
+        return Err(EnvelopeError::NotRepresentable {
+            version: 1,
+            reason: "progress messages were added in version 2".to_string(),
+        });
+    }
+
+    if let Some(status) = value.pointer_mut("/WorkerStatus/status/status") {
+        if status.get("Heartbeat").is_some() {
+            *status = json!("Heartbeat");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Messages as sent on the wire by version 1, must stay decodable
+    const V1_JOB: &str = r#"{"WorkerJob":{"job_id":"6a0c2a8e-0f3d-4c64-9d6b-3f7e2b1c9a01","payload":{"job_id":"6a0c2a8e-0f3d-4c64-9d6b-3f7e2b1c9a01","sub_job_id":"0b8d6f55-3a8e-4d1c-b7a2-8f6e4d2c1b02","url":"http://example.com/file","start_time":"2024-10-01T12:00:00Z","download_start_time":"2024-10-01T12:00:10Z","start_range":0,"end_range":104857600}}}"#;
+    const V1_RESULT: &str = r#"{"WorkerResult":{"job_id":"6a0c2a8e-0f3d-4c64-9d6b-3f7e2b1c9a01","result":{"run_id":"f1e2d3c4-b5a6-4978-8a9b-0c1d2e3f4a03","job_id":"6a0c2a8e-0f3d-4c64-9d6b-3f7e2b1c9a01","sub_job_id":"0b8d6f55-3a8e-4d1c-b7a2-8f6e4d2c1b02","worker_name":"worker-1","is_success":true,"download_result":{"Ok":{"total_bytes":1048576,"elapsed_secs":1.5,"download_speed":5.33,"job_start_time":"2024-10-01T12:00:00Z","download_start_time":"2024-10-01T12:00:10Z","end_time":"2024-10-01T12:00:11.500Z","time_to_first_byte_ms":20.0,"second_by_second_logs":[["2024-10-01T12:00:11Z",524288,524288]]}},"ping_result":{"Ok":{"min":1.0,"max":3.0,"avg":2.0}},"head_result":{"Err":{"error":"RequestFailed"}}}}}"#;
+    const V1_LIFECYCLE: &str = r#"{"WorkerStatus":{"status":{"worker_name":"worker-1","status":{"Lifecycle":{"worker_topics":["all","europe"],"worker_status":"Online"}},"timestamp":"2024-10-01T12:00:00Z"}}}"#;
+    const V1_JOB_STATUS: &str = r#"{"WorkerStatus":{"status":{"worker_name":"worker-1","status":{"Job":{"run_id":"f1e2d3c4-b5a6-4978-8a9b-0c1d2e3f4a03","job_id":"6a0c2a8e-0f3d-4c64-9d6b-3f7e2b1c9a01","sub_job_id":"0b8d6f55-3a8e-4d1c-b7a2-8f6e4d2c1b02","worker_name":"worker-1"}},"timestamp":"2024-10-01T12:00:00Z"}}}"#;
+    const V1_HEARTBEAT: &str = r#"{"WorkerStatus":{"status":{"worker_name":"worker-1","status":"Heartbeat","timestamp":"2024-10-01T12:00:00Z"}}}"#;
+
+    // Messages as sent on the wire by version 2
+    const V2_CONTROL: &str = r#"{"version":2,"message_id":"9c8b7a6d-5e4f-4a3b-9c2d-1e0f9a8b7c04","sender":"scheduler","timestamp":"2024-10-01T12:00:00Z","message":{"WorkerControl":{"worker_name":"worker-1","command":{"SetTopics":["all","europe"]}}}}"#;
+    const V2_HEARTBEAT: &str = r#"{"version":2,"message_id":"9c8b7a6d-5e4f-4a3b-9c2d-1e0f9a8b7c05","sender":"worker-1","timestamp":"2024-10-01T12:00:00Z","message":{"WorkerStatus":{"status":{"worker_name":"worker-1","status":{"Heartbeat":{"sampled_at":"2024-10-01T12:00:00Z","window_secs":5.0,"cpu_usage_percent":12.5,"memory_total_bytes":8589934592,"memory_available_bytes":4294967296,"network":{"interface":"eth0","rx_bytes_per_sec":1000.0,"tx_bytes_per_sec":500.0,"link_speed_mbps":1000}}},"timestamp":"2024-10-01T12:00:00Z"}}}}"#;
+    const V2_RESULT: &str = r#"{"version":2,"message_id":"9c8b7a6d-5e4f-4a3b-9c2d-1e0f9a8b7c06","sender":"worker-1","timestamp":"2024-10-01T12:00:00Z","message":{"WorkerResult":{"job_id":"6a0c2a8e-0f3d-4c64-9d6b-3f7e2b1c9a01","result":{"run_id":"f1e2d3c4-b5a6-4978-8a9b-0c1d2e3f4a03","job_id":"6a0c2a8e-0f3d-4c64-9d6b-3f7e2b1c9a01","sub_job_id":"0b8d6f55-3a8e-4d1c-b7a2-8f6e4d2c1b02","worker_name":"worker-1","is_success":false,"download_result":{"Err":{"error":"Timeout"}},"ping_result":{"Err":{"error":"Timeout"}},"head_result":{"Err":{"error":"Timeout"}},"host_metrics":null}}}}"#;
+    const V2_PROGRESS: &str = r#"{"version":2,"message_id":"9c8b7a6d-5e4f-4a3b-9c2d-1e0f9a8b7c07","sender":"worker-1","timestamp":"2024-10-01T12:00:12Z","message":{"WorkerStatus":{"status":{"worker_name":"worker-1","status":{"Progress":{"run_id":"f1e2d3c4-b5a6-4978-8a9b-0c1d2e3f4a03","job_id":"6a0c2a8e-0f3d-4c64-9d6b-3f7e2b1c9a01","sub_job_id":"0b8d6f55-3a8e-4d1c-b7a2-8f6e4d2c1b02","timestamp":"2024-10-01T12:00:12Z","interval_bytes":524288,"total_bytes":1048576,"elapsed_secs":2.0,"download_speed":4.0}},"timestamp":"2024-10-01T12:00:12Z"}}}}"#;
+
+    fn value(content: &[u8]) -> Value {
+        serde_json::from_slice(content).unwrap()
+    }
+
+    #[test]
+    fn v1_messages_round_trip() {
+        for fixture in [V1_JOB, V1_RESULT, V1_LIFECYCLE, V1_JOB_STATUS, V1_HEARTBEAT] {
+            let envelope = Envelope::decode(fixture.as_bytes(), Encoding::Json).unwrap();
+            assert_eq!(envelope.version, 1);
+
+            let encoded = envelope.encode(1, Encoding::Json).unwrap();
+            let mut expected = value(fixture.as_bytes());
+            // Fields added in version 2 are sent to version 1 consumers, which ignore them
+            if let Some(result) = expected.pointer_mut("/WorkerResult/result") {
+                result["host_metrics"] = Value::Null;
+            }
+            if let Some(details) = expected.pointer_mut("/WorkerStatus/status/status/Lifecycle") {
+                details["calibration"] = Value::Null;
+            }
+            assert_eq!(value(&encoded), expected, "fixture: {}", fixture);
+        }
+    }
+
+    #[test]
+    fn v2_messages_round_trip() {
+        for fixture in [V2_CONTROL, V2_HEARTBEAT, V2_RESULT, V2_PROGRESS] {
+            let envelope = Envelope::decode(fixture.as_bytes(), Encoding::Json).unwrap();
+            assert_eq!(envelope.version, 2);
+
+            let encoded = envelope.encode(2, Encoding::Json).unwrap();
+            assert_eq!(
+                value(&encoded),
+                value(fixture.as_bytes()),
+                "fixture: {}",
+                fixture
+            );
+        }
+    }
+
+    #[test]
+    fn decodes_messages_without_optional_metrics() {
+        let heartbeat = V2_HEARTBEAT.replace(
+            r#","network":{"interface":"eth0","rx_bytes_per_sec":1000.0,"tx_bytes_per_sec":500.0,"link_speed_mbps":1000}"#,
+            "",
+        );
+        let result = V2_RESULT.replace(r#","host_metrics":null"#, "");
+        assert_ne!(heartbeat, V2_HEARTBEAT);
+        assert_ne!(result, V2_RESULT);
+
+        for fixture in [heartbeat, result] {
+            assert!(Envelope::decode(fixture.as_bytes(), Encoding::Json).is_ok());
+        }
+    }
+
+    #[test]
+    fn v1_heartbeat_is_upgraded() {
+        let envelope = Envelope::decode(V1_HEARTBEAT.as_bytes(), Encoding::Json).unwrap();
+        let encoded = value(&envelope.encode(2, Encoding::Json).unwrap());
+
+        assert_eq!(
+            encoded.pointer("/message/WorkerStatus/status/status"),
+            Some(&json!({ "Heartbeat": null }))
+        );
+        assert_eq!(encoded["version"], json!(2));
+    }
+
+    #[test]
+    fn v2_heartbeat_is_downgraded() {
+        let envelope = Envelope::decode(V2_HEARTBEAT.as_bytes(), Encoding::Json).unwrap();
+        let encoded = value(&envelope.encode(1, Encoding::Json).unwrap());
+
+        assert_eq!(
+            encoded.pointer("/WorkerStatus/status/status"),
+            Some(&json!("Heartbeat"))
+        );
+    }
+
+    #[test]
+    fn control_and_progress_messages_are_not_representable_in_v1() {
+        for fixture in [V2_CONTROL, V2_PROGRESS] {
+            let envelope = Envelope::decode(fixture.as_bytes(), Encoding::Json).unwrap();
+
+            assert!(matches!(
+                envelope.encode(1, Encoding::Json),
+                Err(EnvelopeError::NotRepresentable { version: 1, .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn newer_version_with_unknown_fields_is_decoded() {
+        let mut newer = value(V2_RESULT.as_bytes());
+        newer["version"] = json!(MESSAGE_VERSION + 1);
+        newer["trace_id"] = json!("abc");
+        newer["message"]["WorkerResult"]["result"]["new_metric"] = json!(42);
+
+        let envelope =
+            Envelope::decode(&serde_json::to_vec(&newer).unwrap(), Encoding::Json).unwrap();
+
+        assert_eq!(envelope.version, MESSAGE_VERSION);
+        assert_eq!(envelope.sender, "worker-1");
+        assert!(matches!(envelope.message, Message::WorkerResult { .. }));
+    }
+
+    #[test]
+    fn unsupported_versions_are_rejected() {
+        let mut envelope = value(V2_CONTROL.as_bytes());
+        envelope["version"] = json!(1);
+
+        assert!(matches!(
+            Envelope::decode(&serde_json::to_vec(&envelope).unwrap(), Encoding::Json),
+            Err(EnvelopeError::UnsupportedVersion(1))
+        ));
+
+        let envelope = Envelope::decode(V2_CONTROL.as_bytes(), Encoding::Json).unwrap();
+        assert!(matches!(
+            envelope.encode(MESSAGE_VERSION + 1, Encoding::Json),
+            Err(EnvelopeError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[test]
+    fn v2_messages_round_trip_through_cbor() {
+        for fixture in [V2_CONTROL, V2_HEARTBEAT, V2_RESULT, V2_PROGRESS] {
+            let envelope = Envelope::decode(fixture.as_bytes(), Encoding::Json).unwrap();
+            let cbor = envelope.encode(2, Encoding::Cbor).unwrap();
+
+            let decoded = Envelope::decode(&cbor, Encoding::Cbor).unwrap();
+            let encoded = decoded.encode(2, Encoding::Json).unwrap();
+            assert_eq!(
+                value(&encoded),
+                value(fixture.as_bytes()),
+                "fixture: {}",
+                fixture
+            );
+        }
+    }
+
+    #[test]
+    fn cbor_is_not_representable_in_v1() {
+        let envelope = Envelope::decode(V1_JOB.as_bytes(), Encoding::Json).unwrap();
+
+        assert!(matches!(
+            envelope.encode(1, Encoding::Cbor),
+            Err(EnvelopeError::NotRepresentable { version: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn encoding_from_content_type() {
+        assert_eq!(Encoding::from_content_type(None).unwrap(), Encoding::Json);
+        assert_eq!(
+            Encoding::from_content_type(Some("application/cbor")).unwrap(),
+            Encoding::Cbor
+        );
+        assert_eq!(
+            Encoding::from_content_type(Some("Application/JSON; charset=utf-8")).unwrap(),
+            Encoding::Json
+        );
+        assert!(Encoding::from_content_type(Some("text/plain")).is_err());
+        assert!(Encoding::from_content_type(Some("application/json-seq")).is_err());
+    }
+
+    #[test]
+    fn decodes_delivery_in_its_content_type() {
+        let envelope = Envelope::decode(V2_RESULT.as_bytes(), Encoding::Json).unwrap();
+        let delivery = |body: Vec<u8>, content_type: Option<&str>| Delivery {
+            message_id: None,
+            body,
+            content_type: content_type.map(str::to_string),
+            redelivered: false,
+            dead_letter: None,
+            signature: None,
+        };
+
+        for (body, content_type) in [
+            (V2_RESULT.as_bytes().to_vec(), None),
+            (
+                V2_RESULT.as_bytes().to_vec(),
+                Some("application/json; charset=utf-8"),
+            ),
+            (
+                envelope.encode(2, Encoding::Cbor).unwrap(),
+                Some("application/cbor"),
+            ),
+        ] {
+            let decoded = Envelope::from_delivery(&delivery(body, content_type)).unwrap();
+            assert_eq!(decoded.message_id, envelope.message_id);
+        }
+
+        assert!(matches!(
+            Envelope::from_delivery(&delivery(V2_RESULT.as_bytes().to_vec(), Some("text/plain"))),
+            Err(EnvelopeError::UnsupportedEncoding(_))
+        ));
+        // CBOR body labelled as JSON
+        let cbor = envelope.encode(2, Encoding::Cbor).unwrap();
+        assert!(Envelope::from_delivery(&delivery(cbor, None)).is_err());
+    }
+}
@@ -1,231 +1,747 @@
-use std::{collections::HashSet, env};
+use std::{borrow::Cow, future::Future, pin::Pin, sync::Arc, time::Duration};
 
 use amqprs::{
     channel::{
-        BasicConsumeArguments, BasicPublishArguments, Channel, ExchangeDeclareArguments,
-        QueueBindArguments, QueueDeclareArguments,
+        BasicAckArguments, BasicConsumeArguments, BasicNackArguments, BasicPublishArguments,
+        BasicQosArguments, Channel, ConfirmSelectArguments, ExchangeDeclareArguments,
+        QueueBindArguments, QueueDeclareArguments, QueueUnbindArguments,
     },
     connection::{Connection, OpenConnectionArguments},
     consumer::AsyncConsumer,
-    tls::TlsAdaptor,
-    BasicProperties,
+    error::Error as AmqpError,
+    BasicProperties, Deliver, FieldName, FieldTable, FieldValue,
 };
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use url::Url;
+use tokio::{
+    sync::{watch, Mutex, RwLock},
+    time::{interval, sleep},
+};
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
+mod broker;
+mod bus;
+mod config;
+mod confirms;
+mod envelope;
+mod memory;
 mod messages;
+pub mod signing;
+
+pub use broker::{AuthMechanism, BrokerConfig};
+pub use bus::{Acknowledgement, BusError, DeadLetter, Delivery, MessageBus, MessageHandler};
+pub use config::{ExchangeType, QueueConfig, QueueError};
+
+pub use confirms::PublishError;
+use confirms::{wait_for_confirm, Confirms, ConfirmsCallback};
+pub use envelope::{Encoding, Envelope, EnvelopeError, MESSAGE_VERSION, MIN_MESSAGE_VERSION};
+pub use memory::{InMemoryBroker, InMemoryBus};
+use signing::{SigningKey, SIGNATURE_HEADER};
 
 // re export messages
 pub use messages::{
-    AccumulatingBytes, DownloadError, DownloadResult, HeadError, HeadResult, IntervalBytes,
-    JobMessage, PingError, PingResult, ResultMessage, StatusMessage, WorkerDetails, WorkerStatus,
-    WorkerStatusDetails, WorkerStatusJobDetails,
+    AccumulatingBytes, CalibrationError, CalibrationResult, ControlMessage, DownloadError,
+    DownloadProgress, DownloadResult, HeadError, HeadResult, HostMetrics, IntervalBytes,
+    JobMessage, NetworkMetrics, PingError, PingResult, ResultMessage, StatusMessage, WorkerDetails,
+    WorkerStatus, WorkerStatusDetails, WorkerStatusJobDetails,
 };
 
 // Messages that can be sent or received
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
 pub enum Message {
-    WorkerJob { job_id: Uuid, payload: JobMessage },
-    WorkerResult { job_id: Uuid, result: ResultMessage },
-    WorkerStatus { status: StatusMessage },
+    WorkerJob {
+        job_id: Uuid,
+        payload: JobMessage,
+    },
+    WorkerResult {
+        job_id: Uuid,
+        result: ResultMessage,
+    },
+    WorkerStatus {
+        status: StatusMessage,
+    },
+    WorkerControl {
+        worker_name: String,
+        command: ControlMessage,
+    },
 }
 
-// Configuration for RabbitMQ exchange, queue, and routing key
+impl Message {
+    /// Time after which the message is useless and should be dead-lettered instead of delivered
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Message::WorkerJob { payload, .. } => Some(payload.start_time),
+            _ => None,
+        }
+    }
+}
+
+/// Connection to a RabbitMQ exchange and the queue of this service bound to it
 #[derive(Clone)]
 pub struct QueueHandler {
-    pub exchange_name: &'static str,
-    pub queue_name: Option<&'static str>,
-    pub routing_key: Option<&'static str>,
-    exchange_type: &'static str,
-    connection: Option<Connection>,
-    channel: Option<Channel>,
+    config: QueueConfig,
+    // Identifies the publisher in the message envelope
+    sender: String,
+    // Taken from the broker config on setup
+    message_version: u32,
+    message_encoding: Encoding,
+    namespace_prefix: String,
+    // Key signing the published messages, unsigned when not set
+    signing_key: Option<Arc<SigningKey>>,
+    // Shared by all clones of the handler, so they all use the current connection after a reconnect
+    link: Option<Arc<Link>>,
+}
+
+/// State of the connection to the broker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    Reconnecting,
+    Closed,
+}
+
+impl ConnectionState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConnectionState::Connected => "connected",
+            ConnectionState::Reconnecting => "reconnecting",
+            ConnectionState::Closed => "closed",
+        }
+    }
+}
+
+type AmqpResult<T> = Result<T, AmqpError>;
+
+type SubscribeFn = Box<
+    dyn Fn(
+            Channel,
+            BasicConsumeArguments,
+        ) -> Pin<Box<dyn Future<Output = AmqpResult<String>> + Send>>
+        + Send
+        + Sync,
+>;
+
+struct Session {
+    connection: Connection,
+    channel: Channel,
+    confirms: Arc<Mutex<Confirms>>,
 }
 
+struct Link {
+    connection_args: OpenConnectionArguments,
+    session: RwLock<Option<Session>>,
+    state: watch::Sender<ConnectionState>,
+    // Restored after reconnect, topics can change at runtime
+    topics: Mutex<Vec<String>>,
+    consumers: Mutex<Vec<SubscribeFn>>,
+}
+
+// Delay between reconnection attempts, doubled after each failed attempt
+const RECONNECT_MIN_DELAY: Duration = Duration::from_secs(1);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+// How often the connection and channel are checked for being closed
+const CONNECTION_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+// How long to wait for the broker to confirm a published message
+const PUBLISH_CONFIRM_TIMEOUT: Duration = Duration::from_secs(10);
+
 impl QueueHandler {
-    fn set_queue_name(&mut self, queue_name: &'static str) {
-        self.queue_name = Some(queue_name);
+    pub fn new(config: QueueConfig) -> Self {
+        Self {
+            config,
+            sender: String::new(),
+            message_version: MESSAGE_VERSION,
+            message_encoding: Encoding::Json,
+            namespace_prefix: String::new(),
+            signing_key: None,
+            link: None,
+        }
     }
 
-    fn set_routing_key(&mut self, routing_key: &'static str) {
-        self.routing_key = Some(routing_key);
+    /// Name of the service publishing the messages
+    pub fn with_sender(mut self, sender: impl Into<String>) -> Self {
+        self.sender = sender.into();
+        self
     }
 
-    pub async fn setup(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let endpoint = env::var("RABBITMQ_ENDPOINT").expect("RABBITMQ_ENDPOINT must be set");
-        let parsed_url = Url::parse(&endpoint).expect("Invalid URL format for RABBITMQ_ENDPOINT");
+    /// Sign the published messages with the key
+    pub fn with_signing_key(mut self, signing_key: SigningKey) -> Self {
+        self.signing_key = Some(Arc::new(signing_key));
+        self
+    }
 
-        let addr = parsed_url
-            .host_str()
-            .expect("RABBITMQ_ENDPOINT must contain a host");
+    pub fn config(&self) -> &QueueConfig {
+        &self.config
+    }
 
-        let is_ssl = match parsed_url.scheme() {
-            "amqp" => false,
-            "http" => false,
-            "amqps" => true,
-            "amqps+ssl" => true,
-            "amqps+tls" => true,
-            "https" => true,
-            _ => panic!("Invalid scheme for RABBITMQ_ENDPOINT"),
-        };
+    /// Prefix the routing key or topic with the namespace
+    fn namespaced<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        match self.namespace_prefix.is_empty() {
+            true => Cow::Borrowed(name),
+            false => Cow::Owned(format!("{}{}", self.namespace_prefix, name)),
+        }
+    }
 
-        let port = parsed_url.port().unwrap_or(5672);
+    /// Connect to the broker and declare the exchange and queue
+    pub async fn setup(&mut self) -> Result<(), QueueError> {
+        let broker = self.config.validate()?;
+        let connection_args = broker.connection_args()?;
+        self.message_version = broker.message_version;
+        self.message_encoding = broker.message_encoding;
+
+        // Names in the config stay unprefixed until setup, so the constants can be shared
+        let namespace_prefix = broker.namespace_prefix()?;
+        self.config = self.config.clone().namespaced(&namespace_prefix)?;
+        let topics = self
+            .config
+            .topics
+            .iter()
+            .map(|topic| format!("{}{}", namespace_prefix, topic))
+            .collect();
+        self.namespace_prefix = namespace_prefix;
+
+        let link = Arc::new(Link {
+            connection_args,
+            session: RwLock::new(None),
+            state: watch::Sender::new(ConnectionState::Reconnecting),
+            topics: Mutex::new(topics),
+            consumers: Mutex::new(Vec::new()),
+        });
+
+        let session = self.open_session(&link).await?;
+        *link.session.write().await = Some(session);
+        link.state.send_replace(ConnectionState::Connected);
 
-        let username = env::var("RABBITMQ_USERNAME").expect("RABBITMQ_USERNAME must be set");
-        let password = env::var("RABBITMQ_PASSWORD").expect("RABBITMQ_PASSWORD must be set");
+        self.link = Some(link.clone());
 
+        // Watch the connection and reconnect when it's lost
+        tokio::spawn(self.clone().supervise(link));
+
+        Ok(())
+    }
+
+    /// Open connection and channel, declare the exchange and queue with bindings and restore consumers
+    async fn open_session(&self, link: &Link) -> Result<Session, QueueError> {
         // Open connection
-        let connection = if is_ssl {
-            Connection::open(
-                OpenConnectionArguments::new(addr, port, &username, &password)
-                    .tls_adaptor(TlsAdaptor::without_client_auth(None, addr.to_string()).unwrap()),
-            )
-            .await?
-        } else {
-            Connection::open(&OpenConnectionArguments::new(
-                addr, port, &username, &password,
-            ))
-            .await?
-        };
+        let connection = Connection::open(&link.connection_args).await?;
 
         // Open channel
         let channel = connection.open_channel(None).await?;
 
+        // Enable publisher confirms, the callback resolves pending publishes on ack, nack and return
+        let confirms = Arc::new(Mutex::new(Confirms::default()));
+        channel
+            .register_callback(ConfirmsCallback {
+                confirms: confirms.clone(),
+            })
+            .await?;
+        channel
+            .confirm_select(ConfirmSelectArguments::default())
+            .await?;
+
         // Declare exchange
+        let exchange_name = self.config.exchange_name();
         channel
             .exchange_declare(
-                ExchangeDeclareArguments::new(self.exchange_name, self.exchange_type)
-                    .passive(false)
+                ExchangeDeclareArguments::new(exchange_name, self.config.exchange_type.as_str())
+                    .passive(self.config.existing_exchange)
                     .durable(true)
                     .finish(),
             )
-            .await?;
+            .await
+            .map_err(|e| match self.config.existing_exchange {
+                // Most likely the owner of the exchange runs in another namespace
+                true => QueueError::Config(format!(
+                    "Exchange {} not declared, check that RABBITMQ_NAMESPACE matches the scheduler: {}",
+                    exchange_name, e
+                )),
+                false => e.into(),
+            })?;
+
+        // Publish-only handlers are done
+        let (Some(queue_name), Some(routing_key)) =
+            (self.config.queue_name(), self.config.routing_key())
+        else {
+            return Ok(Session {
+                connection,
+                channel,
+                confirms,
+            });
+        };
 
-        if self.queue_name.is_none() || self.routing_key.is_none() {
-            let worker_name: &'static str = Box::leak(
-                env::var("WORKER_NAME")
-                    .unwrap_or_else(|_| "default_worker".to_string())
-                    .into_boxed_str(),
+        let mut queue_args = QueueDeclareArguments::durable_client_named(queue_name);
+        if let Some(dead_letter_exchange) = &self.config.dead_letter_exchange {
+            // Declare it here as well, messages are dropped if it doesn't exist
+            channel
+                .exchange_declare(
+                    ExchangeDeclareArguments::new(dead_letter_exchange, "fanout")
+                        .durable(true)
+                        .finish(),
+                )
+                .await?;
+
+            let mut arguments = FieldTable::new();
+            arguments.insert(
+                field_name("x-dead-letter-exchange"),
+                dead_letter_exchange.as_ref().into(),
             );
-            self.set_queue_name(worker_name);
-            self.set_routing_key(worker_name);
+            queue_args.arguments(arguments);
         }
 
-        let worker_topics: Vec<String> = env::var("WORKER_TOPICS")
-            .unwrap_or_else(|_| "all".to_string())
-            .split(',')
-            .map(|s| s.to_string())
-            .collect::<HashSet<_>>()
-            .into_iter()
-            .collect();
-
         // Declare queue
         channel
-            .queue_declare(QueueDeclareArguments::durable_client_named(
-                self.queue_name.unwrap(),
-            ))
-            .await?;
+            .queue_declare(queue_args)
+            .await
+            .map_err(|e| match &self.config.dead_letter_exchange {
+                // RabbitMQ refuses to redeclare a queue declared before without the argument
+                Some(dead_letter_exchange) => QueueError::Config(format!(
+                    "Queue {} not declared, if it exists without the {} dead-letter exchange delete it once: {}",
+                    queue_name, dead_letter_exchange, e
+                )),
+                None => e.into(),
+            })?;
 
         // Bind queue to exchange
         channel
             .queue_bind(QueueBindArguments::new(
-                self.queue_name.unwrap(),
-                self.exchange_name,
-                self.routing_key.unwrap(),
+                queue_name,
+                exchange_name,
+                routing_key,
             ))
             .await?;
 
-        if self.exchange_type == "topic" {
+        if self.config.exchange_type == ExchangeType::Topic {
             // Bind the queue to the exchange with each topic
-            for topic in worker_topics {
+            for topic in link.topics.lock().await.iter() {
                 channel
-                    .queue_bind(QueueBindArguments::new(
-                        self.queue_name.unwrap(),
-                        self.exchange_name,
-                        &topic,
-                    ))
+                    .queue_bind(QueueBindArguments::new(queue_name, exchange_name, topic))
                     .await?;
             }
         }
 
-        self.connection = Some(connection);
-        self.channel = Some(channel);
+        if self.config.prefetch_count > 0 {
+            channel
+                .basic_qos(BasicQosArguments::new(0, self.config.prefetch_count, false))
+                .await?;
+        }
+
+        for subscribe in link.consumers.lock().await.iter() {
+            subscribe(channel.clone(), self.consume_args()?).await?;
+        }
+
+        Ok(Session {
+            connection,
+            channel,
+            confirms,
+        })
+    }
+
+    /// Wait for the connection or channel to close and reconnect with backoff, until closed by `close`
+    async fn supervise(self, link: Arc<Link>) {
+        loop {
+            let connection = match link.session.read().await.as_ref() {
+                Some(session) => session.connection.clone(),
+                None => return,
+            };
+
+            let mut check_interval = interval(CONNECTION_CHECK_INTERVAL);
+            let mut state_rx = link.state.subscribe();
+            loop {
+                tokio::select! {
+                    _ = connection.listen_network_io_failure() => break,
+                    _ = check_interval.tick() => {
+                        if !self.is_session_open(&link).await {
+                            break;
+                        }
+                    }
+                    _ = state_rx.changed() => {}
+                }
+                if *link.state.borrow() == ConnectionState::Closed {
+                    return;
+                }
+            }
+
+            if *link.state.borrow() == ConnectionState::Closed {
+                return;
+            }
+
+            warn!(
+                "Connection for exchange {} lost, reconnecting...",
+                self.config.exchange_name
+            );
+            link.state.send_replace(ConnectionState::Reconnecting);
+
+            let mut delay = RECONNECT_MIN_DELAY;
+            loop {
+                sleep(delay).await;
+                if *link.state.borrow() == ConnectionState::Closed {
+                    return;
+                }
+
+                match self.open_session(&link).await {
+                    Ok(session) => {
+                        if let Some(old) = link.session.write().await.replace(session) {
+                            // Connection is most likely already gone, just release it
+                            old.connection.close().await.ok();
+                        }
+                        link.state.send_replace(ConnectionState::Connected);
+                        info!("Reconnected to exchange {}", self.config.exchange_name);
+                        break;
+                    }
+                    Err(e) => {
+                        error!(
+                            "Failed to reconnect to exchange {}: {}, retrying in {:?}",
+                            self.config.exchange_name, e, delay
+                        );
+                        delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn is_session_open(&self, link: &Link) -> bool {
+        link.session
+            .read()
+            .await
+            .as_ref()
+            .is_some_and(|session| session.connection.is_open() && session.channel.is_open())
+    }
+
+    fn consume_args(&self) -> Result<BasicConsumeArguments, QueueError> {
+        let queue_name = self
+            .config
+            .queue_name()
+            .ok_or_else(|| QueueError::Config("Queue not declared".to_string()))?;
+        // An empty tag is generated by the broker
+        let consumer_tag = self.config.consumer_tag.as_deref().unwrap_or_default();
+
+        Ok(BasicConsumeArguments::new(queue_name, consumer_tag))
+    }
+
+    /// Channel of the current connection, fails while reconnecting
+    async fn channel(&self) -> Result<Channel, BusError> {
+        let link = self.link.as_ref().ok_or("Channel not initialized")?;
+
+        match link.session.read().await.as_ref() {
+            Some(session) if session.channel.is_open() => Ok(session.channel.clone()),
+            _ => Err("Channel not connected".into()),
+        }
+    }
+
+    /// Channel of the current connection with its pending confirms
+    async fn publisher(&self) -> Result<(Channel, Arc<Mutex<Confirms>>), BusError> {
+        let link = self.link.as_ref().ok_or("Channel not initialized")?;
+
+        match link.session.read().await.as_ref() {
+            Some(session) if session.channel.is_open() => {
+                Ok((session.channel.clone(), session.confirms.clone()))
+            }
+            _ => Err("Channel not connected".into()),
+        }
+    }
+
+    /// Receiver notified on every change of the connection state
+    pub fn watch_connection_state(&self) -> Option<watch::Receiver<ConnectionState>> {
+        self.link.as_ref().map(|link| link.state.subscribe())
+    }
+
+    pub async fn close(&mut self) -> Result<(), QueueError> {
+        if let Some(link) = self.link.take() {
+            // Stop the supervisor before closing, so it doesn't reconnect
+            link.state.send_replace(ConnectionState::Closed);
+
+            if let Some(session) = link.session.write().await.take() {
+                session.channel.close().await?;
+                session.connection.close().await?;
+            }
+        }
 
         Ok(())
     }
+}
+
+#[async_trait]
+impl MessageBus for QueueHandler {
+    fn sender(&self) -> &str {
+        &self.sender
+    }
 
-    pub async fn publish(
+    async fn publish_envelope(
         &self,
-        message: &Message,
+        envelope: &Envelope,
         routing_key: &str,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let serialized_message = serde_json::to_vec(message)?;
-        let args = BasicPublishArguments::new(self.exchange_name, routing_key);
+    ) -> Result<(), BusError> {
+        let serialized_message = envelope.encode(self.message_version, self.message_encoding)?;
+        let args =
+            BasicPublishArguments::new(self.config.exchange_name(), &self.namespaced(routing_key))
+                .mandatory(true)
+                .finish();
+
+        // Returned messages are matched with the pending publish by the message id
+        let message_id = envelope.message_id.to_string();
+        let mut properties = BasicProperties::default()
+            .with_message_id(&message_id)
+            .with_content_type(self.message_encoding.content_type())
+            .finish();
+
+        if let Some(signing_key) = &self.signing_key {
+            let signature = signing::sign(signing_key, &serialized_message);
+            let mut headers = FieldTable::new();
+            headers.insert(
+                field_name(SIGNATURE_HEADER),
+                STANDARD.encode(signature).into(),
+            );
+            properties.with_headers(headers);
+        }
 
-        self.channel
-            .as_ref()
-            .ok_or("Channel not initialized")?
-            .basic_publish(BasicProperties::default(), serialized_message, args)
-            .await?;
+        // Expired messages are dead-lettered instead of being delivered late
+        if let Some(expires_at) = envelope.message.expires_at() {
+            let expiration_ms = (expires_at - Utc::now()).num_milliseconds().max(0);
+            properties.with_expiration(&expiration_ms.to_string());
+        }
 
-        Ok(())
+        let (channel, confirms) = self.publisher().await?;
+
+        // Hold the lock while publishing, so delivery tags match the order of the messages
+        let (delivery_tag, confirmation) = {
+            let mut confirms = confirms.lock().await;
+            let (delivery_tag, confirmation) = confirms.register(message_id, routing_key);
+            if let Err(e) = channel
+                .basic_publish(properties, serialized_message, args)
+                .await
+            {
+                confirms.discard(delivery_tag);
+                return Err(e.into());
+            }
+            (delivery_tag, confirmation)
+        };
+
+        Ok(wait_for_confirm(
+            &confirms,
+            delivery_tag,
+            confirmation,
+            PUBLISH_CONFIRM_TIMEOUT,
+        )
+        .await?)
     }
 
-    pub async fn subscribe<C>(&self, consumer: C) -> Result<(), Box<dyn std::error::Error>>
-    where
-        C: AsyncConsumer + Send + Sync + 'static,
-    {
-        let args = BasicConsumeArguments::new(
-            self.queue_name.unwrap(),
-            "consumer_tag_somehow_take_from_consumer",
-        );
+    /// The handler is subscribed again after a reconnect
+    async fn subscribe(&self, handler: Arc<dyn MessageHandler>) -> Result<(), BusError> {
+        let link = self.link.as_ref().ok_or("Channel not initialized")?;
 
-        self.channel
-            .as_ref()
-            .ok_or("Channel not initialized")?
-            .basic_consume(consumer, args)
-            .await
-            .unwrap();
+        let namespace_prefix = self.namespace_prefix.clone();
+        let subscribe: SubscribeFn = Box::new(move |channel, args| {
+            let consumer = HandlerConsumer {
+                handler: handler.clone(),
+                namespace_prefix: namespace_prefix.clone(),
+            };
+            Box::pin(async move { channel.basic_consume(consumer, args).await })
+        });
+
+        let channel = self.channel().await?;
+        subscribe(channel, self.consume_args()?).await?;
+        link.consumers.lock().await.push(subscribe);
 
         Ok(())
     }
 
-    pub async fn close(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(connection) = self.connection.take() {
-            connection.close().await?;
+    async fn bind_topic(&self, topic: &str) -> Result<(), BusError> {
+        let topic = &self.namespaced(topic);
+        let channel = self.channel().await?;
+        channel
+            .queue_bind(QueueBindArguments::new(
+                self.config.queue_name().ok_or("Queue not declared")?,
+                self.config.exchange_name(),
+                topic,
+            ))
+            .await?;
+
+        if let Some(link) = &self.link {
+            let mut topics = link.topics.lock().await;
+            if !topics.iter().any(|t| t == topic) {
+                topics.push(topic.to_string());
+            }
         }
-        if let Some(channel) = self.channel.take() {
-            channel.close().await?;
+
+        Ok(())
+    }
+
+    async fn unbind_topic(&self, topic: &str) -> Result<(), BusError> {
+        let topic = &self.namespaced(topic);
+        let channel = self.channel().await?;
+        channel
+            .queue_unbind(QueueUnbindArguments::new(
+                self.config.queue_name().ok_or("Queue not declared")?,
+                self.config.exchange_name(),
+                topic,
+            ))
+            .await?;
+
+        if let Some(link) = &self.link {
+            link.topics.lock().await.retain(|t| t != topic);
         }
 
         Ok(())
     }
+
+    fn connection_state(&self) -> ConnectionState {
+        self.link
+            .as_ref()
+            .map(|link| *link.state.borrow())
+            .unwrap_or(ConnectionState::Closed)
+    }
+}
+
+/// Adapts the message handler to the amqprs consumer
+struct HandlerConsumer {
+    handler: Arc<dyn MessageHandler>,
+    // Stripped from the dead-lettered queue names
+    namespace_prefix: String,
+}
+
+#[async_trait]
+impl AsyncConsumer for HandlerConsumer {
+    async fn consume(
+        &mut self,
+        channel: &Channel,
+        deliver: Deliver,
+        basic_properties: BasicProperties,
+        content: Vec<u8>,
+    ) {
+        let delivery = Delivery {
+            message_id: basic_properties.message_id().cloned(),
+            body: content,
+            content_type: basic_properties.content_type().cloned(),
+            redelivered: deliver.redelivered(),
+            dead_letter: basic_properties
+                .headers()
+                .and_then(dead_letter)
+                .map(|dead_letter| DeadLetter {
+                    queue: dead_letter
+                        .queue
+                        .strip_prefix(&self.namespace_prefix)
+                        .map(str::to_string)
+                        .unwrap_or(dead_letter.queue),
+                    ..dead_letter
+                }),
+            signature: basic_properties.headers().and_then(signature),
+        };
+
+        // Unacked message is redelivered after reconnect
+        let result = match self.handler.handle(delivery).await {
+            Acknowledgement::Ack => {
+                channel
+                    .basic_ack(BasicAckArguments::new(deliver.delivery_tag(), false))
+                    .await
+            }
+            Acknowledgement::Nack { requeue } => {
+                channel
+                    .basic_nack(BasicNackArguments::new(
+                        deliver.delivery_tag(),
+                        false,
+                        requeue,
+                    ))
+                    .await
+            }
+        };
+
+        if let Err(e) = result {
+            error!("Error acknowledging message: {:?}", e);
+        }
+    }
+}
+
+fn field_name(name: &str) -> FieldName {
+    name.try_into().expect("Field name too long")
+}
+
+/// Read the body signature from the `x-signature` header
+fn signature(headers: &FieldTable) -> Option<Vec<u8>> {
+    match headers.get(&field_name(SIGNATURE_HEADER))? {
+        FieldValue::S(value) => STANDARD.decode(value.as_ref()).ok(),
+        _ => None,
+    }
 }
 
+/// Read the most recent dead-lettering from the `x-death` header
+fn dead_letter(headers: &FieldTable) -> Option<DeadLetter> {
+    let FieldValue::A(deaths) = headers.get(&field_name("x-death"))? else {
+        return None;
+    };
+    let deaths: Vec<FieldValue> = deaths.clone().into();
+    let FieldValue::F(death) = deaths.into_iter().next()? else {
+        return None;
+    };
+
+    let text = |name: &str| match death.get(&field_name(name)) {
+        Some(FieldValue::S(value)) => Some(value.as_ref().clone()),
+        _ => None,
+    };
+
+    Some(DeadLetter {
+        queue: text("queue")?,
+        reason: text("reason")?,
+    })
+}
+
+// Routing keys of the queues consumed by the scheduler
+pub const RESULT_ROUTING_KEY: &str = "worker_result";
+pub const STATUS_ROUTING_KEY: &str = "worker_status";
+
 // RabbitMQ configurations for various services and use cases
-pub const CONFIG_QUEUE_JOB: QueueHandler = QueueHandler {
-    exchange_name: "job_exchange",
+/// Jobs and control messages, workers add their own queue bound with their name and topics
+pub const CONFIG_QUEUE_JOB: QueueConfig = QueueConfig {
+    exchange_name: Cow::Borrowed("job_exchange"),
+    exchange_type: ExchangeType::Topic,
+    existing_exchange: false,
     queue_name: None,
     routing_key: None,
-    exchange_type: "topic",
-    connection: None,
-    channel: None,
+    dead_letter_exchange: Some(Cow::Borrowed("job_dead_letter_exchange")),
+    topics: Vec::new(),
+    prefetch_count: 0,
+    consumer_tag: None,
+    broker: None,
+};
+
+pub const CONFIG_QUEUE_RESULT: QueueConfig = QueueConfig {
+    exchange_name: Cow::Borrowed("result_exchange"),
+    exchange_type: ExchangeType::Direct,
+    existing_exchange: false,
+    queue_name: Some(Cow::Borrowed("result_queue")),
+    routing_key: Some(Cow::Borrowed(RESULT_ROUTING_KEY)),
+    dead_letter_exchange: None,
+    topics: Vec::new(),
+    prefetch_count: 0,
+    consumer_tag: None,
+    broker: None,
 };
 
-pub const CONFIG_QUEUE_RESULT: QueueHandler = QueueHandler {
-    exchange_name: "result_exchange",
-    queue_name: Some("result_queue"),
-    routing_key: Some("worker_result"),
-    exchange_type: "direct",
-    connection: None,
-    channel: None,
+pub const CONFIG_QUEUE_STATUS: QueueConfig = QueueConfig {
+    exchange_name: Cow::Borrowed("status_exchange"),
+    exchange_type: ExchangeType::Direct,
+    existing_exchange: false,
+    queue_name: Some(Cow::Borrowed("status_queue")),
+    routing_key: Some(Cow::Borrowed(STATUS_ROUTING_KEY)),
+    dead_letter_exchange: None,
+    topics: Vec::new(),
+    prefetch_count: 0,
+    consumer_tag: None,
+    broker: None,
 };
 
-pub const CONFIG_QUEUE_STATUS: QueueHandler = QueueHandler {
-    exchange_name: "status_exchange",
-    queue_name: Some("status_queue"),
-    routing_key: Some("worker_status"),
-    exchange_type: "direct",
-    connection: None,
-    channel: None,
+pub const CONFIG_QUEUE_DEAD_LETTER: QueueConfig = QueueConfig {
+    exchange_name: Cow::Borrowed("job_dead_letter_exchange"),
+    exchange_type: ExchangeType::Fanout,
+    existing_exchange: false,
+    queue_name: Some(Cow::Borrowed("job_dead_letter_queue")),
+    routing_key: Some(Cow::Borrowed("job_dead_letter")),
+    dead_letter_exchange: None,
+    topics: Vec::new(),
+    prefetch_count: 0,
+    consumer_tag: None,
+    broker: None,
 };
@@ -0,0 +1,273 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+
+use crate::{
+    signing::{self, SigningKey},
+    Acknowledgement, BusError, Delivery, Encoding, Envelope, MessageBus, MessageHandler,
+    PublishError, MESSAGE_VERSION,
+};
+
+struct MemoryQueue {
+    exchange_name: String,
+    bindings: HashSet<String>,
+    sender: mpsc::UnboundedSender<Delivery>,
+    // Taken by the first subscriber, messages are buffered until then
+    receiver: Option<mpsc::UnboundedReceiver<Delivery>>,
+}
+
+/// In-process broker routing messages between `InMemoryBus` handles, for running without RabbitMQ.
+/// Routing keys and topics are matched exactly, wildcards are not supported.
+/// Messages don't expire and are never dead-lettered.
+#[derive(Clone, Default)]
+pub struct InMemoryBroker {
+    queues: Arc<Mutex<HashMap<String, MemoryQueue>>>,
+}
+
+impl InMemoryBroker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declare the queue bound to the exchange with the routing key, same as `QueueHandler::setup`
+    pub fn bus(
+        &self,
+        exchange_name: &str,
+        queue_name: &str,
+        routing_key: &str,
+        sender: &str,
+    ) -> InMemoryBus {
+        let mut queues = self.queues.lock().unwrap();
+        let queue = queues.entry(queue_name.to_string()).or_insert_with(|| {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            MemoryQueue {
+                exchange_name: exchange_name.to_string(),
+                bindings: HashSet::new(),
+                sender,
+                receiver: Some(receiver),
+            }
+        });
+        queue.bindings.insert(routing_key.to_string());
+
+        InMemoryBus {
+            broker: self.clone(),
+            queue_name: queue_name.to_string(),
+            exchange_name: exchange_name.to_string(),
+            sender: sender.to_string(),
+            signing_key: None,
+        }
+    }
+}
+
+/// `MessageBus` backed by the `InMemoryBroker`
+#[derive(Clone)]
+pub struct InMemoryBus {
+    broker: InMemoryBroker,
+    exchange_name: String,
+    queue_name: String,
+    sender: String,
+    signing_key: Option<Arc<SigningKey>>,
+}
+
+impl InMemoryBus {
+    /// Sign the published messages with the key, same as `QueueHandler::with_signing_key`
+    pub fn with_signing_key(mut self, signing_key: SigningKey) -> Self {
+        self.signing_key = Some(Arc::new(signing_key));
+        self
+    }
+}
+
+#[async_trait]
+impl MessageBus for InMemoryBus {
+    fn sender(&self) -> &str {
+        &self.sender
+    }
+
+    async fn publish_envelope(
+        &self,
+        envelope: &Envelope,
+        routing_key: &str,
+    ) -> Result<(), BusError> {
+        let body = envelope.encode(MESSAGE_VERSION, Encoding::Json)?;
+        let signature = self
+            .signing_key
+            .as_ref()
+            .map(|signing_key| signing::sign(signing_key, &body));
+        let queues = self.broker.queues.lock().unwrap();
+
+        let mut routed = false;
+        for queue in queues.values().filter(|queue| {
+            queue.exchange_name == self.exchange_name && queue.bindings.contains(routing_key)
+        }) {
+            queue
+                .sender
+                .send(Delivery {
+                    message_id: Some(envelope.message_id.to_string()),
+                    body: body.clone(),
+                    content_type: Some(Encoding::Json.content_type().to_string()),
+                    redelivered: false,
+                    dead_letter: None,
+                    signature: signature.clone(),
+                })
+                .map_err(|_| "Queue closed")?;
+            routed = true;
+        }
+
+        // Same as a mandatory publish to RabbitMQ
+        if !routed {
+            return Err(PublishError::Unroutable {
+                routing_key: routing_key.to_string(),
+                reply_text: "NO_ROUTE".to_string(),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    async fn subscribe(&self, handler: Arc<dyn MessageHandler>) -> Result<(), BusError> {
+        let (sender, mut receiver) = {
+            let mut queues = self.broker.queues.lock().unwrap();
+            let queue = queues
+                .get_mut(&self.queue_name)
+                .ok_or("Queue not declared")?;
+            let receiver = queue
+                .receiver
+                .take()
+                .ok_or("Queue already has a subscriber")?;
+            (queue.sender.clone(), receiver)
+        };
+
+        tokio::spawn(async move {
+            while let Some(delivery) = receiver.recv().await {
+                if let Acknowledgement::Nack { requeue: true } =
+                    handler.handle(delivery.clone()).await
+                {
+                    sender
+                        .send(Delivery {
+                            redelivered: true,
+                            ..delivery
+                        })
+                        .ok();
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    async fn bind_topic(&self, topic: &str) -> Result<(), BusError> {
+        let mut queues = self.broker.queues.lock().unwrap();
+        let queue = queues
+            .get_mut(&self.queue_name)
+            .ok_or("Queue not declared")?;
+        queue.bindings.insert(topic.to_string());
+
+        Ok(())
+    }
+
+    async fn unbind_topic(&self, topic: &str) -> Result<(), BusError> {
+        let mut queues = self.broker.queues.lock().unwrap();
+        let queue = queues
+            .get_mut(&self.queue_name)
+            .ok_or("Queue not declared")?;
+        queue.bindings.remove(topic);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc::UnboundedSender;
+
+    use super::*;
+    use crate::{ControlMessage, Message};
+
+    struct Collector {
+        received: UnboundedSender<Envelope>,
+        requeue_first: bool,
+    }
+
+    #[async_trait]
+    impl MessageHandler for Collector {
+        async fn handle(&self, delivery: Delivery) -> Acknowledgement {
+            if self.requeue_first && !delivery.redelivered {
+                return Acknowledgement::Nack { requeue: true };
+            }
+            self.received
+                .send(Envelope::decode(&delivery.body, Encoding::Json).unwrap())
+                .unwrap();
+            Acknowledgement::Ack
+        }
+    }
+
+    fn control_message() -> Message {
+        Message::WorkerControl {
+            worker_name: "worker-1".to_string(),
+            command: ControlMessage::SetTopics(vec!["all".to_string()]),
+        }
+    }
+
+    #[tokio::test]
+    async fn routes_by_topic() {
+        let broker = InMemoryBroker::new();
+        let scheduler = broker.bus("job_exchange", "scheduler", "scheduler", "scheduler");
+        let worker = broker.bus("job_exchange", "worker-1", "worker-1", "worker-1");
+
+        let (received, mut receiver) = mpsc::unbounded_channel();
+        worker
+            .subscribe(Arc::new(Collector {
+                received,
+                requeue_first: false,
+            }))
+            .await
+            .unwrap();
+
+        let unroutable = scheduler.publish(&control_message(), "europe").await;
+        assert!(matches!(
+            unroutable.unwrap_err().downcast_ref::<PublishError>(),
+            Some(PublishError::Unroutable { .. })
+        ));
+
+        worker.bind_topic("europe").await.unwrap();
+        scheduler
+            .publish(&control_message(), "europe")
+            .await
+            .unwrap();
+
+        let envelope = receiver.recv().await.unwrap();
+        assert_eq!(envelope.sender, "scheduler");
+        assert!(matches!(envelope.message, Message::WorkerControl { .. }));
+    }
+
+    #[tokio::test]
+    async fn redelivers_requeued_messages() {
+        let broker = InMemoryBroker::new();
+        let bus = broker.bus(
+            "status_exchange",
+            "status_queue",
+            "worker_status",
+            "worker-1",
+        );
+
+        // Published before subscribing, must be buffered
+        bus.publish(&control_message(), "worker_status")
+            .await
+            .unwrap();
+
+        let (received, mut receiver) = mpsc::unbounded_channel();
+        bus.subscribe(Arc::new(Collector {
+            received,
+            requeue_first: true,
+        }))
+        .await
+        .unwrap();
+
+        assert!(receiver.recv().await.is_some());
+    }
+}
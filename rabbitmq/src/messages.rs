@@ -23,6 +23,8 @@ pub struct ResultMessage {
     pub download_result: Result<DownloadResult, DownloadError>,
     pub ping_result: Result<PingResult, PingError>,
     pub head_result: Result<HeadResult, HeadError>,
+    #[serde(default)]
+    pub host_metrics: Option<HostMetrics>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -92,11 +94,34 @@ impl ResultMessage {
                 error: error.clone(),
             }),
             head_result: Err(HeadError { error }),
+            host_metrics: None,
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Resource usage of the worker host, averaged over the sampling window
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HostMetrics {
+    pub sampled_at: DateTime<Utc>,
+    pub window_secs: f64,
+    pub cpu_usage_percent: f64,
+    pub memory_total_bytes: u64,
+    pub memory_available_bytes: u64,
+    // Optional fields may be left out by other versions of the worker
+    #[serde(default)]
+    pub network: Option<NetworkMetrics>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NetworkMetrics {
+    pub interface: String,
+    pub rx_bytes_per_sec: f64,
+    pub tx_bytes_per_sec: f64,
+    #[serde(default)]
+    pub link_speed_mbps: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct WorkerStatusJobDetails {
     pub run_id: Uuid,
     pub job_id: Uuid,
@@ -104,19 +129,53 @@ pub struct WorkerStatusJobDetails {
     pub worker_name: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Bytes downloaded so far by a running sub job, published every interval when enabled on the worker
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DownloadProgress {
+    pub run_id: Uuid,
+    pub job_id: Uuid,
+    pub sub_job_id: Uuid,
+    pub timestamp: DateTime<Utc>,
+    pub interval_bytes: usize,
+    pub total_bytes: usize,
+    /// Seconds since the first byte
+    pub elapsed_secs: f64,
+    /// Mbps since the first byte
+    pub download_speed: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum WorkerStatusDetails {
     Lifecycle(WorkerDetails),
     Job(Option<WorkerStatusJobDetails>),
-    Heartbeat,
+    Heartbeat(Option<HostMetrics>),
+    /// Not understood by schedulers older than the variant, enable it on workers after upgrading them
+    Progress(DownloadProgress),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct WorkerDetails {
     pub worker_topics: Vec<String>,
     pub worker_status: WorkerStatus,
+    #[serde(default)]
+    pub calibration: Option<CalibrationResult>,
 }
-#[derive(Serialize, Deserialize, Debug)]
+
+/// Throughput the worker achieved against the reference endpoint
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CalibrationResult {
+    pub url: String,
+    pub total_bytes: usize,
+    pub elapsed_secs: f64,
+    pub download_speed: f64,
+    pub calibrated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CalibrationError {
+    pub error: String,
+}
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum WorkerStatus {
     Online,
     Offline,
@@ -130,7 +189,14 @@ impl WorkerStatus {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Commands sent by the scheduler to a single worker
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ControlMessage {
+    /// Replace the topics the worker is bound to
+    SetTopics(Vec<String>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct StatusMessage {
     pub worker_name: String,
     pub status: WorkerStatusDetails,
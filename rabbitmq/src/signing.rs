@@ -0,0 +1,94 @@
+//! Ed25519 signatures of the published messages.
+//!
+//! The signature covers the encoded message body exactly as published and travels in the
+//! `x-signature` header (base64), so it doesn't depend on the envelope version or encoding.
+//! Public keys are exchanged as base64 of the 32 key bytes.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ed25519_dalek::{Signature, Signer, Verifier};
+
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+/// AMQP header carrying the signature of the message body
+pub const SIGNATURE_HEADER: &str = "x-signature";
+
+/// Sign the encoded message body
+pub fn sign(key: &SigningKey, body: &[u8]) -> Vec<u8> {
+    key.sign(body).to_bytes().to_vec()
+}
+
+/// Check the signature of the encoded message body
+pub fn verify(key: &VerifyingKey, body: &[u8], signature: &[u8]) -> bool {
+    Signature::from_slice(signature)
+        .map(|signature| key.verify(body, &signature).is_ok())
+        .unwrap_or(false)
+}
+
+/// Parse the base64 public key
+pub fn decode_public_key(public_key: &str) -> Result<VerifyingKey, String> {
+    let bytes = STANDARD
+        .decode(public_key.trim())
+        .map_err(|e| format!("Invalid base64: {}", e))?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| "Public key must be 32 bytes".to_string())?;
+
+    VerifyingKey::from_bytes(&bytes).map_err(|e| format!("Invalid public key: {}", e))
+}
+
+/// Base64 of the public key, as registered with the scheduler
+pub fn encode_public_key(key: &VerifyingKey) -> String {
+    STANDARD.encode(key.as_bytes())
+}
+
+/// Parse the base64 secret key, as stored in the key file
+pub fn decode_signing_key(secret_key: &str) -> Result<SigningKey, String> {
+    let bytes = STANDARD
+        .decode(secret_key.trim())
+        .map_err(|e| format!("Invalid base64: {}", e))?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| "Secret key must be 32 bytes".to_string())?;
+
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+/// Base64 of the secret key, as stored in the key file
+pub fn encode_signing_key(key: &SigningKey) -> String {
+    STANDARD.encode(key.to_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> SigningKey {
+        SigningKey::from_bytes(&[7; 32])
+    }
+
+    #[test]
+    fn verifies_signed_body() {
+        let key = key();
+        let signature = sign(&key, b"body");
+
+        assert!(verify(&key.verifying_key(), b"body", &signature));
+        assert!(!verify(&key.verifying_key(), b"tampered", &signature));
+        assert!(!verify(&key.verifying_key(), b"body", &signature[1..]));
+    }
+
+    #[test]
+    fn round_trips_keys() {
+        let key = key();
+
+        let public_key = encode_public_key(&key.verifying_key());
+        assert_eq!(decode_public_key(&public_key), Ok(key.verifying_key()));
+
+        let secret_key = encode_signing_key(&key);
+        assert_eq!(
+            decode_signing_key(&secret_key).unwrap().to_bytes(),
+            key.to_bytes()
+        );
+
+        assert!(decode_public_key("c2hvcnQ=").is_err());
+    }
+}
@@ -1,20 +1,41 @@
-use axum::extract::rejection::{JsonRejection, QueryRejection};
-use axum::http::StatusCode;
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use std::time::Duration;
+
+use axum::http::{header::RETRY_AFTER, StatusCode};
 use axum::response::{IntoResponse, Json, Response};
 use serde::Serialize;
+use utoipa::ToSchema;
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct ErrorResponse {
     error: String,
 }
 
 pub enum ApiResponse<T> {
     BadRequest(Json<ErrorResponse>),
+    Unauthorized(Json<ErrorResponse>),
+    Forbidden(Json<ErrorResponse>),
     InternalServerError(Json<ErrorResponse>),
     NotFound(Json<ErrorResponse>),
+    TooManyRequests(Json<ErrorResponse>, Duration),
     OkResponse(Json<T>),
 }
 
+impl<T> ApiResponse<T> {
+    /// Message of the error responses, `None` for the ok response
+    pub fn error_message(&self) -> Option<&str> {
+        match self {
+            ApiResponse::BadRequest(json)
+            | ApiResponse::Unauthorized(json)
+            | ApiResponse::Forbidden(json)
+            | ApiResponse::InternalServerError(json)
+            | ApiResponse::NotFound(json)
+            | ApiResponse::TooManyRequests(json, _) => Some(&json.error),
+            ApiResponse::OkResponse(_) => None,
+        }
+    }
+}
+
 impl From<JsonRejection> for ApiResponse<ErrorResponse> {
     fn from(rejection: JsonRejection) -> ApiResponse<ErrorResponse> {
         ApiResponse::BadRequest(Json(ErrorResponse {
@@ -31,6 +52,14 @@ impl From<QueryRejection> for ApiResponse<ErrorResponse> {
     }
 }
 
+impl From<PathRejection> for ApiResponse<ErrorResponse> {
+    fn from(rejection: PathRejection) -> ApiResponse<ErrorResponse> {
+        ApiResponse::BadRequest(Json(ErrorResponse {
+            error: rejection.body_text(),
+        }))
+    }
+}
+
 impl<T> IntoResponse for ApiResponse<T>
 where
     T: Serialize,
@@ -38,10 +67,18 @@ where
     fn into_response(self) -> Response {
         match self {
             ApiResponse::BadRequest(json) => (StatusCode::BAD_REQUEST, json).into_response(),
+            ApiResponse::Unauthorized(json) => (StatusCode::UNAUTHORIZED, json).into_response(),
+            ApiResponse::Forbidden(json) => (StatusCode::FORBIDDEN, json).into_response(),
             ApiResponse::InternalServerError(json) => {
                 (StatusCode::INTERNAL_SERVER_ERROR, json).into_response()
             }
             ApiResponse::NotFound(json) => (StatusCode::NOT_FOUND, json).into_response(),
+            ApiResponse::TooManyRequests(json, retry_after) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(RETRY_AFTER, retry_after.as_secs_f64().ceil().to_string())],
+                json,
+            )
+                .into_response(),
             ApiResponse::OkResponse(json) => (StatusCode::OK, json).into_response(),
         }
     }
@@ -51,6 +88,14 @@ pub fn bad_request<T: Into<String>>(msg: T) -> ApiResponse<()> {
     ApiResponse::BadRequest(Json(ErrorResponse { error: msg.into() }))
 }
 
+pub fn unauthorized<T: Into<String>>(msg: T) -> ApiResponse<()> {
+    ApiResponse::Unauthorized(Json(ErrorResponse { error: msg.into() }))
+}
+
+pub fn forbidden<T: Into<String>>(msg: T) -> ApiResponse<()> {
+    ApiResponse::Forbidden(Json(ErrorResponse { error: msg.into() }))
+}
+
 pub fn internal_server_error<T: Into<String>>(msg: T) -> ApiResponse<()> {
     ApiResponse::InternalServerError(Json(ErrorResponse { error: msg.into() }))
 }
@@ -59,6 +104,11 @@ pub fn not_found<T: Into<String>>(msg: T) -> ApiResponse<()> {
     ApiResponse::NotFound(Json(ErrorResponse { error: msg.into() }))
 }
 
+/// 429 with the Retry-After header, rounded up to whole seconds
+pub fn too_many_requests<T: Into<String>>(msg: T, retry_after: Duration) -> ApiResponse<()> {
+    ApiResponse::TooManyRequests(Json(ErrorResponse { error: msg.into() }), retry_after)
+}
+
 pub fn ok_response<T: Serialize>(data: T) -> ApiResponse<T> {
     ApiResponse::OkResponse(Json(data))
 }
@@ -0,0 +1,288 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use axum::{
+    debug_handler,
+    extract::{Extension, Json, State},
+};
+use axum_extra::extract::WithRejection;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::Semaphore, task::JoinSet};
+use tracing::{error, info};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    api::api_response::*,
+    api_key_repository::ApiKey,
+    job_service::{self, JobProfile, PreparedJob, JOB_DURATION, SUB_JOB_DURATION, SYNC_DELAY_SECS},
+    state::AppState,
+};
+
+const MAX_TARGETS: usize = 100;
+// HEAD requests sent at once while validating the targets
+const VALIDATION_CONCURRENCY: usize = 10;
+
+#[derive(Deserialize, ToSchema)]
+pub struct BatchInput {
+    /// Profile of the targets without their own
+    #[serde(default)]
+    pub profile: Option<JobProfile>,
+    pub targets: Vec<BatchTarget>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct BatchTarget {
+    pub url: String,
+    /// Replaces the shared profile for this target
+    #[serde(default)]
+    pub profile: Option<JobProfile>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BatchResponse {
+    /// Groups the jobs, filter the job listing with it
+    pub batch_id: Uuid,
+    /// In the order of the targets
+    pub jobs: Vec<BatchJob>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BatchJob {
+    pub url: String,
+    /// Not set when the job couldn't be created, see `error`. Set with an `error` when the job
+    /// was created but its sub jobs couldn't be dispatched, the job is failed then.
+    pub job_id: Option<Uuid>,
+    pub sub_jobs: Vec<Uuid>,
+    /// Start of the first sub job
+    pub start_time: DateTime<Utc>,
+    pub error: Option<String>,
+}
+
+/// POST /jobs/batch
+/// Create a job for each target, scheduled so that jobs running at the same time don't share workers
+#[utoipa::path(
+    post,
+    path = "/jobs/batch",
+    request_body = BatchInput,
+    responses(
+        (status = 200, description = "Batch created, jobs that couldn't be created (e.g. over the rate limits) have an error", body = BatchResponse),
+        (status = 400, description = "Invalid batch or targets, no job was created", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or revoked API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the required scope", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+    ),
+    security(("api_key" = ["jobs:create"])),
+)]
+#[debug_handler]
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    Extension(api_key): Extension<ApiKey>,
+    WithRejection(Json(payload), _): WithRejection<Json<BatchInput>, ApiResponse<ErrorResponse>>,
+) -> Result<ApiResponse<BatchResponse>, ApiResponse<()>> {
+    if payload.targets.is_empty() || payload.targets.len() > MAX_TARGETS {
+        return Err(bad_request(format!(
+            "Batch must have between 1 and {} targets",
+            MAX_TARGETS
+        )));
+    }
+
+    // Unknown providers reject the batch like the other invalid input, before the HEAD requests
+    let mut checked_providers = HashSet::new();
+    let profiles = payload
+        .targets
+        .iter()
+        .filter_map(|target| target.profile.as_ref())
+        .chain(payload.profile.as_ref());
+    for profile in profiles {
+        if profile
+            .provider_id
+            .is_some_and(|provider_id| checked_providers.insert(provider_id))
+        {
+            job_service::check_provider(&state, profile).await?;
+        }
+    }
+
+    let targets = prepare_targets(&state, &api_key, payload).await?;
+
+    // Jobs of one slot run at the same time, so they must not share workers
+    let mut workers_by_routing_key: HashMap<String, Vec<String>> = HashMap::new();
+    let mut slots: Vec<HashSet<String>> = Vec::new();
+    let mut job_slots = Vec::with_capacity(targets.len());
+    for (_, target) in &targets {
+        let Ok(job) = target else {
+            job_slots.push(None);
+            continue;
+        };
+        let routing_key = &job.profile.routing_key;
+        if !workers_by_routing_key.contains_key(routing_key) {
+            let workers = state
+                .topic_repo
+                .get_workers_for_routing_key(routing_key)
+                .await
+                .map_err(|e| {
+                    error!("Failed to get workers from the database: {:?}", e);
+                    internal_server_error("Failed to get workers")
+                })?;
+            workers_by_routing_key.insert(routing_key.clone(), workers);
+        }
+        let workers = &workers_by_routing_key[routing_key];
+
+        let slot = slots
+            .iter()
+            .position(|busy| workers.iter().all(|worker| !busy.contains(worker)))
+            .unwrap_or_else(|| {
+                slots.push(HashSet::new());
+                slots.len() - 1
+            });
+        slots[slot].extend(workers.iter().cloned());
+        job_slots.push(Some(slot));
+    }
+
+    // Workers are busy until the end of their pending and running sub jobs, also of other jobs
+    let mut busy_until: HashMap<String, DateTime<Utc>> = state
+        .sub_job_repo
+        .get_last_sub_job_start_by_worker()
+        .await
+        .map_err(|e| {
+            error!("Failed to get sub jobs from the database: {:?}", e);
+            internal_server_error("Failed to get workers")
+        })?
+        .into_iter()
+        .map(|(worker, last_start)| (worker, last_start + SUB_JOB_DURATION))
+        .collect();
+
+    // Dispatched slot by slot, each job starts once its workers are free. The start is computed
+    // when the job is dispatched, so a slow dispatch can't make it start before it's sent.
+    let batch_id = Uuid::new_v4();
+    let mut targets: Vec<_> = targets.into_iter().zip(job_slots).enumerate().collect();
+    targets.sort_by_key(|(_, (_, slot))| *slot);
+
+    let mut jobs = Vec::with_capacity(targets.len());
+    for (index, ((url, target), _)) in targets {
+        let workers = target
+            .as_ref()
+            .ok()
+            .map(|job| workers_by_routing_key[&job.profile.routing_key].as_slice())
+            .unwrap_or_default();
+        let start_time = workers
+            .iter()
+            .filter_map(|worker| busy_until.get(worker))
+            .copied()
+            .fold(
+                Utc::now() + Duration::from_secs(SYNC_DELAY_SECS),
+                |start_time, busy_until| start_time.max(busy_until),
+            );
+
+        let created = match target {
+            Ok(job) => {
+                job_service::create_job(&state, &api_key, &job, start_time, Some(batch_id)).await
+            }
+            Err(limited) => Err(limited.into()),
+        };
+
+        let job = match created {
+            Ok(created) => BatchJob {
+                url,
+                job_id: Some(created.job_id),
+                sub_jobs: created.sub_jobs,
+                start_time,
+                error: None,
+            },
+            Err(e) => BatchJob {
+                url,
+                job_id: e.job_id,
+                sub_jobs: Vec::new(),
+                start_time,
+                error: e.response.error_message().map(str::to_string),
+            },
+        };
+        // Sub jobs dispatched before a failure still run
+        if job.job_id.is_some() {
+            for worker in workers {
+                busy_until.insert(worker.clone(), start_time + JOB_DURATION);
+            }
+        }
+        jobs.push((index, job));
+    }
+    jobs.sort_by_key(|(index, _)| *index);
+    let jobs: Vec<BatchJob> = jobs.into_iter().map(|(_, job)| job).collect();
+
+    info!(
+        "Batch {} created: {} jobs in {} slots",
+        batch_id,
+        jobs.len(),
+        slots.len()
+    );
+
+    Ok(ok_response(BatchResponse { batch_id, jobs }))
+}
+
+/// Validate the targets concurrently, all of them must be valid. Targets over the rate limits
+/// aren't contacted, they are returned with the 429 response and the batch goes on without them.
+async fn prepare_targets(
+    state: &Arc<AppState>,
+    api_key: &ApiKey,
+    payload: BatchInput,
+) -> Result<Vec<(String, Result<PreparedJob, ApiResponse<()>>)>, ApiResponse<()>> {
+    let semaphore = Arc::new(Semaphore::new(VALIDATION_CONCURRENCY));
+    let mut tasks = JoinSet::new();
+    for (index, target) in payload.targets.into_iter().enumerate() {
+        let Some(profile) = target.profile.or_else(|| payload.profile.clone()) else {
+            return Err(bad_request(format!(
+                "Target {} has no profile and the batch has no shared profile",
+                index
+            )));
+        };
+
+        let semaphore = semaphore.clone();
+        let state = state.clone();
+        let api_key = api_key.clone();
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            let prepared = job_service::prepare_job(&state, &api_key, &target.url, profile).await;
+            (index, target.url, prepared)
+        });
+    }
+
+    let mut targets = Vec::with_capacity(tasks.len());
+    let mut errors = Vec::new();
+    while let Some(task) = tasks.join_next().await {
+        match task {
+            Ok((index, url, Err(response)))
+                if !matches!(response, ApiResponse::TooManyRequests(..)) =>
+            {
+                errors.push((
+                    index,
+                    format!(
+                        "target {} ({}): {}",
+                        index,
+                        url,
+                        response.error_message().unwrap_or_default()
+                    ),
+                ));
+            }
+            Ok((index, url, prepared)) => targets.push((index, url, prepared)),
+            Err(e) => {
+                error!("Target validation task failed: {:?}", e);
+                return Err(internal_server_error("Failed to validate targets"));
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        errors.sort_by_key(|(index, _)| *index);
+        let errors: Vec<String> = errors.into_iter().map(|(_, e)| e).collect();
+        return Err(bad_request(format!("Invalid {}", errors.join("; "))));
+    }
+
+    targets.sort_by_key(|(index, _, _)| *index);
+    Ok(targets
+        .into_iter()
+        .map(|(_, url, prepared)| (url, prepared))
+        .collect())
+}
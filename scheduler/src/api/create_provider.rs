@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use axum::{
+    debug_handler,
+    extract::{Json, State},
+};
+use axum_extra::extract::WithRejection;
+use serde::Deserialize;
+use tracing::{error, info};
+use url::Url;
+use utoipa::ToSchema;
+
+use crate::{
+    api::api_response::*,
+    provider_repository::{NewProvider, Provider},
+    state::AppState,
+};
+
+#[derive(Deserialize, ToSchema)]
+pub struct ProviderInput {
+    /// Filecoin miner ID, e.g. f01234
+    pub miner_id: String,
+    #[serde(default)]
+    pub organization: Option<String>,
+    /// http or https URLs the provider serves retrievals from
+    #[serde(default)]
+    pub retrieval_endpoints: Vec<String>,
+    #[serde(default)]
+    pub region: Option<String>,
+}
+
+impl ProviderInput {
+    pub fn to_new_provider(&self) -> Result<NewProvider<'_>, ApiResponse<()>> {
+        if !is_miner_id(&self.miner_id) {
+            return Err(bad_request(format!(
+                "Invalid miner ID: {:?}, expected e.g. f01234",
+                self.miner_id
+            )));
+        }
+        for endpoint in &self.retrieval_endpoints {
+            let url = Url::parse(endpoint)
+                .map_err(|_| bad_request(format!("Invalid retrieval endpoint: {}", endpoint)))?;
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(bad_request(
+                    "Retrieval endpoint scheme must be http or https",
+                ));
+            }
+        }
+        if self.organization.as_deref().is_some_and(str::is_empty) {
+            return Err(bad_request("Organization cannot be empty"));
+        }
+        if self.region.as_deref().is_some_and(str::is_empty) {
+            return Err(bad_request("Region cannot be empty"));
+        }
+
+        Ok(NewProvider {
+            miner_id: &self.miner_id,
+            organization: self.organization.as_deref(),
+            retrieval_endpoints: &self.retrieval_endpoints,
+            region: self.region.as_deref(),
+        })
+    }
+}
+
+/// Miner IDs are ID addresses, `f0` (or `t0` on testnets) followed by the actor number
+fn is_miner_id(miner_id: &str) -> bool {
+    miner_id
+        .strip_prefix("f0")
+        .or_else(|| miner_id.strip_prefix("t0"))
+        .is_some_and(|number| !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Map the unique violation of the miner ID to a bad request
+pub fn provider_save_error(e: sqlx::Error, miner_id: &str) -> ApiResponse<()> {
+    match e.as_database_error() {
+        Some(db_error) if db_error.is_unique_violation() => {
+            bad_request(format!("Provider {} already exists", miner_id))
+        }
+        _ => {
+            error!("Failed to save provider: {:?}", e);
+            internal_server_error("Failed to save provider")
+        }
+    }
+}
+
+/// POST /providers
+/// Register a storage provider, jobs reference it with `provider_id`
+#[utoipa::path(
+    post,
+    path = "/providers",
+    request_body = ProviderInput,
+    responses(
+        (status = 200, description = "Provider registered", body = Provider),
+        (status = 400, description = "Invalid input or the miner ID is already registered", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or revoked API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the required scope", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+    ),
+    security(("api_key" = ["admin"])),
+)]
+#[debug_handler]
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<ProviderInput>, ApiResponse<ErrorResponse>>,
+) -> Result<ApiResponse<Provider>, ApiResponse<()>> {
+    let new_provider = payload.to_new_provider()?;
+
+    let provider = state
+        .provider_repo
+        .create_provider(new_provider)
+        .await
+        .map_err(|e| provider_save_error(e, &payload.miner_id))?;
+
+    info!("Provider {} registered: {}", provider.miner_id, provider.id);
+
+    Ok(ok_response(provider))
+}
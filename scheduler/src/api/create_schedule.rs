@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use axum::{
+    debug_handler,
+    extract::{Extension, Json, State},
+};
+use axum_extra::extract::WithRejection;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::{
+    api::api_response::*,
+    api_key_repository::ApiKey,
+    job_service::{self, JobProfile},
+    schedule_repository::{NewSchedule, Schedule},
+    schedules::{next_run_after, parse_cron},
+    state::AppState,
+};
+
+#[derive(Deserialize, ToSchema)]
+pub struct ScheduleInput {
+    pub name: String,
+    pub url: String,
+    #[serde(flatten)]
+    pub profile: JobProfile,
+    /// 5 fields (minute, hour, day of month, month, day of week) or a shortcut like `@weekly`, in UTC
+    pub cron_expression: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl ScheduleInput {
+    /// Validate the schedule, the job is validated without contacting the URL
+    pub fn to_new_schedule(&self) -> Result<NewSchedule<'_>, ApiResponse<()>> {
+        if self.name.trim().is_empty() {
+            return Err(bad_request("Name cannot be empty"));
+        }
+        job_service::validate_job(&self.url, &self.profile)?;
+        let cron = parse_cron(&self.cron_expression).map_err(bad_request)?;
+        let next_run_at: DateTime<Utc> = next_run_after(&cron, Utc::now())
+            .ok_or_else(|| bad_request("Cron expression is never due"))?;
+
+        Ok(NewSchedule {
+            name: &self.name,
+            url: &self.url,
+            routing_key: &self.profile.routing_key,
+            tags: &self.profile.tags,
+            callback_url: self.profile.callback_url.as_deref(),
+            callback_secret: self.profile.callback_secret.as_deref(),
+            cron_expression: self.cron_expression.trim(),
+            enabled: self.enabled,
+            provider_id: self.profile.provider_id,
+            next_run_at,
+        })
+    }
+}
+
+/// POST /schedules
+/// Create a schedule, a job is created with the API key whenever the cron expression is due
+#[utoipa::path(
+    post,
+    path = "/schedules",
+    request_body = ScheduleInput,
+    responses(
+        (status = 200, description = "Schedule created", body = Schedule),
+        (status = 400, description = "Invalid input or cron expression", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or revoked API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the required scope", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+    ),
+    security(("api_key" = ["jobs:create"])),
+)]
+#[debug_handler]
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    Extension(api_key): Extension<ApiKey>,
+    WithRejection(Json(payload), _): WithRejection<Json<ScheduleInput>, ApiResponse<ErrorResponse>>,
+) -> Result<ApiResponse<Schedule>, ApiResponse<()>> {
+    let new_schedule = payload.to_new_schedule()?;
+    job_service::check_provider(&state, &payload.profile).await?;
+
+    let schedule = state
+        .schedule_repo
+        .create_schedule(new_schedule, api_key.id)
+        .await
+        .map_err(|e| {
+            error!("Failed to create schedule: {:?}", e);
+            internal_server_error("Failed to create schedule")
+        })?;
+
+    info!(
+        "Schedule {} created, next run at {}",
+        schedule.id, schedule.next_run_at
+    );
+
+    Ok(ok_response(schedule))
+}
@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use axum::{
+    debug_handler,
+    extract::{Path, State},
+};
+use axum_extra::extract::WithRejection;
+use serde::Serialize;
+use tracing::{error, info};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{api::api_response::*, state::AppState};
+
+#[derive(Serialize, ToSchema)]
+pub struct DeleteProviderResponse {
+    pub provider_id: Uuid,
+}
+
+/// DELETE /providers/{provider_id}
+/// Delete a provider registered by mistake, providers referenced by jobs or schedules are kept
+#[utoipa::path(
+    delete,
+    path = "/providers/{provider_id}",
+    params(("provider_id" = Uuid, Path, description = "Provider id")),
+    responses(
+        (status = 200, description = "Provider deleted", body = DeleteProviderResponse),
+        (status = 400, description = "Invalid provider_id or the provider has jobs or schedules", body = ErrorResponse),
+        (status = 404, description = "Provider not found", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or revoked API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the required scope", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+    ),
+    security(("api_key" = ["admin"])),
+)]
+#[debug_handler]
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    WithRejection(Path(provider_id), _): WithRejection<Path<String>, ApiResponse<ErrorResponse>>,
+) -> Result<ApiResponse<DeleteProviderResponse>, ApiResponse<()>> {
+    let provider_id = Uuid::parse_str(&provider_id)
+        .map_err(|_| bad_request("Invalid provider_id; must be a valid UUID"))?;
+
+    let provider = state
+        .provider_repo
+        .get_provider(provider_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to get provider from the database: {:?}", e);
+            internal_server_error("Failed to get provider from the database")
+        })?;
+    if provider.is_none() {
+        return Err(not_found("Provider not found"));
+    }
+
+    let deleted = state
+        .provider_repo
+        .delete_unreferenced_provider(provider_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to delete provider: {:?}", e);
+            internal_server_error("Failed to delete provider")
+        })?;
+    if !deleted {
+        return Err(bad_request(
+            "Provider has jobs or schedules, its history is kept",
+        ));
+    }
+
+    info!("Deleted provider: {}", provider_id);
+
+    Ok(ok_response(DeleteProviderResponse { provider_id }))
+}
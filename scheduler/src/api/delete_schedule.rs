@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use axum::{
+    debug_handler,
+    extract::{Extension, Path, State},
+};
+use axum_extra::extract::WithRejection;
+use serde::Serialize;
+use tracing::{error, info};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{api::api_response::*, api_key_repository::ApiKey, state::AppState};
+
+#[derive(Serialize, ToSchema)]
+pub struct DeleteScheduleResponse {
+    pub schedule_id: Uuid,
+}
+
+/// DELETE /schedules/{schedule_id}
+/// Delete the schedule and its history, the jobs it created are kept
+#[utoipa::path(
+    delete,
+    path = "/schedules/{schedule_id}",
+    params(("schedule_id" = Uuid, Path, description = "Schedule id")),
+    responses(
+        (status = 200, description = "Schedule deleted", body = DeleteScheduleResponse),
+        (status = 400, description = "Invalid schedule_id", body = ErrorResponse),
+        (status = 404, description = "Schedule not found or created by another API key", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or revoked API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the required scope", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+    ),
+    security(("api_key" = ["jobs:create"])),
+)]
+#[debug_handler]
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    Extension(api_key): Extension<ApiKey>,
+    WithRejection(Path(schedule_id), _): WithRejection<Path<String>, ApiResponse<ErrorResponse>>,
+) -> Result<ApiResponse<DeleteScheduleResponse>, ApiResponse<()>> {
+    let schedule_id = Uuid::parse_str(&schedule_id)
+        .map_err(|_| bad_request("Invalid schedule_id; must be a valid UUID"))?;
+
+    let deleted = state
+        .schedule_repo
+        .delete_schedule(schedule_id, api_key.owner_filter())
+        .await
+        .map_err(|e| {
+            error!("Failed to delete schedule: {:?}", e);
+            internal_server_error("Failed to delete schedule")
+        })?;
+
+    if !deleted {
+        return Err(not_found("Schedule not found"));
+    }
+
+    info!("Deleted schedule: {}", schedule_id);
+
+    Ok(ok_response(DeleteScheduleResponse { schedule_id }))
+}
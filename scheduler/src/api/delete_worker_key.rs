@@ -0,0 +1,55 @@
+use std::sync::Arc;
+
+use axum::{
+    debug_handler,
+    extract::{Path, State},
+};
+use axum_extra::extract::WithRejection;
+use serde::Serialize;
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::{api::api_response::*, state::AppState};
+
+#[derive(Serialize, ToSchema)]
+pub struct DeleteWorkerKeyResponse {
+    pub worker_name: String,
+}
+
+/// DELETE /worker/{worker_name}/key
+/// Revoke the worker key, e.g. when it leaked. Messages of the worker are no longer trusted.
+#[utoipa::path(
+    delete,
+    path = "/worker/{worker_name}/key",
+    params(("worker_name" = String, Path, description = "Worker name")),
+    responses(
+        (status = 200, description = "Key revoked", body = DeleteWorkerKeyResponse),
+        (status = 404, description = "Worker key not found", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or revoked API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the required scope", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+    ),
+    security(("api_key" = ["admin"])),
+)]
+#[debug_handler]
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    WithRejection(Path(worker_name), _): WithRejection<Path<String>, ApiResponse<ErrorResponse>>,
+) -> Result<ApiResponse<DeleteWorkerKeyResponse>, ApiResponse<()>> {
+    let deleted = state
+        .worker_key_repo
+        .delete_public_key(&worker_name)
+        .await
+        .map_err(|e| {
+            error!("Failed to delete worker key: {:?}", e);
+            internal_server_error("Failed to delete worker key")
+        })?;
+
+    if !deleted {
+        return Err(not_found("Worker key not found"));
+    }
+
+    info!("Revoked public key of worker: {}", worker_name);
+
+    Ok(ok_response(DeleteWorkerKeyResponse { worker_name }))
+}
@@ -2,6 +2,7 @@ use std::sync::Arc;
 
 use crate::{
     api::api_response::{bad_request, ApiResponse, ErrorResponse},
+    provider_repository::Provider,
     repository::job_repository::JobWithData,
     state::AppState,
 };
@@ -10,30 +11,80 @@ use axum::{
     extract::{Query, State},
 };
 use axum_extra::extract::WithRejection;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use tracing::{debug, error, info};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
 use super::api_response::*;
 
-#[derive(Deserialize)]
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct GetDataQuery {
-    job_id: String,
+    /// Data of a single job, either this or `provider_id` is required
+    job_id: Option<String>,
+    /// Data of the jobs of a registered provider, the newest first
+    provider_id: Option<Uuid>,
+    /// With `provider_id`, jobs created from then on
+    created_after: Option<DateTime<Utc>>,
+    /// With `provider_id`, jobs created before then, to page through older jobs
+    created_before: Option<DateTime<Utc>>,
+    /// With `provider_id`, maximum number of jobs
+    limit: Option<i64>,
 }
 
-#[derive(Serialize)]
-pub struct GetDataResponse(pub JobWithData);
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum GetDataResponse {
+    Job(JobWithData),
+    Provider(ProviderData),
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ProviderData {
+    pub provider: Provider,
+    pub jobs: Vec<JobWithData>,
+}
 
 /// GET /data?job_id={job_id}
-/// Get the data for a job
+/// GET /data?provider_id={provider_id}&created_after={created_after}&created_before={created_before}&limit={limit}
+/// Get the data for a job, or for the jobs of a provider whatever endpoints they targeted
+#[utoipa::path(
+    get,
+    path = "/data",
+    params(GetDataQuery),
+    responses(
+        (status = 200, description = "Job with the measurements of every worker, or the provider with its jobs", body = GetDataResponse),
+        (status = 400, description = "Invalid job_id, provider_id or limit", body = ErrorResponse),
+        (status = 404, description = "Job or provider not found", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or revoked API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the required scope", body = ErrorResponse),
+    ),
+    security(("api_key" = ["data:read"])),
+)]
 #[debug_handler]
 pub async fn handle(
     WithRejection(Query(params), _): WithRejection<Query<GetDataQuery>, ApiResponse<ErrorResponse>>,
     State(state): State<Arc<AppState>>,
+) -> Result<ApiResponse<GetDataResponse>, ApiResponse<()>> {
+    match (&params.job_id, params.provider_id) {
+        (Some(job_id), None) => get_job_data(&state, job_id).await,
+        (None, Some(provider_id)) => get_provider_data(&state, provider_id, &params).await,
+        _ => Err(bad_request("Either job_id or provider_id is required")),
+    }
+}
+
+async fn get_job_data(
+    state: &AppState,
+    job_id: &str,
 ) -> Result<ApiResponse<GetDataResponse>, ApiResponse<()>> {
     // Validate the job_id
-    let job_id = Uuid::parse_str(&params.job_id)
-        .map_err(|_| bad_request("Invalid job_id; must be a valid UUID"))?;
+    let job_id =
+        Uuid::parse_str(job_id).map_err(|_| bad_request("Invalid job_id; must be a valid UUID"))?;
 
     info!("Getting data for job_id: {}", job_id);
 
@@ -51,5 +102,50 @@ pub async fn handle(
 
     debug!("Job data found for job_id: {} {:?}", job_id, job);
 
-    Ok(ok_response(GetDataResponse(job)))
+    Ok(ok_response(GetDataResponse::Job(job)))
+}
+
+async fn get_provider_data(
+    state: &AppState,
+    provider_id: Uuid,
+    params: &GetDataQuery,
+) -> Result<ApiResponse<GetDataResponse>, ApiResponse<()>> {
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(bad_request(format!(
+            "Limit must be between 1 and {}",
+            MAX_LIMIT
+        )));
+    }
+
+    info!("Getting data for provider_id: {}", provider_id);
+
+    let provider = state
+        .provider_repo
+        .get_provider(provider_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to get provider from the database: {:?}", e);
+            internal_server_error("Failed to get provider from the database")
+        })?
+        .ok_or_else(|| not_found("Provider not found"))?;
+
+    let jobs = state
+        .job_repo
+        .get_provider_jobs_with_data(
+            provider_id,
+            params.created_after,
+            params.created_before,
+            limit,
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to get data from the database: {:?}", e);
+            internal_server_error("Failed to get data from the database")
+        })?;
+
+    Ok(ok_response(GetDataResponse::Provider(ProviderData {
+        provider,
+        jobs,
+    })))
 }
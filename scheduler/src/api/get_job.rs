@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use axum::{
+    debug_handler,
+    extract::{Path, State},
+};
+use axum_extra::extract::WithRejection;
+use serde::Serialize;
+use tracing::{debug, error};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{api::api_response::*, job_repository::JobProgress, state::AppState};
+
+#[derive(Serialize, ToSchema)]
+pub struct GetJobResponse(pub JobProgress);
+
+/// GET /job/{job_id}
+/// Get the job status and the progress of its sub jobs, meant for polling until the job is finished
+#[utoipa::path(
+    get,
+    path = "/job/{job_id}",
+    params(("job_id" = Uuid, Path, description = "Job id")),
+    responses(
+        (status = 200, description = "Job status and the progress of its sub jobs", body = GetJobResponse),
+        (status = 400, description = "Invalid job_id", body = ErrorResponse),
+        (status = 404, description = "Job not found", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or revoked API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the required scope", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+    ),
+    security(("api_key" = ["jobs:read"])),
+)]
+#[debug_handler]
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    WithRejection(Path(job_id), _): WithRejection<Path<String>, ApiResponse<ErrorResponse>>,
+) -> Result<ApiResponse<GetJobResponse>, ApiResponse<()>> {
+    let job_id = Uuid::parse_str(&job_id)
+        .map_err(|_| bad_request("Invalid job_id; must be a valid UUID"))?;
+
+    let job = state
+        .job_repo
+        .get_job_progress(job_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => not_found("Job not found"),
+            _ => {
+                error!("Failed to get job from the database: {:?}", e);
+                internal_server_error("Failed to get job from the database")
+            }
+        })?;
+
+    debug!("Job found for job_id: {} {:?}", job_id, job);
+
+    Ok(ok_response(GetJobResponse(job)))
+}
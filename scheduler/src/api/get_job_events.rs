@@ -0,0 +1,120 @@
+use std::{convert::Infallible, sync::Arc};
+
+use axum::{
+    debug_handler,
+    extract::{Path, State},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use axum_extra::extract::WithRejection;
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+use tracing::{debug, error, warn};
+use uuid::Uuid;
+
+use crate::{
+    api::api_response::*,
+    events::JobEvent,
+    job_repository::{JobProgress, JobStatus},
+    state::AppState,
+};
+
+// Events waiting for a slow client, the stream lags behind the broadcast after that
+const CLIENT_BUFFER: usize = 64;
+
+/// GET /job/{job_id}/events
+/// Stream the live events of the job as server-sent events, until the job is finished
+#[utoipa::path(
+    get,
+    path = "/job/{job_id}/events",
+    params(("job_id" = Uuid, Path, description = "Job id")),
+    responses(
+        (status = 200, description = "Stream of `snapshot`, `sub_job`, `progress`, `result` and `job` events, each with a JSON object in the data. The snapshot is the job as returned by GET /job/{job_id}, the stream ends after the `job` event", content_type = "text/event-stream", body = String),
+        (status = 400, description = "Invalid job_id", body = ErrorResponse),
+        (status = 404, description = "Job not found", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or revoked API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the required scope", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+    ),
+    security(("api_key" = ["jobs:read"])),
+)]
+#[debug_handler]
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    WithRejection(Path(job_id), _): WithRejection<Path<String>, ApiResponse<ErrorResponse>>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiResponse<()>> {
+    let job_id = Uuid::parse_str(&job_id)
+        .map_err(|_| bad_request("Invalid job_id; must be a valid UUID"))?;
+
+    // Subscribe before taking the snapshot, so no event between them is missed
+    let mut events = state.job_events.subscribe();
+
+    let job = state
+        .job_repo
+        .get_job_progress(job_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => not_found("Job not found"),
+            _ => {
+                error!("Failed to get job from the database: {:?}", e);
+                internal_server_error("Failed to get job from the database")
+            }
+        })?;
+
+    let (sender, receiver) = mpsc::channel(CLIENT_BUFFER);
+    tokio::spawn(async move {
+        let finished = matches!(job.status, JobStatus::Completed | JobStatus::Failed);
+        if sender.send(snapshot_event(&job)).await.is_err() || finished {
+            return;
+        }
+
+        loop {
+            let event = tokio::select! {
+                event = events.recv() => event,
+                // Stop listening as soon as the client disconnects
+                _ = sender.closed() => break,
+            };
+
+            match event {
+                Ok(event) if event.job_id() == job_id => {
+                    let last = matches!(event, JobEvent::Job { .. });
+                    if sender.send(sse_event(&event)).await.is_err() || last {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                // Missed events are lost, the client can refresh its state with GET /job/{job_id}
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!(
+                        "Events of job {} lagged, skipped {} events",
+                        job_id, skipped
+                    );
+                    let lagged = Event::default()
+                        .event("lagged")
+                        .data(format!(r#"{{"skipped":{}}}"#, skipped));
+                    if sender.send(Ok(lagged)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+
+        debug!("Events stream of job {} closed", job_id);
+    });
+
+    Ok(Sse::new(ReceiverStream::new(receiver)).keep_alive(KeepAlive::default()))
+}
+
+fn snapshot_event(job: &JobProgress) -> Result<Event, Infallible> {
+    Ok(Event::default()
+        .event("snapshot")
+        .json_data(job)
+        .unwrap_or_else(|e| Event::default().event("error").data(e.to_string())))
+}
+
+fn sse_event(event: &JobEvent) -> Result<Event, Infallible> {
+    Ok(Event::default()
+        .event(event.name())
+        .json_data(event)
+        .unwrap_or_else(|e| Event::default().event("error").data(e.to_string())))
+}
@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use axum::{
+    debug_handler,
+    extract::{Path, State},
+};
+use axum_extra::extract::WithRejection;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{api::api_response::*, provider_repository::Provider, state::AppState};
+
+/// GET /providers/{provider_id}
+/// Get the provider
+#[utoipa::path(
+    get,
+    path = "/providers/{provider_id}",
+    params(("provider_id" = Uuid, Path, description = "Provider id")),
+    responses(
+        (status = 200, description = "Provider", body = Provider),
+        (status = 400, description = "Invalid provider_id", body = ErrorResponse),
+        (status = 404, description = "Provider not found", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or revoked API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the required scope", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+    ),
+    security(("api_key" = ["jobs:read"])),
+)]
+#[debug_handler]
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    WithRejection(Path(provider_id), _): WithRejection<Path<String>, ApiResponse<ErrorResponse>>,
+) -> Result<ApiResponse<Provider>, ApiResponse<()>> {
+    let provider_id = Uuid::parse_str(&provider_id)
+        .map_err(|_| bad_request("Invalid provider_id; must be a valid UUID"))?;
+
+    let provider = state
+        .provider_repo
+        .get_provider(provider_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to get provider from the database: {:?}", e);
+            internal_server_error("Failed to get provider from the database")
+        })?
+        .ok_or_else(|| not_found("Provider not found"))?;
+
+    Ok(ok_response(provider))
+}
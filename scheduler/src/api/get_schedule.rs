@@ -0,0 +1,52 @@
+use std::sync::Arc;
+
+use axum::{
+    debug_handler,
+    extract::{Extension, Path, State},
+};
+use axum_extra::extract::WithRejection;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+    api::api_response::*, api_key_repository::ApiKey, schedule_repository::Schedule,
+    state::AppState,
+};
+
+/// GET /schedules/{schedule_id}
+/// Get the schedule with its next run
+#[utoipa::path(
+    get,
+    path = "/schedules/{schedule_id}",
+    params(("schedule_id" = Uuid, Path, description = "Schedule id")),
+    responses(
+        (status = 200, description = "Schedule", body = Schedule),
+        (status = 400, description = "Invalid schedule_id", body = ErrorResponse),
+        (status = 404, description = "Schedule not found or created by another API key", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or revoked API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the required scope", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+    ),
+    security(("api_key" = ["jobs:read"])),
+)]
+#[debug_handler]
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    Extension(api_key): Extension<ApiKey>,
+    WithRejection(Path(schedule_id), _): WithRejection<Path<String>, ApiResponse<ErrorResponse>>,
+) -> Result<ApiResponse<Schedule>, ApiResponse<()>> {
+    let schedule_id = Uuid::parse_str(&schedule_id)
+        .map_err(|_| bad_request("Invalid schedule_id; must be a valid UUID"))?;
+
+    let schedule = state
+        .schedule_repo
+        .get_schedule(schedule_id, api_key.owner_filter())
+        .await
+        .map_err(|e| {
+            error!("Failed to get schedule from the database: {:?}", e);
+            internal_server_error("Failed to get schedule from the database")
+        })?
+        .ok_or_else(|| not_found("Schedule not found"))?;
+
+    Ok(ok_response(schedule))
+}
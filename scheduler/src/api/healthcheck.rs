@@ -1,18 +1,40 @@
-use axum::debug_handler;
+use std::sync::Arc;
+
+use axum::{debug_handler, extract::State};
+use rabbitmq::ConnectionState;
 use serde::Serialize;
+use utoipa::ToSchema;
 
-use crate::api::api_response::*;
+use crate::{api::api_response::*, state::AppState};
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct HealthcheckResponse {
     pub status: String,
+    pub job_queue: String,
 }
 
 /// GET /healthcheck
-/// Return simple healthcheck response
+/// Return simple healthcheck response, degraded while the job queue is reconnecting
+#[utoipa::path(
+    get,
+    path = "/healthcheck",
+    responses(
+        (status = 200, description = "`ok`, or `degraded` while the job queue is reconnecting", body = HealthcheckResponse),
+    ),
+)]
 #[debug_handler]
-pub async fn handle() -> Result<ApiResponse<HealthcheckResponse>, ApiResponse<()>> {
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+) -> Result<ApiResponse<HealthcheckResponse>, ApiResponse<()>> {
+    let job_queue = state.job_queue.connection_state();
+
+    let status = match job_queue {
+        ConnectionState::Connected => "ok",
+        _ => "degraded",
+    };
+
     Ok(ok_response(HealthcheckResponse {
-        status: "ok".to_string(),
+        status: status.to_string(),
+        job_queue: job_queue.as_str().to_string(),
     }))
 }
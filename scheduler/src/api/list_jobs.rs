@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use axum::{
+    debug_handler,
+    extract::{Query, State},
+};
+use axum_extra::extract::WithRejection;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::{
+    api::api_response::*,
+    job_repository::{JobCursor, JobFilter, JobStatus, JobSummary},
+    state::AppState,
+};
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListJobsQuery {
+    status: Option<JobStatus>,
+    url: Option<String>,
+    host: Option<String>,
+    routing_key: Option<String>,
+    created_after: Option<DateTime<Utc>>,
+    created_before: Option<DateTime<Utc>>,
+    /// Comma separated, jobs having all of the tags are listed
+    tags: Option<String>,
+    /// Jobs created by one POST /jobs/batch request
+    batch_id: Option<Uuid>,
+    /// Jobs of a registered provider, whatever URL they targeted
+    provider_id: Option<Uuid>,
+    /// `next_cursor` of the previous page
+    cursor: Option<String>,
+    /// Order by creation time, newest first by default
+    #[serde(default)]
+    order: SortOrder,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ListJobsResponse {
+    pub jobs: Vec<JobSummary>,
+    /// Set when there are more jobs, pass it as `cursor` to get the next page
+    pub next_cursor: Option<String>,
+}
+
+/// GET /jobs?status={status}&host={host}&tags={tag,tag}&batch_id={batch_id}&provider_id={provider_id}&cursor={cursor}&order={asc|desc}&limit={limit}
+/// List the jobs matching the filters with their sub job counts and median download speed
+#[utoipa::path(
+    get,
+    path = "/jobs",
+    params(ListJobsQuery),
+    responses(
+        (status = 200, description = "Page of the jobs matching the filters", body = ListJobsResponse),
+        (status = 400, description = "Invalid filter, cursor or limit", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or revoked API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the required scope", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+    ),
+    security(("api_key" = ["jobs:read"])),
+)]
+#[debug_handler]
+pub async fn handle(
+    WithRejection(Query(params), _): WithRejection<
+        Query<ListJobsQuery>,
+        ApiResponse<ErrorResponse>,
+    >,
+    State(state): State<Arc<AppState>>,
+) -> Result<ApiResponse<ListJobsResponse>, ApiResponse<()>> {
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(bad_request(format!(
+            "Limit must be between 1 and {}",
+            MAX_LIMIT
+        )));
+    }
+    let cursor = params
+        .cursor
+        .as_deref()
+        .map(decode_cursor)
+        .transpose()
+        .map_err(|_| bad_request("Invalid cursor"))?;
+
+    let filter = JobFilter {
+        status: params.status,
+        url: params.url,
+        host: params.host,
+        routing_key: params.routing_key,
+        created_after: params.created_after,
+        created_before: params.created_before,
+        tags: params.tags.map(|tags| {
+            tags.split(',')
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .map(str::to_string)
+                .collect()
+        }),
+        batch_id: params.batch_id,
+        provider_id: params.provider_id,
+    };
+
+    // One more job tells whether there is a next page
+    let mut jobs = state
+        .job_repo
+        .list_jobs(&filter, cursor, params.order == SortOrder::Asc, limit + 1)
+        .await
+        .map_err(|e| {
+            error!("Failed to list jobs: {:?}", e);
+            internal_server_error("Failed to list jobs")
+        })?;
+
+    let next_cursor = if jobs.len() as i64 > limit {
+        jobs.truncate(limit as usize);
+        jobs.last().map(|job| {
+            encode_cursor(&JobCursor {
+                created_at: job.created_at,
+                id: job.id,
+            })
+        })
+    } else {
+        None
+    };
+
+    Ok(ok_response(ListJobsResponse { jobs, next_cursor }))
+}
+
+/// Opaque to the clients, base64 of `<created_at>/<id>`
+fn encode_cursor(cursor: &JobCursor) -> String {
+    let created_at = cursor
+        .created_at
+        .to_rfc3339_opts(SecondsFormat::Micros, true);
+    URL_SAFE_NO_PAD.encode(format!("{}/{}", created_at, cursor.id))
+}
+
+fn decode_cursor(cursor: &str) -> Result<JobCursor, ()> {
+    let decoded = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| ())?;
+    let decoded = String::from_utf8(decoded).map_err(|_| ())?;
+    let (created_at, id) = decoded.split_once('/').ok_or(())?;
+
+    Ok(JobCursor {
+        created_at: DateTime::parse_from_rfc3339(created_at)
+            .map_err(|_| ())?
+            .with_timezone(&Utc),
+        id: Uuid::parse_str(id).map_err(|_| ())?,
+    })
+}
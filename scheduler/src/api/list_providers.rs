@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use axum::{
+    debug_handler,
+    extract::{Query, State},
+};
+use axum_extra::extract::WithRejection;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    api::api_response::*,
+    provider_repository::{Provider, ProviderFilter},
+    state::AppState,
+};
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListProvidersQuery {
+    organization: Option<String>,
+    region: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ListProvidersResponse {
+    pub providers: Vec<Provider>,
+}
+
+/// GET /providers?organization={organization}&region={region}
+/// List the registered providers, ordered by miner ID
+#[utoipa::path(
+    get,
+    path = "/providers",
+    params(ListProvidersQuery),
+    responses(
+        (status = 200, description = "Providers matching the filters", body = ListProvidersResponse),
+        (status = 401, description = "Missing, invalid or revoked API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the required scope", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+    ),
+    security(("api_key" = ["jobs:read"])),
+)]
+#[debug_handler]
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(params), _): WithRejection<
+        Query<ListProvidersQuery>,
+        ApiResponse<ErrorResponse>,
+    >,
+) -> Result<ApiResponse<ListProvidersResponse>, ApiResponse<()>> {
+    let filter = ProviderFilter {
+        organization: params.organization,
+        region: params.region,
+    };
+
+    let providers = state
+        .provider_repo
+        .list_providers(&filter)
+        .await
+        .map_err(|e| {
+            error!("Failed to list providers: {:?}", e);
+            internal_server_error("Failed to list providers")
+        })?;
+
+    Ok(ok_response(ListProvidersResponse { providers }))
+}
@@ -0,0 +1,93 @@
+use std::sync::Arc;
+
+use axum::{
+    debug_handler,
+    extract::{Extension, Path, Query, State},
+};
+use axum_extra::extract::WithRejection;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::{
+    api::api_response::*, api_key_repository::ApiKey, schedule_repository::ScheduleRun,
+    state::AppState,
+};
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListScheduleRunsQuery {
+    limit: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ListScheduleRunsResponse {
+    pub runs: Vec<ScheduleRun>,
+}
+
+/// GET /schedules/{schedule_id}/runs?limit={limit}
+/// History of the schedule, the latest runs first with the jobs they created or why they failed
+#[utoipa::path(
+    get,
+    path = "/schedules/{schedule_id}/runs",
+    params(
+        ("schedule_id" = Uuid, Path, description = "Schedule id"),
+        ListScheduleRunsQuery,
+    ),
+    responses(
+        (status = 200, description = "Latest runs of the schedule", body = ListScheduleRunsResponse),
+        (status = 400, description = "Invalid schedule_id or limit", body = ErrorResponse),
+        (status = 404, description = "Schedule not found or created by another API key", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or revoked API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the required scope", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+    ),
+    security(("api_key" = ["jobs:read"])),
+)]
+#[debug_handler]
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    Extension(api_key): Extension<ApiKey>,
+    WithRejection(Path(schedule_id), _): WithRejection<Path<String>, ApiResponse<ErrorResponse>>,
+    WithRejection(Query(params), _): WithRejection<
+        Query<ListScheduleRunsQuery>,
+        ApiResponse<ErrorResponse>,
+    >,
+) -> Result<ApiResponse<ListScheduleRunsResponse>, ApiResponse<()>> {
+    let schedule_id = Uuid::parse_str(&schedule_id)
+        .map_err(|_| bad_request("Invalid schedule_id; must be a valid UUID"))?;
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(bad_request(format!(
+            "Limit must be between 1 and {}",
+            MAX_LIMIT
+        )));
+    }
+
+    let schedule = state
+        .schedule_repo
+        .get_schedule(schedule_id, api_key.owner_filter())
+        .await
+        .map_err(|e| {
+            error!("Failed to get schedule from the database: {:?}", e);
+            internal_server_error("Failed to get schedule from the database")
+        })?;
+    if schedule.is_none() {
+        return Err(not_found("Schedule not found"));
+    }
+
+    let runs = state
+        .schedule_repo
+        .list_runs(schedule_id, limit)
+        .await
+        .map_err(|e| {
+            error!("Failed to list schedule runs: {:?}", e);
+            internal_server_error("Failed to list schedule runs")
+        })?;
+
+    Ok(ok_response(ListScheduleRunsResponse { runs }))
+}
@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use axum::{
+    debug_handler,
+    extract::{Extension, State},
+};
+use serde::Serialize;
+use tracing::error;
+use utoipa::ToSchema;
+
+use crate::{
+    api::api_response::*, api_key_repository::ApiKey, schedule_repository::Schedule,
+    state::AppState,
+};
+
+#[derive(Serialize, ToSchema)]
+pub struct ListSchedulesResponse {
+    pub schedules: Vec<Schedule>,
+}
+
+/// GET /schedules
+/// List the schedules created by the API key, all of them for admin keys, the oldest first
+#[utoipa::path(
+    get,
+    path = "/schedules",
+    responses(
+        (status = 200, description = "All schedules", body = ListSchedulesResponse),
+        (status = 401, description = "Missing, invalid or revoked API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the required scope", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+    ),
+    security(("api_key" = ["jobs:read"])),
+)]
+#[debug_handler]
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    Extension(api_key): Extension<ApiKey>,
+) -> Result<ApiResponse<ListSchedulesResponse>, ApiResponse<()>> {
+    let schedules = state
+        .schedule_repo
+        .list_schedules(api_key.owner_filter())
+        .await
+        .map_err(|e| {
+            error!("Failed to list schedules: {:?}", e);
+            internal_server_error("Failed to list schedules")
+        })?;
+
+    Ok(ok_response(ListSchedulesResponse { schedules }))
+}
@@ -1,4 +1,23 @@
 pub mod api_response;
+pub mod create_batch;
 pub mod create_job;
+pub mod create_provider;
+pub mod create_schedule;
+pub mod delete_provider;
+pub mod delete_schedule;
+pub mod delete_worker_key;
 pub mod get_data;
+pub mod get_job;
+pub mod get_job_events;
+pub mod get_provider;
+pub mod get_schedule;
 pub mod healthcheck;
+pub mod list_jobs;
+pub mod list_providers;
+pub mod list_schedule_runs;
+pub mod list_schedules;
+pub mod openapi;
+pub mod update_provider;
+pub mod update_schedule;
+pub mod update_worker_key;
+pub mod update_worker_topics;
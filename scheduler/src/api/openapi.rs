@@ -0,0 +1,125 @@
+use utoipa::{
+    openapi::{
+        security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+        Server,
+    },
+    Modify, OpenApi,
+};
+
+use crate::{
+    api::{
+        api_response::ErrorResponse, create_batch, create_job, create_provider, create_schedule,
+        delete_provider, delete_schedule, delete_worker_key, get_data, get_job, get_job_events,
+        get_provider, get_schedule, healthcheck, list_jobs, list_providers, list_schedule_runs,
+        list_schedules, update_provider, update_schedule, update_worker_key, update_worker_topics,
+    },
+    data_repository::{BmsData, SignatureStatus},
+    job_repository::{JobDetails, JobProgress, JobStatus, JobSummary, JobWithData},
+    job_service::JobProfile,
+    provider_repository::Provider,
+    schedule_repository::{Schedule, ScheduleRun, ScheduleRunStatus},
+    sub_job_repository::{SubJobProgress, SubJobStatus},
+};
+
+/// Prefix the API is mounted under, bumped on breaking changes
+pub const API_PREFIX: &str = "/v1";
+
+/// OpenAPI document of the scheduler API, served at `/openapi.json`
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Bandwidth Measurement System scheduler API"),
+    paths(
+        healthcheck::handle,
+        create_job::handle,
+        create_batch::handle,
+        get_job::handle,
+        get_job_events::handle,
+        list_jobs::handle,
+        get_data::handle,
+        create_provider::handle,
+        list_providers::handle,
+        get_provider::handle,
+        update_provider::handle,
+        delete_provider::handle,
+        create_schedule::handle,
+        list_schedules::handle,
+        get_schedule::handle,
+        update_schedule::handle,
+        delete_schedule::handle,
+        list_schedule_runs::handle,
+        update_worker_topics::handle,
+        update_worker_key::handle,
+        delete_worker_key::handle,
+    ),
+    components(schemas(
+        ErrorResponse,
+        healthcheck::HealthcheckResponse,
+        create_job::JobInput,
+        create_job::JobResponse,
+        create_batch::BatchInput,
+        create_batch::BatchTarget,
+        create_batch::BatchResponse,
+        create_batch::BatchJob,
+        JobProfile,
+        get_job::GetJobResponse,
+        list_jobs::ListJobsResponse,
+        list_jobs::SortOrder,
+        get_data::GetDataResponse,
+        get_data::ProviderData,
+        create_provider::ProviderInput,
+        list_providers::ListProvidersResponse,
+        delete_provider::DeleteProviderResponse,
+        Provider,
+        create_schedule::ScheduleInput,
+        list_schedules::ListSchedulesResponse,
+        list_schedule_runs::ListScheduleRunsResponse,
+        delete_schedule::DeleteScheduleResponse,
+        Schedule,
+        ScheduleRun,
+        ScheduleRunStatus,
+        update_worker_topics::UpdateWorkerTopicsInput,
+        update_worker_topics::UpdateWorkerTopicsResponse,
+        update_worker_key::UpdateWorkerKeyInput,
+        update_worker_key::UpdateWorkerKeyResponse,
+        delete_worker_key::DeleteWorkerKeyResponse,
+        JobStatus,
+        JobDetails,
+        JobProgress,
+        JobSummary,
+        JobWithData,
+        SubJobStatus,
+        SubJobProgress,
+        BmsData,
+        SignatureStatus,
+    )),
+    modifiers(&ApiPrefix, &ApiKeySecurity)
+)]
+pub struct ApiDoc;
+
+/// Paths are relative to the prefix
+struct ApiPrefix;
+
+impl Modify for ApiPrefix {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi.servers = Some(vec![Server::new(API_PREFIX)]);
+    }
+}
+
+/// API keys are sent as bearer tokens, `X-Api-Key` is accepted as well
+struct ApiKeySecurity;
+
+impl Modify for ApiKeySecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "api_key",
+                SecurityScheme::Http(
+                    HttpBuilder::new()
+                        .scheme(HttpAuthScheme::Bearer)
+                        .description(Some("API key created with `scheduler api-key create`"))
+                        .build(),
+                ),
+            );
+        }
+    }
+}
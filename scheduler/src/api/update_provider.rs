@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use axum::{
+    debug_handler,
+    extract::{Json, Path, State},
+};
+use axum_extra::extract::WithRejection;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    api::{
+        api_response::*,
+        create_provider::{provider_save_error, ProviderInput},
+    },
+    provider_repository::Provider,
+    state::AppState,
+};
+
+/// PUT /providers/{provider_id}
+/// Replace the provider, e.g. when its endpoints move. Its jobs keep referencing it.
+#[utoipa::path(
+    put,
+    path = "/providers/{provider_id}",
+    params(("provider_id" = Uuid, Path, description = "Provider id")),
+    request_body = ProviderInput,
+    responses(
+        (status = 200, description = "Provider updated", body = Provider),
+        (status = 400, description = "Invalid input or the miner ID is already registered", body = ErrorResponse),
+        (status = 404, description = "Provider not found", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or revoked API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the required scope", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+    ),
+    security(("api_key" = ["admin"])),
+)]
+#[debug_handler]
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    WithRejection(Path(provider_id), _): WithRejection<Path<String>, ApiResponse<ErrorResponse>>,
+    WithRejection(Json(payload), _): WithRejection<Json<ProviderInput>, ApiResponse<ErrorResponse>>,
+) -> Result<ApiResponse<Provider>, ApiResponse<()>> {
+    let provider_id = Uuid::parse_str(&provider_id)
+        .map_err(|_| bad_request("Invalid provider_id; must be a valid UUID"))?;
+    let new_provider = payload.to_new_provider()?;
+
+    let provider = state
+        .provider_repo
+        .update_provider(provider_id, new_provider)
+        .await
+        .map_err(|e| provider_save_error(e, &payload.miner_id))?
+        .ok_or_else(|| not_found("Provider not found"))?;
+
+    info!("Provider {} updated: {}", provider.miner_id, provider.id);
+
+    Ok(ok_response(provider))
+}
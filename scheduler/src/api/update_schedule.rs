@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use axum::{
+    debug_handler,
+    extract::{Extension, Json, Path, State},
+};
+use axum_extra::extract::WithRejection;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::{
+    api::{api_response::*, create_schedule::ScheduleInput},
+    api_key_repository::ApiKey,
+    job_service,
+    schedule_repository::Schedule,
+    state::AppState,
+};
+
+/// PUT /schedules/{schedule_id}
+/// Replace the schedule, the next run is computed from the new cron expression
+#[utoipa::path(
+    put,
+    path = "/schedules/{schedule_id}",
+    params(("schedule_id" = Uuid, Path, description = "Schedule id")),
+    request_body = ScheduleInput,
+    responses(
+        (status = 200, description = "Schedule updated", body = Schedule),
+        (status = 400, description = "Invalid input or cron expression", body = ErrorResponse),
+        (status = 404, description = "Schedule not found or created by another API key", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or revoked API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the required scope", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+    ),
+    security(("api_key" = ["jobs:create"])),
+)]
+#[debug_handler]
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    Extension(api_key): Extension<ApiKey>,
+    WithRejection(Path(schedule_id), _): WithRejection<Path<String>, ApiResponse<ErrorResponse>>,
+    WithRejection(Json(payload), _): WithRejection<Json<ScheduleInput>, ApiResponse<ErrorResponse>>,
+) -> Result<ApiResponse<Schedule>, ApiResponse<()>> {
+    let schedule_id = Uuid::parse_str(&schedule_id)
+        .map_err(|_| bad_request("Invalid schedule_id; must be a valid UUID"))?;
+    let new_schedule = payload.to_new_schedule()?;
+    job_service::check_provider(&state, &payload.profile).await?;
+
+    let schedule = state
+        .schedule_repo
+        .update_schedule(schedule_id, api_key.owner_filter(), new_schedule)
+        .await
+        .map_err(|e| {
+            error!("Failed to update schedule: {:?}", e);
+            internal_server_error("Failed to update schedule")
+        })?
+        .ok_or_else(|| not_found("Schedule not found"))?;
+
+    info!(
+        "Schedule {} updated, next run at {}",
+        schedule.id, schedule.next_run_at
+    );
+
+    Ok(ok_response(schedule))
+}
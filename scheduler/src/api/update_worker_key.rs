@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use axum::{
+    debug_handler,
+    extract::{Json, Path, State},
+};
+use axum_extra::extract::WithRejection;
+use rabbitmq::signing;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::{api::api_response::*, state::AppState};
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateWorkerKeyInput {
+    /// Base64 Ed25519 public key, logged by the worker at startup
+    pub public_key: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UpdateWorkerKeyResponse {
+    pub worker_name: String,
+    pub public_key: String,
+}
+
+/// PUT /worker/{worker_name}/key
+/// Register the public key the worker signs its results and statuses with.
+/// Replaces the previous key, messages signed with it are no longer trusted.
+#[utoipa::path(
+    put,
+    path = "/worker/{worker_name}/key",
+    params(("worker_name" = String, Path, description = "Worker name")),
+    request_body = UpdateWorkerKeyInput,
+    responses(
+        (status = 200, description = "Key registered", body = UpdateWorkerKeyResponse),
+        (status = 400, description = "Invalid public key", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or revoked API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the required scope", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+    ),
+    security(("api_key" = ["admin"])),
+)]
+#[debug_handler]
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    WithRejection(Path(worker_name), _): WithRejection<Path<String>, ApiResponse<ErrorResponse>>,
+    WithRejection(Json(payload), _): WithRejection<
+        Json<UpdateWorkerKeyInput>,
+        ApiResponse<ErrorResponse>,
+    >,
+) -> Result<ApiResponse<UpdateWorkerKeyResponse>, ApiResponse<()>> {
+    // Validation
+    let public_key = signing::decode_public_key(&payload.public_key)
+        .map(|key| signing::encode_public_key(&key))
+        .map_err(bad_request)?;
+
+    state
+        .worker_key_repo
+        .upsert_public_key(&worker_name, &public_key)
+        .await
+        .map_err(|e| {
+            error!("Failed to save worker key: {:?}", e);
+            internal_server_error("Failed to save worker key")
+        })?;
+
+    info!("Registered public key for worker: {}", worker_name);
+
+    Ok(ok_response(UpdateWorkerKeyResponse {
+        worker_name,
+        public_key,
+    }))
+}
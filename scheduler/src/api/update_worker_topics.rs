@@ -0,0 +1,135 @@
+use std::sync::Arc;
+
+use axum::{
+    debug_handler,
+    extract::{Json, Path, State},
+};
+use axum_extra::extract::WithRejection;
+use rabbitmq::{ControlMessage, Message, PublishError, WorkerStatus};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info};
+use utoipa::ToSchema;
+
+use crate::{api::api_response::*, state::AppState};
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateWorkerTopicsInput {
+    pub topics: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UpdateWorkerTopicsResponse {
+    pub worker_name: String,
+    pub topics: Vec<String>,
+}
+
+/// PUT /worker/{worker_name}/topics
+/// Ask the worker to rebind its job queue to the given topics.
+/// Topics stored for the worker are updated once the worker reports back with its new topics.
+#[utoipa::path(
+    put,
+    path = "/worker/{worker_name}/topics",
+    params(("worker_name" = String, Path, description = "Worker name")),
+    request_body = UpdateWorkerTopicsInput,
+    responses(
+        (status = 200, description = "Control message sent to the worker", body = UpdateWorkerTopicsResponse),
+        (status = 400, description = "Invalid topics or the worker is offline", body = ErrorResponse),
+        (status = 404, description = "Worker not found", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or revoked API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the required scope", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+    ),
+    security(("api_key" = ["admin"])),
+)]
+#[debug_handler]
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    WithRejection(Path(worker_name), _): WithRejection<Path<String>, ApiResponse<ErrorResponse>>,
+    WithRejection(Json(payload), _): WithRejection<
+        Json<UpdateWorkerTopicsInput>,
+        ApiResponse<ErrorResponse>,
+    >,
+) -> Result<ApiResponse<UpdateWorkerTopicsResponse>, ApiResponse<()>> {
+    // Validation
+    if payload.topics.iter().any(|topic| topic.trim().is_empty()) {
+        return Err(bad_request("Topics cannot be empty"));
+    }
+    if let Some(topic) = payload
+        .topics
+        .iter()
+        .find(|topic| !is_valid_topic(topic.trim()))
+    {
+        return Err(bad_request(format!(
+            "Invalid topic: {}, use letters, digits, - and _",
+            topic
+        )));
+    }
+
+    let status = state
+        .worker_repo
+        .get_worker_status(&worker_name)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => not_found("Worker not found"),
+            _ => {
+                error!("Failed to get worker from the database: {:?}", e);
+                internal_server_error("Failed to get worker from the database")
+            }
+        })?;
+
+    if status != WorkerStatus::Online.as_str() {
+        return Err(bad_request("Worker is not online"));
+    }
+
+    // Workers bind their queue to their own name, so the message reaches only this worker
+    let control_message = Message::WorkerControl {
+        worker_name: worker_name.clone(),
+        command: ControlMessage::SetTopics(payload.topics.clone()),
+    };
+
+    debug!("Publishing control message: {:?}", control_message);
+
+    state
+        .job_queue
+        .publish(&control_message, &worker_name)
+        .await
+        .map_err(|e| match e.downcast_ref::<PublishError>() {
+            Some(PublishError::Unroutable { .. }) => {
+                not_found("Worker queue not bound to the job exchange")
+            }
+            _ => internal_server_error("Failed to publish control message"),
+        })?;
+
+    info!(
+        "Requested topics change for worker: {}, topics: {:?}",
+        worker_name, payload.topics
+    );
+
+    Ok(ok_response(UpdateWorkerTopicsResponse {
+        worker_name,
+        topics: payload.topics,
+    }))
+}
+
+/// Topics are bound as routing key patterns, wildcards and dots would match the keys of other workers
+fn is_valid_topic(topic: &str) -> bool {
+    !topic.is_empty()
+        && topic
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_topics() {
+        for topic in ["all", "europe", "eu-west_1"] {
+            assert!(is_valid_topic(topic), "topic: {}", topic);
+        }
+        for topic in ["#", "*", "a.*", "worker.1", "eu west", ""] {
+            assert!(!is_valid_topic(topic), "topic: {}", topic);
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use std::{fmt, str::FromStr, sync::Arc};
+
+use axum::{
+    extract::{Request, State},
+    http::{header::AUTHORIZATION, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{api::api_response::*, api_key_repository::ApiKey, state::AppState};
+
+// Keys are recognizable in logs and secret scanners
+const API_KEY_PREFIX: &str = "bms_";
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// What an API key is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiScope {
+    /// Create jobs, which makes the workers download from the job URL
+    JobsCreate,
+    /// Read the job status and the job listing
+    JobsRead,
+    /// Read the measurements
+    DataRead,
+    /// Everything, including the worker management
+    Admin,
+}
+
+impl ApiScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::JobsCreate => "jobs:create",
+            ApiScope::JobsRead => "jobs:read",
+            ApiScope::DataRead => "data:read",
+            ApiScope::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for ApiScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ApiScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jobs:create" => Ok(ApiScope::JobsCreate),
+            "jobs:read" => Ok(ApiScope::JobsRead),
+            "data:read" => Ok(ApiScope::DataRead),
+            "admin" => Ok(ApiScope::Admin),
+            _ => Err(format!("Unknown scope: {}", s)),
+        }
+    }
+}
+
+impl ApiKey {
+    pub fn has_scope(&self, scope: ApiScope) -> bool {
+        self.scopes
+            .iter()
+            .any(|s| s == scope.as_str() || s == ApiScope::Admin.as_str())
+    }
+
+    /// Key the resources visible to this key were created with, `None` for admin keys seeing all
+    pub fn owner_filter(&self) -> Option<Uuid> {
+        let is_admin = self.scopes.iter().any(|s| s == ApiScope::Admin.as_str());
+        (!is_admin).then_some(self.id)
+    }
+}
+
+/// New random API key, shown once to the user, only its hash is stored
+pub fn generate_api_key() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("{}{}", API_KEY_PREFIX, hex::encode(bytes))
+}
+
+/// Hex SHA-256 of the key. Keys are random, so a fast unsalted hash is enough.
+pub fn hash_api_key(api_key: &str) -> String {
+    hex::encode(Sha256::digest(api_key.as_bytes()))
+}
+
+/// Start of the key stored next to the hash, to tell the keys apart
+pub fn api_key_prefix(api_key: &str) -> &str {
+    &api_key[..api_key.len().min(API_KEY_PREFIX.len() + 8)]
+}
+
+/// Key sent as `Authorization: Bearer <key>` or `X-Api-Key: <key>`
+fn api_key_from_headers(headers: &HeaderMap) -> Option<String> {
+    let bearer = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let header = headers
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok());
+
+    bearer.or(header).map(|key| key.trim().to_string())
+}
+
+/// Resolve the API key of the request and attach it as an `ApiKey` extension.
+/// Requests without a key pass through, routes reject them with `require_scope`.
+pub async fn authenticate(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, ApiResponse<()>> {
+    if let Some(api_key) = api_key_from_headers(request.headers()) {
+        let api_key = state
+            .api_key_repo
+            .get_active_api_key(&hash_api_key(&api_key))
+            .await
+            .map_err(|e| {
+                error!("Failed to get API key from the database: {:?}", e);
+                internal_server_error("Failed to authenticate request")
+            })?
+            .ok_or_else(|| unauthorized("Invalid or revoked API key"))?;
+
+        request.extensions_mut().insert(api_key);
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// Reject requests without an API key having the scope, used as a route layer
+pub async fn require_scope(
+    State(scope): State<ApiScope>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiResponse<()>> {
+    let api_key = request
+        .extensions()
+        .get::<ApiKey>()
+        .ok_or_else(|| unauthorized("API key required"))?;
+
+    if !api_key.has_scope(scope) {
+        return Err(forbidden(format!("API key lacks the {} scope", scope)));
+    }
+
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn api_key(scopes: &[&str]) -> ApiKey {
+        ApiKey {
+            id: Uuid::new_v4(),
+            name: "test".to_string(),
+            key_prefix: "bms_00000000".to_string(),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            created_at: None,
+            revoked_at: None,
+            jobs_per_hour: None,
+            bytes_per_day: None,
+            concurrent_jobs: None,
+        }
+    }
+
+    #[test]
+    fn parses_scopes() {
+        for scope in [
+            ApiScope::JobsCreate,
+            ApiScope::JobsRead,
+            ApiScope::DataRead,
+            ApiScope::Admin,
+        ] {
+            assert_eq!(scope.as_str().parse::<ApiScope>(), Ok(scope));
+        }
+        assert!("jobs".parse::<ApiScope>().is_err());
+        assert!("Admin".parse::<ApiScope>().is_err());
+        assert!("".parse::<ApiScope>().is_err());
+    }
+
+    #[test]
+    fn admin_implies_every_scope() {
+        let admin = api_key(&["admin"]);
+        assert!(admin.has_scope(ApiScope::JobsCreate));
+        assert!(admin.has_scope(ApiScope::DataRead));
+        assert_eq!(admin.owner_filter(), None);
+
+        let reader = api_key(&["jobs:read"]);
+        assert!(reader.has_scope(ApiScope::JobsRead));
+        assert!(!reader.has_scope(ApiScope::JobsCreate));
+        assert!(!reader.has_scope(ApiScope::Admin));
+        assert_eq!(reader.owner_filter(), Some(reader.id));
+    }
+
+    #[test]
+    fn extracts_key_prefix() {
+        let key = generate_api_key();
+        assert!(key.starts_with(API_KEY_PREFIX));
+        assert_eq!(api_key_prefix(&key), &key[..API_KEY_PREFIX.len() + 8]);
+        assert_eq!(api_key_prefix("bms_12"), "bms_12");
+        assert_eq!(api_key_prefix(""), "");
+    }
+
+    #[test]
+    fn reads_key_from_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(api_key_from_headers(&headers), None);
+
+        headers.insert(API_KEY_HEADER, HeaderValue::from_static("bms_header"));
+        assert_eq!(
+            api_key_from_headers(&headers).as_deref(),
+            Some("bms_header")
+        );
+
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_static("Bearer bms_bearer "),
+        );
+        assert_eq!(
+            api_key_from_headers(&headers).as_deref(),
+            Some("bms_bearer")
+        );
+
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Basic dXNlcg=="));
+        assert_eq!(
+            api_key_from_headers(&headers).as_deref(),
+            Some("bms_header")
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use anyhow::{anyhow, bail, Result};
+use uuid::Uuid;
+
+use crate::{
+    auth::{api_key_prefix, generate_api_key, hash_api_key, ApiScope},
+    repository::ApiKeyRepository,
+};
+
+const USAGE: &str = "Usage:
+  scheduler api-key create <name> <scope>[,<scope>...]
+  scheduler api-key list
+  scheduler api-key revoke <id>
+  scheduler api-key set-limits <id> <jobs_per_hour> <bytes_per_day> <concurrent_jobs>
+
+Scopes: jobs:create, jobs:read, data:read, admin
+Limits: a number, or - for the RATE_LIMIT_KEY_* default";
+
+/// Admin commands run instead of the server, e.g. to create the first API key
+pub async fn run(args: &[String], api_key_repo: ApiKeyRepository) -> Result<()> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        ["api-key", "create", name, scopes] => {
+            let scopes = scopes
+                .split(',')
+                .map(|scope| scope.trim().parse::<ApiScope>().map(|s| s.to_string()))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| anyhow!(e))?;
+
+            let api_key = generate_api_key();
+            let created = api_key_repo
+                .create_api_key(
+                    name,
+                    &hash_api_key(&api_key),
+                    api_key_prefix(&api_key),
+                    &scopes,
+                )
+                .await?;
+
+            println!("Created API key {} ({})", created.id, created.name);
+            println!("{}", api_key);
+            println!("Store it now, it can't be shown again.");
+        }
+        ["api-key", "list"] => {
+            for api_key in api_key_repo.list_api_keys().await? {
+                let status = match api_key.revoked_at {
+                    Some(revoked_at) => format!("revoked {}", revoked_at),
+                    None => "active".to_string(),
+                };
+                let limits = [
+                    api_key.jobs_per_hour,
+                    api_key.bytes_per_day,
+                    api_key.concurrent_jobs,
+                ]
+                .map(|limit| limit.map_or("-".to_string(), |limit| limit.to_string()))
+                .join(" ");
+                println!(
+                    "{}  {}...  {}  {}  limits: {}  {}",
+                    api_key.id,
+                    api_key.key_prefix,
+                    api_key.name,
+                    api_key.scopes.join(","),
+                    limits,
+                    status
+                );
+            }
+        }
+        ["api-key", "revoke", id] => {
+            let id = Uuid::parse_str(id).map_err(|_| anyhow!("Invalid API key id: {}", id))?;
+            if !api_key_repo.revoke_api_key(id).await? {
+                bail!("No active API key {}", id);
+            }
+            println!("Revoked API key {}", id);
+        }
+        ["api-key", "set-limits", id, jobs_per_hour, bytes_per_day, concurrent_jobs] => {
+            let id = Uuid::parse_str(id).map_err(|_| anyhow!("Invalid API key id: {}", id))?;
+            let updated = api_key_repo
+                .update_limits(
+                    id,
+                    parse_limit(jobs_per_hour)?,
+                    parse_limit(bytes_per_day)?,
+                    parse_limit(concurrent_jobs)?,
+                )
+                .await?;
+            if !updated {
+                bail!("No API key {}", id);
+            }
+            println!("Updated limits of API key {}", id);
+        }
+        _ => bail!("{}", USAGE),
+    }
+
+    Ok(())
+}
+
+/// `-` restores the default limit
+fn parse_limit(limit: &str) -> Result<Option<i64>> {
+    match limit {
+        "-" => Ok(None),
+        _ => limit
+            .parse()
+            .map(Some)
+            .map_err(|_| anyhow!("Invalid limit: {}", limit)),
+    }
+}
@@ -0,0 +1,90 @@
+//! Live events of the jobs, streamed to the clients by `GET /job/{job_id}/events`.
+//!
+//! Events are fanned out to the subscribers of this scheduler instance only and aren't stored,
+//! clients get the current state from the snapshot sent when they connect.
+
+use rabbitmq::DownloadProgress;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::{job_repository::JobStatus, sub_job_repository::SubJobStatus};
+
+// Events kept for slow subscribers, about a minute of progress from a large fleet
+const EVENTS_CAPACITY: usize = 4096;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobEvent {
+    /// Bytes downloaded so far by a worker, sent only by workers reporting progress
+    Progress {
+        worker_name: String,
+        #[serde(flatten)]
+        progress: DownloadProgress,
+    },
+    /// Sub job became running or finished
+    SubJob {
+        job_id: Uuid,
+        sub_job_id: Uuid,
+        status: SubJobStatus,
+    },
+    /// Worker reported the result of a sub job
+    Result {
+        job_id: Uuid,
+        sub_job_id: Uuid,
+        run_id: Uuid,
+        worker_name: String,
+        is_success: bool,
+        /// Mbps, set when the download succeeded
+        download_speed: Option<f64>,
+    },
+    /// Job finished, the last event of the job
+    Job { job_id: Uuid, status: JobStatus },
+}
+
+impl JobEvent {
+    pub fn job_id(&self) -> Uuid {
+        match self {
+            JobEvent::Progress { progress, .. } => progress.job_id,
+            JobEvent::SubJob { job_id, .. }
+            | JobEvent::Result { job_id, .. }
+            | JobEvent::Job { job_id, .. } => *job_id,
+        }
+    }
+
+    /// SSE event name, the same as the `type` field
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobEvent::Progress { .. } => "progress",
+            JobEvent::SubJob { .. } => "sub_job",
+            JobEvent::Result { .. } => "result",
+            JobEvent::Job { .. } => "job",
+        }
+    }
+}
+
+pub struct JobEvents {
+    sender: broadcast::Sender<JobEvent>,
+}
+
+impl Default for JobEvents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JobEvents {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENTS_CAPACITY);
+        Self { sender }
+    }
+
+    /// Send the event to the current subscribers, dropped when nobody listens
+    pub fn publish(&self, event: JobEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<JobEvent> {
+        self.sender.subscribe()
+    }
+}
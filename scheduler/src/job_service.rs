@@ -0,0 +1,452 @@
+//! Creation of the jobs and dispatch of their sub jobs to the workers,
+//! shared by `POST /job` and `POST /jobs/batch`.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rabbitmq::{JobMessage, Message, PublishError};
+use rand::Rng;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::PgConnection;
+use tracing::{debug, error, info};
+use url::Url;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    api::{api_response::*, create_job::JobResponse},
+    api_key_repository::ApiKey,
+    events::JobEvent,
+    job_repository::{Job, JobStatus, NewJob},
+    rate_limit::check_rate_limits,
+    state::AppState,
+    sub_job_repository::{SubJob, SubJobStatus, SubJobType},
+    webhook,
+};
+
+const MAX_DOWNLOAD_DURATION_SECS: u64 = 60;
+const DOWNLOAD_DELAY_SECS: u64 = 10;
+pub const SYNC_DELAY_SECS: u64 = 1;
+const SUB_JOBS_PER_JOB: i64 = 2;
+// Size of the range downloaded from the file
+const RANGE_SIZE_MB: u64 = 100;
+
+/// Time a sub job occupies its workers, the next sub job of the job starts after it
+pub const SUB_JOB_DURATION: Duration =
+    Duration::from_secs(DOWNLOAD_DELAY_SECS + MAX_DOWNLOAD_DURATION_SECS + SYNC_DELAY_SECS);
+/// Time a job occupies its workers, from the start of its first sub job to the end of the last
+pub const JOB_DURATION: Duration =
+    Duration::from_secs(SUB_JOB_DURATION.as_secs() * SUB_JOBS_PER_JOB as u64);
+
+/// How the target is benchmarked
+#[derive(Deserialize, ToSchema, Clone, Debug)]
+pub struct JobProfile {
+    pub routing_key: String,
+    /// Labels used to filter the job listing
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Notified with a POST when the job finishes
+    #[serde(default)]
+    pub callback_url: Option<String>,
+    /// Signs the notifications with HMAC-SHA256, requires `callback_url`
+    #[serde(default)]
+    pub callback_secret: Option<String>,
+    /// Registered provider serving the URL, groups its jobs even when its endpoints change
+    #[serde(default)]
+    pub provider_id: Option<Uuid>,
+}
+
+/// Validated job with the range to download, ready to be created
+#[derive(Debug)]
+pub struct PreparedJob {
+    pub url: Url,
+    pub host: String,
+    pub start_range: u64,
+    pub end_range: u64,
+    /// Bytes downloaded by all the workers receiving the routing key
+    pub estimated_bytes: i64,
+    pub profile: JobProfile,
+}
+
+/// Job that couldn't be created, or whose sub jobs couldn't be dispatched
+pub struct CreateJobError {
+    /// Set when the job was created, it's failed then
+    pub job_id: Option<Uuid>,
+    pub response: ApiResponse<()>,
+}
+
+impl From<ApiResponse<()>> for CreateJobError {
+    fn from(response: ApiResponse<()>) -> Self {
+        Self {
+            job_id: None,
+            response,
+        }
+    }
+}
+
+impl From<CreateJobError> for ApiResponse<()> {
+    fn from(error: CreateJobError) -> Self {
+        error.response
+    }
+}
+
+/// Validate the job, check the rate limits and pick the range to download with a HEAD request
+/// to the URL. Targets over the limits aren't contacted, the limits are checked again when the
+/// job is created.
+pub async fn prepare_job(
+    state: &AppState,
+    api_key: &ApiKey,
+    url: &str,
+    profile: JobProfile,
+) -> Result<PreparedJob, ApiResponse<()>> {
+    let url = validate_job(url, &profile)?;
+    let host = url.host_str().unwrap_or_default().to_lowercase();
+
+    // Every worker receiving the routing key downloads the range once per sub job
+    let workers = state
+        .topic_repo
+        .get_workers_for_routing_key(&profile.routing_key)
+        .await
+        .map_err(|_| internal_server_error("Failed to count workers"))?;
+    let estimated_bytes =
+        (RANGE_SIZE_MB * 1024 * 1024) as i64 * SUB_JOBS_PER_JOB * (workers.len() as i64).max(1);
+
+    // Rolled back, releasing the usage before the HEAD request
+    let mut tx = state.job_repo.begin().await.map_err(|e| {
+        error!("Failed to begin transaction: {:?}", e);
+        internal_server_error("Failed to check rate limits")
+    })?;
+    check_rate_limits(state, &mut tx, api_key, &host, estimated_bytes).await?;
+    drop(tx);
+
+    let (start_range, end_range) = get_file_range_for_file(url.as_ref()).await?;
+
+    Ok(PreparedJob {
+        url,
+        host,
+        start_range,
+        end_range,
+        estimated_bytes,
+        profile,
+    })
+}
+
+/// Validate the job without contacting the URL
+pub fn validate_job(url: &str, profile: &JobProfile) -> Result<Url, ApiResponse<()>> {
+    let url = validate_url(url)?;
+    validate_routing_key(profile)?;
+    validate_tags(profile)?;
+    validate_callback(profile)?;
+
+    Ok(url)
+}
+
+/// Check that the provider of the profile is registered
+pub async fn check_provider(state: &AppState, profile: &JobProfile) -> Result<(), ApiResponse<()>> {
+    let Some(provider_id) = profile.provider_id else {
+        return Ok(());
+    };
+
+    let provider = state
+        .provider_repo
+        .get_provider(provider_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to get provider from the database: {:?}", e);
+            internal_server_error("Failed to get provider from the database")
+        })?;
+    if provider.is_none() {
+        return Err(bad_request(format!("Provider {} not found", provider_id)));
+    }
+
+    Ok(())
+}
+
+/// Create the job and dispatch its sub jobs, the first one starts at `start_time`
+pub async fn create_job(
+    state: &AppState,
+    api_key: &ApiKey,
+    prepared: &PreparedJob,
+    start_time: DateTime<Utc>,
+    batch_id: Option<Uuid>,
+) -> Result<JobResponse, CreateJobError> {
+    let profile = &prepared.profile;
+    let job_id = Uuid::new_v4();
+
+    check_provider(state, profile).await?;
+
+    // The usage is locked until the job is created, so concurrent jobs can't both fit in the limits
+    let mut tx = state.job_repo.begin().await.map_err(|e| {
+        error!("Failed to begin transaction: {:?}", e);
+        internal_server_error("Failed to create job")
+    })?;
+    check_rate_limits(
+        state,
+        &mut tx,
+        api_key,
+        &prepared.host,
+        prepared.estimated_bytes,
+    )
+    .await?;
+
+    let job = state
+        .job_repo
+        .create_job(
+            &mut tx,
+            NewJob {
+                id: job_id,
+                url: prepared.url.as_str(),
+                routing_key: &profile.routing_key,
+                status: JobStatus::Pending,
+                details: json!({
+                    "start_range": prepared.start_range,
+                    "end_range": prepared.end_range,
+                }),
+                tags: &profile.tags,
+                api_key_id: api_key.id,
+                host: &prepared.host,
+                estimated_bytes: prepared.estimated_bytes,
+                callback_url: profile.callback_url.as_deref(),
+                callback_secret: profile.callback_secret.as_deref(),
+                batch_id,
+                provider_id: profile.provider_id,
+            },
+        )
+        .await
+        .map_err(|_| internal_server_error("Failed to create job"))?;
+    tx.commit().await.map_err(|e| {
+        error!("Failed to commit job {}: {:?}", job_id, e);
+        internal_server_error("Failed to create job")
+    })?;
+
+    debug!("Job created successfully: {:?}", job);
+
+    // Createa sub jobs and send them to the worker, one after another
+    let mut sub_jobs = Vec::new();
+    for i in 0..SUB_JOBS_PER_JOB as u32 {
+        let sub_job_start_time = start_time + SUB_JOB_DURATION * i;
+        match create_and_dispatch_subjob(state, &job, sub_job_start_time).await {
+            Ok(sub_job) => sub_jobs.push(sub_job.id),
+            Err(response) => {
+                let response = match fail_job(state, job_id).await {
+                    Ok(()) => response,
+                    Err(failed) => failed,
+                };
+                return Err(CreateJobError {
+                    job_id: Some(job_id),
+                    response,
+                });
+            }
+        }
+    }
+
+    info!(
+        "Job with sub jobs created successfully: {}, sub_jobs: {:?}",
+        job_id, sub_jobs
+    );
+
+    Ok(JobResponse { job_id, sub_jobs })
+}
+
+/// Finish the job once all its sub jobs are, and queue the notification of the client in the
+/// same transaction. Returns the status only to the caller that finished the job.
+pub async fn finish_job_if_done(
+    state: &AppState,
+    conn: &mut PgConnection,
+    job_id: Uuid,
+) -> anyhow::Result<Option<JobStatus>> {
+    let status = state.job_repo.finish_job_if_done(conn, job_id).await?;
+    if status.is_some() {
+        webhook::enqueue_job_finished(state, conn, job_id).await?;
+    }
+
+    Ok(status)
+}
+
+/// Fail the job whose sub job couldn't be dispatched, with the sub jobs dispatched before it.
+/// Workers still run those, but their results no longer change the job.
+async fn fail_job(state: &AppState, job_id: Uuid) -> Result<(), ApiResponse<()>> {
+    // The webhook is queued in the same transaction as the failed status
+    let failed: anyhow::Result<Vec<Uuid>> = async {
+        let mut tx = state.job_repo.begin().await?;
+        state.job_repo.lock_job(&mut tx, job_id).await?;
+        let sub_jobs = state
+            .sub_job_repo
+            .fail_unfinished_sub_jobs(&mut tx, job_id)
+            .await?;
+        state
+            .job_repo
+            .update_job_status(&mut tx, job_id, JobStatus::Failed)
+            .await?;
+        webhook::enqueue_job_finished(state, &mut tx, job_id).await?;
+        tx.commit().await?;
+        Ok(sub_jobs)
+    }
+    .await;
+    let sub_jobs = failed.map_err(|e| {
+        error!("Failed to mark job {} as failed: {:?}", job_id, e);
+        internal_server_error("Failed to update job status")
+    })?;
+
+    for sub_job_id in sub_jobs {
+        state.job_events.publish(JobEvent::SubJob {
+            job_id,
+            sub_job_id,
+            status: SubJobStatus::Failed,
+        });
+    }
+    state.job_events.publish(JobEvent::Job {
+        job_id,
+        status: JobStatus::Failed,
+    });
+
+    Ok(())
+}
+
+/// Validate url and its scheme
+fn validate_url(url: &str) -> Result<Url, ApiResponse<()>> {
+    let url = Url::parse(url).map_err(|_| bad_request("Invalid URL provided"))?;
+    match url.scheme() {
+        "http" | "https" => Ok(url),
+        _ => Err(bad_request("URL scheme must be http or https")),
+    }
+}
+
+/// Validate routing key
+/// In future we want to validate if the routing key is valid, maybe by checking a set of allowed keys
+fn validate_routing_key(profile: &JobProfile) -> Result<(), ApiResponse<()>> {
+    if profile.routing_key.is_empty() {
+        return Err(bad_request("Routing key cannot be empty"));
+    }
+
+    Ok(())
+}
+
+/// Validate tags, they are matched exactly so empty or padded tags are rejected
+fn validate_tags(profile: &JobProfile) -> Result<(), ApiResponse<()>> {
+    for tag in &profile.tags {
+        if tag.is_empty() || tag.trim() != tag || tag.contains(',') {
+            return Err(bad_request(format!("Invalid tag: {:?}", tag)));
+        }
+    }
+
+    Ok(())
+}
+
+/// Validate the callback URL, the secret is useless without it
+fn validate_callback(profile: &JobProfile) -> Result<(), ApiResponse<()>> {
+    if let Some(callback_url) = &profile.callback_url {
+        let url =
+            Url::parse(callback_url).map_err(|_| bad_request("Invalid callback URL provided"))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(bad_request("Callback URL scheme must be http or https"));
+        }
+    }
+    match &profile.callback_secret {
+        Some(secret) if secret.is_empty() => Err(bad_request("Callback secret cannot be empty")),
+        Some(_) if profile.callback_url.is_none() => {
+            Err(bad_request("Callback secret requires a callback URL"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Get a random range of 100MB from the file using HEAD request
+async fn get_file_range_for_file(url: &str) -> Result<(u64, u64), ApiResponse<()>> {
+    let response = Client::new()
+        .head(url)
+        .send()
+        .await
+        .map_err(|e| bad_request(format!("Failed to execute HEAD request {}", e)))?;
+
+    debug!("Response: {:?}", response);
+
+    // For some freak reason response.content_length() is returning 0
+    let content_length = response
+        .headers()
+        .get(reqwest::header::CONTENT_LENGTH)
+        .ok_or_else(|| bad_request("Content-Length header is missing in the response"))?
+        .to_str()
+        .map_err(|e| bad_request(format!("Failed to parse Content-Length header: {}", e)))?
+        .parse::<u64>()
+        .map_err(|e| bad_request(format!("Failed to parse Content-Length header: {}", e)))?;
+
+    debug!("Content-Length: {:?}", content_length);
+
+    let size = RANGE_SIZE_MB * 1024 * 1024;
+
+    if content_length < size {
+        return Err(bad_request(format!(
+            "File size is less than {} MB",
+            RANGE_SIZE_MB
+        )));
+    }
+
+    let mut rng = rand::thread_rng();
+    let start_range = rng.gen_range(0..content_length - size);
+    let end_range = start_range + size;
+
+    Ok((start_range, end_range))
+}
+
+async fn create_and_dispatch_subjob(
+    state: &AppState,
+    job: &Job,
+    start_time: chrono::DateTime<Utc>,
+) -> Result<SubJob, ApiResponse<()>> {
+    let download_start_time = start_time + Duration::from_secs(DOWNLOAD_DELAY_SECS);
+
+    let sub_job = state
+        .sub_job_repo
+        .create_sub_job(
+            Uuid::new_v4(),
+            job.id,
+            SubJobStatus::Pending,
+            SubJobType::CombinedDHP,
+            json!({
+                "start_time": start_time,
+                "donwload_start_time": download_start_time,
+                // TODO: optional worker names whitelist
+            }),
+        )
+        .await
+        .map_err(|_| internal_server_error("Failed to create sub job"))?;
+
+    debug!("Sub job created successfully: {:?}", sub_job);
+
+    let job_message = Message::WorkerJob {
+        job_id: job.id,
+        payload: JobMessage {
+            job_id: job.id,
+            sub_job_id: sub_job.id,
+            url: job.url.clone(),
+            start_time,
+            download_start_time,
+            start_range: job.details.start_range,
+            end_range: job.details.end_range,
+        },
+    };
+
+    debug!("Publishing job message: {:?}", job_message);
+
+    state
+        .job_queue
+        .publish(&job_message, &job.routing_key)
+        .await
+        .map_err(|e| {
+            error!("Failed to publish job message {}: {}", sub_job.id, e);
+            match e.downcast_ref::<PublishError>() {
+                Some(PublishError::Unroutable { routing_key, .. }) => bad_request(format!(
+                    "Job published but no worker queue bound to routing key {}",
+                    routing_key
+                )),
+                _ => internal_server_error("Failed to publish job message"),
+            }
+        })?;
+
+    debug!("Job message published successfully: {}", sub_job.id);
+
+    Ok(sub_job)
+}
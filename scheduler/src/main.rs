@@ -1,32 +1,43 @@
 use std::{env, error::Error, sync::Arc};
 
-use axum::Router;
 use color_eyre::Result;
+use events::JobEvents;
 use queue::data_consumer::DataConsumer;
+use queue::dead_letter_consumer::DeadLetterConsumer;
+use queue::signature::SignaturePolicy;
 use queue::status_consumer::StatusConsumer;
 use rabbitmq::*;
+use rate_limit::RateLimits;
 use repository::*;
 use sqlx::{migrate::Migrator, PgPool};
 use state::AppState;
 use tokio::net::TcpListener;
-use tokio::sync::Mutex;
-use tower::ServiceBuilder;
-use tower_http::trace::TraceLayer;
 use tracing::info;
 use tracing_subscriber::EnvFilter;
 use types::DbConnectParams;
 
 mod api;
+mod auth;
+mod cli;
+mod events;
+mod job_service;
 mod queue;
+mod rate_limit;
 mod repository;
 mod routes;
+mod schedules;
 mod state;
+mod sub_job_sweeper;
 mod types;
+mod webhook;
 
 static MIGRATOR: Migrator = sqlx::migrate!("./src/migrations");
 
+// Sender of the messages published by the scheduler
+const SENDER_NAME: &str = "scheduler";
+
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     info!("Scheduler is starting...");
 
     // Initialize color_eyre panic and error handlers
@@ -59,8 +70,24 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let pool = PgPool::connect(&db_url).await?;
     MIGRATOR.run(&pool).await?;
 
-    let job_queue = Arc::new(Mutex::new(QueueHandler::clone(&CONFIG_QUEUE_JOB)));
-    job_queue.lock().await.setup().await?;
+    // Admin commands, e.g. `scheduler api-key create <name> <scopes>`, run without the server
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        cli::run(&args, ApiKeyRepository::new(pool)).await?;
+        return Ok(());
+    }
+
+    let broker_config = BrokerConfig::from_env()?;
+    let namespace_prefix = broker_config.namespace_prefix()?;
+    info!(
+        "RabbitMQ namespace: {}",
+        namespace_prefix.strip_suffix('.').unwrap_or("none")
+    );
+
+    // The scheduler only publishes jobs, workers declare their own queues
+    let mut job_queue = QueueHandler::new(CONFIG_QUEUE_JOB.with_broker(broker_config.clone()))
+        .with_sender(SENDER_NAME);
+    job_queue.setup().await?;
     info!("Successfully set up job queue");
 
     // Initialize repositories
@@ -69,38 +96,77 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let job_repo = Arc::new(JobRepository::new(pool.clone()));
     let topic_repo = Arc::new(TopicRepository::new(pool.clone()));
     let sub_job_repo = Arc::new(SubJobRepository::new(pool.clone()));
+    let missed_sub_job_repo = Arc::new(MissedSubJobRepository::new(pool.clone()));
+    let acknowledged_sub_job_repo = Arc::new(AcknowledgedSubJobRepository::new(pool.clone()));
+    let worker_key_repo = Arc::new(WorkerKeyRepository::new(pool.clone()));
+    let api_key_repo = Arc::new(ApiKeyRepository::new(pool.clone()));
+    let webhook_delivery_repo = Arc::new(WebhookDeliveryRepository::new(pool.clone()));
+    let schedule_repo = Arc::new(ScheduleRepository::new(pool.clone()));
+    let provider_repo = Arc::new(ProviderRepository::new(pool.clone()));
+
+    // Accept unverified worker messages by default, until the keys of the fleet are registered
+    let signature_policy = match env::var("SIGNATURE_POLICY") {
+        Ok(policy) => policy.parse::<SignaturePolicy>()?,
+        Err(_) => SignaturePolicy::Accept,
+    };
+    info!("Signature policy: {:?}", signature_policy);
+
+    // Unlimited unless the RATE_LIMIT_* variables are set
+    let rate_limits = RateLimits::from_env()?;
+    info!("Rate limits: {:?}", rate_limits);
 
     // Initialize app state
     let app_state = Arc::new(AppState::new(
-        job_queue.clone(),
+        Arc::new(job_queue.clone()),
         data_repo,
         worker_repo,
         job_repo,
         topic_repo,
         sub_job_repo,
+        missed_sub_job_repo,
+        acknowledged_sub_job_repo,
+        worker_key_repo,
+        api_key_repo,
+        webhook_delivery_repo,
+        schedule_repo,
+        provider_repo,
+        signature_policy,
+        rate_limits,
+        JobEvents::new(),
     ));
 
-    let mut data_queue = QueueHandler::clone(&CONFIG_QUEUE_RESULT);
+    let mut data_queue = QueueHandler::new(CONFIG_QUEUE_RESULT.with_broker(broker_config.clone()))
+        .with_sender(SENDER_NAME);
     data_queue.setup().await?;
     info!("Successfully set up data queue");
 
     let data_consumer = DataConsumer::new(app_state.clone());
-    data_queue.subscribe(data_consumer).await?;
+    data_queue.subscribe(Arc::new(data_consumer)).await?;
     info!("Successfully started data queue consumer");
 
-    let mut status_queue = QueueHandler::clone(&CONFIG_QUEUE_STATUS);
+    let mut status_queue =
+        QueueHandler::new(CONFIG_QUEUE_STATUS.with_broker(broker_config.clone()))
+            .with_sender(SENDER_NAME);
     status_queue.setup().await?;
     let status_consumer = StatusConsumer::new(app_state.clone());
-    status_queue.subscribe(status_consumer).await?;
+    status_queue.subscribe(Arc::new(status_consumer)).await?;
     info!("Successfully started status queue consumer");
 
-    let app = Router::new()
-        .merge(routes::create_routes())
-        .layer(
-            ServiceBuilder::new().layer(TraceLayer::new_for_http()),
-            // TODO: add something to authenticate requests
-        )
-        .with_state(app_state.clone());
+    let mut dead_letter_queue =
+        QueueHandler::new(CONFIG_QUEUE_DEAD_LETTER.with_broker(broker_config))
+            .with_sender(SENDER_NAME);
+    dead_letter_queue.setup().await?;
+    let dead_letter_consumer = DeadLetterConsumer::new(app_state.clone());
+    dead_letter_queue
+        .subscribe(Arc::new(dead_letter_consumer))
+        .await?;
+    info!("Successfully started dead letter queue consumer");
+
+    let webhook_dispatcher = tokio::spawn(webhook::run_dispatcher(app_state.clone()));
+    let schedule_runner = tokio::spawn(schedules::run_schedules(app_state.clone()));
+    let sub_job_sweeper = tokio::spawn(sub_job_sweeper::run_sweeper(app_state.clone()));
+
+    let app = routes::create_app(app_state.clone());
 
     let server_addr = "0.0.0.0:3000".to_string();
     let listener = TcpListener::bind(&server_addr).await?;
@@ -115,10 +181,18 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // TODO: do not accept new jobs and wait for execution of existing ones
     // TODO: maybe lookup tokio::sync::Notify for this
 
+    // Undelivered webhooks stay pending and are sent after the restart
+    webhook_dispatcher.abort();
+    // Interrupted runs are failed on the next start, the schedules run at their next due time
+    schedule_runner.abort();
+    // Stale sub jobs are found again after the restart
+    sub_job_sweeper.abort();
+
     // Close the connection gracefully
-    job_queue.lock().await.close().await?;
+    job_queue.close().await?;
     data_queue.close().await?;
     status_queue.close().await?;
+    dead_letter_queue.close().await?;
 
     info!("Scheduler shut down gracefully");
 
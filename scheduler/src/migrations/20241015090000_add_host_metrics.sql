@@ -0,0 +1,8 @@
+-- Add last reported host metrics to workers table
+ALTER TABLE workers
+    ADD COLUMN IF NOT EXISTS host_metrics JSONB;
+
+-- Add host metrics sampled during the measurement to worker_data table
+ALTER TABLE worker_data
+    ADD COLUMN IF NOT EXISTS host_metrics JSONB,
+    ADD COLUMN IF NOT EXISTS is_worker_bound BOOLEAN;
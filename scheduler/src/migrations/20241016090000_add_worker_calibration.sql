@@ -0,0 +1,7 @@
+-- Add last reported calibration baseline to workers table
+ALTER TABLE workers
+    ADD COLUMN IF NOT EXISTS calibration JSONB;
+
+-- Add worker calibration baseline at the time of the measurement to worker_data table
+ALTER TABLE worker_data
+    ADD COLUMN IF NOT EXISTS worker_calibration JSONB;
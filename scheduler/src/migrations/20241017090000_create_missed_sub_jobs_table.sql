@@ -0,0 +1,9 @@
+-- Create the missed_sub_jobs table, sub jobs that expired in a worker queue before the worker took them
+CREATE TABLE IF NOT EXISTS missed_sub_jobs (
+  sub_job_id UUID NOT NULL,
+  worker_name VARCHAR(255) NOT NULL,
+  reason VARCHAR(255) NOT NULL,
+  created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (sub_job_id, worker_name),
+  FOREIGN KEY (sub_job_id) REFERENCES sub_jobs(id) ON DELETE CASCADE
+);
@@ -0,0 +1,13 @@
+-- Create the worker_keys table, public keys of the workers signing their results and statuses
+CREATE TABLE IF NOT EXISTS worker_keys (
+  worker_name VARCHAR(255) PRIMARY KEY,
+  public_key VARCHAR(255) NOT NULL,
+  created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Record whether the result was signed by the key registered for the worker, NULL for results saved before
+CREATE TYPE signature_status AS ENUM ('valid', 'invalid', 'unsigned', 'unknown_key');
+
+ALTER TABLE worker_data
+ADD COLUMN signature_status signature_status;
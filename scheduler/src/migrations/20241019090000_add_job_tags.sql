@@ -0,0 +1,9 @@
+-- Free-form labels of the job, used to filter the job listing
+ALTER TABLE jobs
+ADD COLUMN IF NOT EXISTS tags TEXT[] NOT NULL DEFAULT '{}';
+
+-- Create index on tags, used by the "contains all tags" filter
+CREATE INDEX IF NOT EXISTS jobs_tags_index ON jobs USING GIN (tags);
+
+-- Create index on created_at and id, used for sorting and cursor pagination of the job listing
+CREATE INDEX IF NOT EXISTS jobs_created_at_id_index ON jobs(created_at, id);
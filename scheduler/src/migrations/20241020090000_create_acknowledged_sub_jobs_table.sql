@@ -0,0 +1,10 @@
+-- Create the acknowledged_sub_jobs table, sub jobs the workers took from their queues
+CREATE TABLE IF NOT EXISTS acknowledged_sub_jobs (
+  sub_job_id UUID NOT NULL,
+  worker_name VARCHAR(255) NOT NULL,
+  run_id UUID NOT NULL,
+  acknowledged_at TIMESTAMP WITH TIME ZONE NOT NULL,
+  created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (sub_job_id, worker_name),
+  FOREIGN KEY (sub_job_id) REFERENCES sub_jobs(id) ON DELETE CASCADE
+);
@@ -0,0 +1,18 @@
+-- Create the api_keys table, only the SHA-256 hash of the key is stored
+CREATE TABLE IF NOT EXISTS api_keys (
+  id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  name VARCHAR(255) NOT NULL,
+  key_hash VARCHAR(64) NOT NULL UNIQUE,
+  -- First characters of the key, to tell the keys apart
+  key_prefix VARCHAR(16) NOT NULL,
+  scopes TEXT[] NOT NULL,
+  created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+  revoked_at TIMESTAMP WITH TIME ZONE
+);
+
+-- Record the key that created the job, NULL for jobs created before
+ALTER TABLE jobs
+ADD COLUMN api_key_id UUID REFERENCES api_keys(id) ON DELETE SET NULL;
+
+-- Create index on api_key_id
+CREATE INDEX IF NOT EXISTS jobs_api_key_id_index ON jobs(api_key_id);
@@ -0,0 +1,26 @@
+-- Host of the job URL, jobs are rate limited per host
+ALTER TABLE jobs
+ADD COLUMN IF NOT EXISTS host VARCHAR(255);
+
+UPDATE jobs
+SET host = COALESCE(LOWER(SUBSTRING(url FROM '^[a-zA-Z]+://(?:[^/@]*@)?([^/:?#]+)')), '');
+
+ALTER TABLE jobs
+ALTER COLUMN host SET NOT NULL;
+
+-- Bytes the workers are expected to download for the job, counted towards the daily quotas
+ALTER TABLE jobs
+ADD COLUMN IF NOT EXISTS estimated_bytes BIGINT NOT NULL DEFAULT 0;
+
+-- Replace the api_key_id index, usage is counted over a time window
+DROP INDEX IF EXISTS jobs_api_key_id_index;
+CREATE INDEX IF NOT EXISTS jobs_api_key_id_created_at_index ON jobs(api_key_id, created_at);
+
+-- Create index on host and created_at
+CREATE INDEX IF NOT EXISTS jobs_host_created_at_index ON jobs(host, created_at);
+
+-- Limits of the key overriding the defaults, NULL uses the default
+ALTER TABLE api_keys
+ADD COLUMN jobs_per_hour BIGINT,
+ADD COLUMN bytes_per_day BIGINT,
+ADD COLUMN concurrent_jobs BIGINT;
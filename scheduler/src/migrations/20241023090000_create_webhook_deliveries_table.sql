@@ -0,0 +1,33 @@
+-- URL notified when the job reaches a terminal state, and the secret signing the notification
+ALTER TABLE jobs
+ADD COLUMN callback_url TEXT,
+ADD COLUMN callback_secret TEXT;
+
+-- Create webhook_delivery_status enum
+CREATE TYPE webhook_delivery_status AS ENUM ('pending', 'delivered', 'failed');
+
+-- Create the webhook_deliveries table, one notification per job retried until delivered or given up
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+  id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  job_id UUID NOT NULL UNIQUE,
+  url TEXT NOT NULL,
+  payload JSONB NOT NULL,
+  status webhook_delivery_status NOT NULL DEFAULT 'pending',
+  attempts INT NOT NULL DEFAULT 0,
+  next_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  last_status_code INT,
+  last_error TEXT,
+  delivered_at TIMESTAMP WITH TIME ZONE,
+  created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY (job_id) REFERENCES jobs(id) ON DELETE CASCADE
+);
+
+-- Create index on the pending deliveries, polled by the dispatcher
+CREATE INDEX IF NOT EXISTS webhook_deliveries_pending_index ON webhook_deliveries(next_attempt_at) WHERE status = 'pending';
+
+-- Call the trigger function before every update on webhook_deliveries
+CREATE TRIGGER update_updated_at_trigger
+BEFORE UPDATE ON webhook_deliveries
+FOR EACH ROW
+EXECUTE FUNCTION update_updated_at_column();
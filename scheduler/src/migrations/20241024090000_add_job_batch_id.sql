@@ -0,0 +1,6 @@
+-- Batch of the jobs created together by POST /jobs/batch, NULL for single jobs
+ALTER TABLE jobs
+ADD COLUMN IF NOT EXISTS batch_id UUID;
+
+-- Create index on batch_id, used to list the jobs of a batch
+CREATE INDEX IF NOT EXISTS jobs_batch_id_index ON jobs(batch_id) WHERE batch_id IS NOT NULL;
@@ -0,0 +1,52 @@
+-- Create the schedules table, a job is created from the schedule whenever its cron expression is due
+CREATE TABLE IF NOT EXISTS schedules (
+  id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  name VARCHAR(255) NOT NULL,
+  url TEXT NOT NULL,
+  routing_key VARCHAR(255) NOT NULL,
+  tags TEXT[] NOT NULL DEFAULT '{}',
+  callback_url TEXT,
+  callback_secret TEXT,
+  cron_expression VARCHAR(255) NOT NULL,
+  enabled BOOLEAN NOT NULL DEFAULT TRUE,
+  -- Key that created the schedule, the jobs are created with it
+  api_key_id UUID NOT NULL,
+  next_run_at TIMESTAMP WITH TIME ZONE NOT NULL,
+  created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY (api_key_id) REFERENCES api_keys(id)
+);
+
+-- Create index on the enabled schedules, polled by the schedule runner
+CREATE INDEX IF NOT EXISTS schedules_next_run_at_index ON schedules(next_run_at) WHERE enabled;
+
+-- Call the trigger function before every update on schedules
+CREATE TRIGGER update_updated_at_trigger
+BEFORE UPDATE ON schedules
+FOR EACH ROW
+EXECUTE FUNCTION update_updated_at_column();
+
+-- Create schedule_run_status enum
+CREATE TYPE schedule_run_status AS ENUM ('pending', 'created', 'failed');
+
+-- Create the schedule_runs table, the history of the schedule.
+-- One run per due time, so a run is never repeated, e.g. after a restart or by another scheduler instance
+CREATE TABLE IF NOT EXISTS schedule_runs (
+  id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  schedule_id UUID NOT NULL,
+  scheduled_for TIMESTAMP WITH TIME ZONE NOT NULL,
+  status schedule_run_status NOT NULL DEFAULT 'pending',
+  job_id UUID,
+  error TEXT,
+  created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE (schedule_id, scheduled_for),
+  FOREIGN KEY (schedule_id) REFERENCES schedules(id) ON DELETE CASCADE,
+  FOREIGN KEY (job_id) REFERENCES jobs(id) ON DELETE SET NULL
+);
+
+-- Call the trigger function before every update on schedule_runs
+CREATE TRIGGER update_updated_at_trigger
+BEFORE UPDATE ON schedule_runs
+FOR EACH ROW
+EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,25 @@
+-- Create the providers table, the storage providers benchmarked by the jobs
+CREATE TABLE IF NOT EXISTS providers (
+  id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  -- Filecoin miner ID, e.g. f01234
+  miner_id VARCHAR(255) NOT NULL UNIQUE,
+  organization VARCHAR(255),
+  retrieval_endpoints TEXT[] NOT NULL DEFAULT '{}',
+  region VARCHAR(255),
+  created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Call the trigger function before every update on providers
+CREATE TRIGGER update_updated_at_trigger
+BEFORE UPDATE ON providers
+FOR EACH ROW
+EXECUTE FUNCTION update_updated_at_column();
+
+-- Jobs and schedules optionally benchmark a provider, referenced by id so the history
+-- of the provider is kept when its endpoints change. Referenced providers can't be deleted.
+ALTER TABLE jobs ADD COLUMN provider_id UUID REFERENCES providers(id);
+ALTER TABLE schedules ADD COLUMN provider_id UUID REFERENCES providers(id);
+
+-- Create index on the provider of the jobs, used by the listing and the data filters
+CREATE INDEX IF NOT EXISTS jobs_provider_id_created_at_index ON jobs(provider_id, created_at) WHERE provider_id IS NOT NULL;
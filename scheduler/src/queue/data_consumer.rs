@@ -1,116 +1,164 @@
 use std::sync::Arc;
 
-use amqprs::{
-    channel::{BasicAckArguments, Channel},
-    consumer::AsyncConsumer,
-    BasicProperties, Deliver,
-};
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use async_trait::async_trait;
-use rabbitmq::{Message, ResultMessage};
-use serde_json;
-use tracing::{debug, error, info};
+use rabbitmq::{Acknowledgement, Delivery, Envelope, Message, MessageHandler, ResultMessage};
+use tracing::{debug, info, warn};
 use uuid::Uuid;
 
-use crate::{job_repository::JobStatus, state::AppState, sub_job_repository::SubJobStatus};
+use crate::{
+    data_repository::SignatureStatus,
+    events::JobEvent,
+    job_service,
+    queue::{
+        redelivery::{InvalidMessage, Redeliveries},
+        signature::{verify_signature, SignaturePolicy},
+    },
+    state::AppState,
+};
 
 pub struct DataConsumer {
     state: Arc<AppState>,
+    redeliveries: Redeliveries,
 }
 
 impl DataConsumer {
     pub fn new(state: Arc<AppState>) -> Self {
-        Self { state }
+        Self {
+            state,
+            redeliveries: Redeliveries::default(),
+        }
     }
 
-    async fn parse_message(&self, content_str: &str) -> Result<(Uuid, ResultMessage)> {
-        match serde_json::from_str::<Message>(content_str) {
+    async fn parse_message(&self, delivery: &Delivery) -> Result<(Uuid, ResultMessage)> {
+        match Envelope::from_delivery(delivery).map(|envelope| envelope.message) {
             Ok(Message::WorkerResult { job_id, result }) => Ok((job_id, result)),
-            Ok(_) => Err(anyhow!("Received unexpected message")),
-            Err(e) => {
-                error!("Error parsing message: {:?}", e);
-                Err(e.into())
-            }
+            Ok(_) => Err(InvalidMessage("Received unexpected message".to_string()).into()),
+            Err(e) => Err(InvalidMessage(format!("Error parsing message: {}", e)).into()),
         }
     }
 
     #[tracing::instrument(skip(self, result_message), fields(worker_name = %result_message.worker_name))]
-    async fn process_message(&self, job_id: Uuid, result_message: ResultMessage) -> Result<()> {
+    async fn process_message(
+        &self,
+        job_id: Uuid,
+        result_message: ResultMessage,
+        signature_status: SignatureStatus,
+    ) -> Result<()> {
         info!("Handling data message");
         debug!("Handling data message: {:?} {:?}", job_id, result_message);
 
         let sub_job_id = result_message.sub_job_id;
-        let is_success = result_message.is_success;
+        let run_id = result_message.run_id;
+        let result_event = JobEvent::Result {
+            job_id,
+            sub_job_id,
+            run_id,
+            worker_name: result_message.worker_name.clone(),
+            is_success: result_message.is_success,
+            download_speed: result_message
+                .download_result
+                .as_ref()
+                .ok()
+                .map(|result| result.download_speed),
+        };
+
+        // Saving the result and finishing the sub job and job commit together, with the webhook of
+        // the finished job. Otherwise a failure in between would lose the finish on redelivery.
+        let mut tx = self.state.job_repo.begin().await?;
+        self.state.job_repo.lock_job(&mut tx, job_id).await?;
+
+        // Save the data, workers may publish the same result again (e.g. from their outbox)
+        if !self
+            .state
+            .data_repo
+            .save_data(&mut tx, result_message, signature_status)
+            .await?
+        {
+            info!("Result for run_id: {} already saved, skipping", run_id);
+            return Ok(());
+        }
 
-        // Save the data
-        self.state.data_repo.save_data(result_message).await?;
-        // Update the sub job status
-        self.state
-            .sub_job_repo
-            .update_sub_job_status(
-                &sub_job_id,
-                if is_success {
-                    SubJobStatus::Completed
-                } else {
-                    SubJobStatus::Failed
-                },
-            )
-            .await?;
+        // Quarantined results are kept for inspection, but don't count towards the job
+        let quarantine = self.state.signature_policy == SignaturePolicy::Quarantine;
+        if signature_status != SignatureStatus::Valid && quarantine {
+            warn!("Quarantined result for run_id: {}", run_id);
+        }
 
-        // Check if all sub jobs are completed
-        let pending_sub_jobs = self
+        // The sub job is finished once every worker that took it reported
+        let sub_job_status = self
             .state
             .sub_job_repo
-            .count_pending_sub_jobs(job_id)
+            .finish_sub_job_if_reported(&mut tx, &sub_job_id, !quarantine)
             .await?;
-        debug!("Pending sub jobs: {}", pending_sub_jobs);
 
-        // Update the job status if all sub jobs are completed
-        if pending_sub_jobs == 0 {
-            debug!("All sub jobs completed for job_id: {}", job_id);
+        // The job is finished once all its sub jobs are, notify the client only once
+        let job_status = job_service::finish_job_if_done(&self.state, &mut tx, job_id).await?;
+
+        tx.commit().await?;
 
+        self.state.job_events.publish(result_event);
+        if let Some(status) = sub_job_status {
+            self.state.job_events.publish(JobEvent::SubJob {
+                job_id,
+                sub_job_id,
+                status,
+            });
+        }
+        if let Some(status) = job_status {
+            info!("Job {} finished: {:?}", job_id, status);
             self.state
-                .job_repo
-                .update_job_status(job_id, JobStatus::Completed)
-                .await?;
+                .job_events
+                .publish(JobEvent::Job { job_id, status });
         }
 
         Ok(())
     }
 
-    async fn run(&self, content: Vec<u8>) -> Result<()> {
-        let content_str = String::from_utf8(content)?;
-
-        debug!("Received message: {}", content_str);
-
-        let (job_id, result_message) = self.parse_message(&content_str).await?;
+    async fn run(&self, delivery: Delivery) -> Result<()> {
+        debug!(
+            "Received message: {} bytes of {:?}",
+            delivery.body.len(),
+            delivery.content_type
+        );
+
+        let (job_id, result_message) = self.parse_message(&delivery).await?;
+
+        let signature_status =
+            verify_signature(&self.state, &result_message.worker_name, &delivery).await?;
+        if signature_status != SignatureStatus::Valid {
+            warn!(
+                "Result from worker {} failed signature verification: {:?}",
+                result_message.worker_name, signature_status
+            );
+            if self.state.signature_policy == SignaturePolicy::Reject {
+                return Err(InvalidMessage(format!(
+                    "Rejected result with {:?} signature",
+                    signature_status
+                ))
+                .into());
+            }
+        }
 
-        self.process_message(job_id, result_message).await?;
+        self.process_message(job_id, result_message, signature_status)
+            .await?;
 
         Ok(())
     }
 }
 
 #[async_trait]
-impl AsyncConsumer for DataConsumer {
-    async fn consume(
-        &mut self,
-        channel: &Channel,
-        deliver: Deliver,
-        _basic_properties: BasicProperties,
-        content: Vec<u8>,
-    ) {
-        match self.run(content).await {
-            Ok(_) => {
-                info!("Processed message successfully");
-                // Ack message only if processed successfully
-                let args = BasicAckArguments::new(deliver.delivery_tag(), false);
-                channel.basic_ack(args).await.unwrap();
-                debug!("Acked message");
-            }
-            Err(e) => {
-                error!("Error processing message: {:?}", e);
-            }
+impl MessageHandler for DataConsumer {
+    async fn handle(&self, delivery: Delivery) -> Acknowledgement {
+        let message_id = delivery.message_id.clone();
+        let result = self.run(delivery).await;
+        if result.is_ok() {
+            info!("Processed message successfully");
         }
+
+        // Ack message only if processed successfully
+        self.redeliveries
+            .acknowledge(message_id.as_deref(), result)
+            .await
     }
 }
@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use crate::{
+    events::JobEvent,
+    job_service,
+    queue::{
+        redelivery::{InvalidMessage, Redeliveries},
+        signature::SignaturePolicy,
+    },
+    state::AppState,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use rabbitmq::{
+    Acknowledgement, DeadLetter, Delivery, Envelope, JobMessage, Message, MessageHandler,
+};
+use tracing::{debug, info};
+
+/// Consumes jobs dead-lettered from the worker queues, e.g. expired while the worker was offline
+pub struct DeadLetterConsumer {
+    state: Arc<AppState>,
+    redeliveries: Redeliveries,
+}
+
+impl DeadLetterConsumer {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self {
+            state,
+            redeliveries: Redeliveries::default(),
+        }
+    }
+
+    async fn parse_message(&self, delivery: &Delivery) -> Result<(JobMessage, DeadLetter)> {
+        let dead_letter = delivery
+            .dead_letter
+            .clone()
+            .ok_or_else(|| InvalidMessage("Message was not dead-lettered".to_string()))?;
+
+        match Envelope::from_delivery(delivery).map(|envelope| envelope.message) {
+            Ok(Message::WorkerJob { payload, .. }) => Ok((payload, dead_letter)),
+            Ok(_) => Err(InvalidMessage("Received unexpected message".to_string()).into()),
+            Err(e) => Err(InvalidMessage(format!("Error parsing message: {}", e)).into()),
+        }
+    }
+
+    // Worker queues are named after the workers
+    #[tracing::instrument(skip(self, job_message, dead_letter), fields(worker_name = %dead_letter.queue))]
+    async fn process_message(
+        &self,
+        job_message: JobMessage,
+        dead_letter: DeadLetter,
+    ) -> Result<()> {
+        info!(
+            "Worker missed sub job {} ({})",
+            job_message.sub_job_id, dead_letter.reason
+        );
+
+        self.state
+            .missed_sub_job_repo
+            .record_missed_sub_job(
+                job_message.sub_job_id,
+                &dead_letter.queue,
+                &dead_letter.reason,
+            )
+            .await?;
+
+        // The missing result no longer holds the sub job, e.g. no worker took it at all
+        let job_id = job_message.job_id;
+        let sub_job_id = job_message.sub_job_id;
+        let count_unverified = self.state.signature_policy != SignaturePolicy::Quarantine;
+
+        let mut tx = self.state.job_repo.begin().await?;
+        self.state.job_repo.lock_job(&mut tx, job_id).await?;
+        let sub_job_status = self
+            .state
+            .sub_job_repo
+            .finish_sub_job_if_reported(&mut tx, &sub_job_id, count_unverified)
+            .await?;
+        let job_status = job_service::finish_job_if_done(&self.state, &mut tx, job_id).await?;
+        tx.commit().await?;
+
+        if let Some(status) = sub_job_status {
+            info!("Sub job {} finished after a miss: {:?}", sub_job_id, status);
+            self.state.job_events.publish(JobEvent::SubJob {
+                job_id,
+                sub_job_id,
+                status,
+            });
+        }
+        if let Some(status) = job_status {
+            info!("Job {} finished: {:?}", job_id, status);
+            self.state
+                .job_events
+                .publish(JobEvent::Job { job_id, status });
+        }
+
+        Ok(())
+    }
+
+    async fn run(&self, delivery: Delivery) -> Result<()> {
+        debug!(
+            "Received message: {} bytes of {:?}",
+            delivery.body.len(),
+            delivery.content_type
+        );
+
+        let (job_message, dead_letter) = self.parse_message(&delivery).await?;
+
+        self.process_message(job_message, dead_letter).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MessageHandler for DeadLetterConsumer {
+    async fn handle(&self, delivery: Delivery) -> Acknowledgement {
+        let message_id = delivery.message_id.clone();
+        let result = self.run(delivery).await;
+        if result.is_ok() {
+            debug!("Processed message successfully");
+        }
+
+        // Ack message only if processed successfully
+        self.redeliveries
+            .acknowledge(message_id.as_deref(), result)
+            .await
+    }
+}
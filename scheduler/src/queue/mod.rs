@@ -1,2 +1,5 @@
 pub mod data_consumer;
+pub mod dead_letter_consumer;
+pub mod redelivery;
+pub mod signature;
 pub mod status_consumer;
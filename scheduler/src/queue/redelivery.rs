@@ -0,0 +1,104 @@
+//! Acknowledgement of the consumed messages. Messages that can never be processed are dropped,
+//! failures that may pass, e.g. while the database is unavailable, are requeued a bounded number of times.
+
+use std::{collections::HashMap, fmt, sync::Mutex, time::Duration};
+
+use anyhow::Error;
+use rabbitmq::Acknowledgement;
+use tokio::time::sleep;
+use tracing::{error, warn};
+
+// Deliveries still failing after this many attempts are dropped, with the delays that's about 15 minutes
+const MAX_DELIVERY_ATTEMPTS: u32 = 20;
+// Delay before requeueing, doubled after each failed attempt. The consumer waits meanwhile,
+// so an outage doesn't spin through the queue.
+const RETRY_MIN_DELAY: Duration = Duration::from_secs(1);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Message that can never be processed, e.g. malformed or with a rejected signature
+#[derive(Debug)]
+pub struct InvalidMessage(pub String);
+
+impl fmt::Display for InvalidMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for InvalidMessage {}
+
+/// Failed attempts of the deliveries being retried, by message id
+#[derive(Default)]
+pub struct Redeliveries {
+    attempts: Mutex<HashMap<String, u32>>,
+}
+
+impl Redeliveries {
+    /// Ack the processed delivery, drop invalid ones and requeue the others after a delay
+    pub async fn acknowledge(
+        &self,
+        message_id: Option<&str>,
+        result: Result<(), Error>,
+    ) -> Acknowledgement {
+        let e = match result {
+            Ok(()) => {
+                self.forget(message_id);
+                return Acknowledgement::Ack;
+            }
+            Err(e) => e,
+        };
+
+        if is_permanent(&e) {
+            error!("Dropping message that can't be processed: {:?}", e);
+            self.forget(message_id);
+            return Acknowledgement::Nack { requeue: false };
+        }
+        let Some(message_id) = message_id else {
+            error!("Dropping message without id, it can't be retried: {:?}", e);
+            return Acknowledgement::Nack { requeue: false };
+        };
+
+        let attempt = {
+            let mut attempts = self.attempts.lock().unwrap();
+            let attempt = attempts.entry(message_id.to_string()).or_default();
+            *attempt += 1;
+            *attempt
+        };
+        if attempt >= MAX_DELIVERY_ATTEMPTS {
+            error!(
+                "Dropping message {} after {} failed attempts: {:?}",
+                message_id, attempt, e
+            );
+            self.forget(Some(message_id));
+            return Acknowledgement::Nack { requeue: false };
+        }
+
+        let delay = RETRY_MIN_DELAY
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(RETRY_MAX_DELAY);
+        warn!(
+            "Failed to process message {} (attempt {}), requeueing in {:?}: {:?}",
+            message_id, attempt, delay, e
+        );
+        sleep(delay).await;
+
+        Acknowledgement::Nack { requeue: true }
+    }
+
+    fn forget(&self, message_id: Option<&str>) {
+        if let Some(message_id) = message_id {
+            self.attempts.lock().unwrap().remove(message_id);
+        }
+    }
+}
+
+/// Invalid messages and constraint violations, e.g. a result of an unknown job, fail on every attempt
+fn is_permanent(e: &Error) -> bool {
+    if e.is::<InvalidMessage>() {
+        return true;
+    }
+    match e.downcast_ref::<sqlx::Error>() {
+        Some(sqlx::Error::Database(db_error)) => db_error.constraint().is_some(),
+        _ => false,
+    }
+}
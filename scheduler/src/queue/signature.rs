@@ -0,0 +1,54 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use rabbitmq::{signing, Delivery};
+
+use crate::{data_repository::SignatureStatus, state::AppState};
+
+/// What to do with worker messages not signed by the key registered for the worker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignaturePolicy {
+    /// Process the messages, only record the signature status of the results
+    Accept,
+    /// Store the results without completing their sub jobs, ignore the statuses
+    Quarantine,
+    /// Drop the messages
+    Reject,
+}
+
+impl FromStr for SignaturePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "accept" => Ok(SignaturePolicy::Accept),
+            "quarantine" => Ok(SignaturePolicy::Quarantine),
+            "reject" => Ok(SignaturePolicy::Reject),
+            _ => Err(format!("Unknown signature policy: {}", s)),
+        }
+    }
+}
+
+/// Check the delivery signature against the key registered for the worker
+pub async fn verify_signature(
+    state: &AppState,
+    worker_name: &str,
+    delivery: &Delivery,
+) -> Result<SignatureStatus> {
+    let Some(signature) = &delivery.signature else {
+        return Ok(SignatureStatus::Unsigned);
+    };
+
+    let Some(public_key) = state.worker_key_repo.get_public_key(worker_name).await? else {
+        return Ok(SignatureStatus::UnknownKey);
+    };
+    // Keys are validated when registered
+    let public_key = signing::decode_public_key(&public_key)
+        .map_err(|e| anyhow!("Invalid key registered for {}: {}", worker_name, e))?;
+
+    if signing::verify(&public_key, &delivery.body, signature) {
+        Ok(SignatureStatus::Valid)
+    } else {
+        Ok(SignatureStatus::Invalid)
+    }
+}
@@ -1,34 +1,41 @@
 use std::sync::Arc;
 
-use crate::state::AppState;
-use amqprs::{
-    channel::{BasicAckArguments, Channel},
-    consumer::AsyncConsumer,
-    BasicProperties, Deliver,
+use crate::{
+    data_repository::SignatureStatus,
+    events::JobEvent,
+    queue::{
+        redelivery::{InvalidMessage, Redeliveries},
+        signature::{verify_signature, SignaturePolicy},
+    },
+    state::AppState,
+    sub_job_repository::SubJobStatus,
 };
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use async_trait::async_trait;
-use rabbitmq::{Message, StatusMessage, WorkerStatus, WorkerStatusDetails};
-use serde_json;
-use tracing::{debug, error, info};
+use rabbitmq::{
+    Acknowledgement, Delivery, Envelope, Message, MessageHandler, StatusMessage, WorkerStatus,
+    WorkerStatusDetails,
+};
+use tracing::{debug, info, warn};
 
 pub struct StatusConsumer {
     state: Arc<AppState>,
+    redeliveries: Redeliveries,
 }
 
 impl StatusConsumer {
     pub fn new(state: Arc<AppState>) -> Self {
-        Self { state }
+        Self {
+            state,
+            redeliveries: Redeliveries::default(),
+        }
     }
 
-    async fn parse_message(&self, content_str: &str) -> Result<StatusMessage> {
-        match serde_json::from_str::<Message>(content_str) {
+    async fn parse_message(&self, delivery: &Delivery) -> Result<StatusMessage> {
+        match Envelope::from_delivery(delivery).map(|envelope| envelope.message) {
             Ok(Message::WorkerStatus { status }) => Ok(status),
-            Ok(_) => Err(anyhow!("Received unexpected message")),
-            Err(e) => {
-                error!("Error parsing message: {:?}", e);
-                Err(e.into())
-            }
+            Ok(_) => Err(InvalidMessage("Received unexpected message".to_string()).into()),
+            Err(e) => Err(InvalidMessage(format!("Error parsing message: {}", e)).into()),
         }
     }
 
@@ -48,6 +55,14 @@ impl StatusConsumer {
                     )
                     .await?;
 
+                // Keep the latest baseline, it's attached to the worker results
+                if let Some(calibration) = status.calibration {
+                    self.state
+                        .worker_repo
+                        .update_worker_calibration(&status_message.worker_name, calibration)
+                        .await?;
+                }
+
                 // Create or remove worker topics based on worker status
                 match status.worker_status {
                     WorkerStatus::Online => {
@@ -65,16 +80,56 @@ impl StatusConsumer {
                 }
             }
             WorkerStatusDetails::Job(job_details) => {
-                let job_id = job_details.map(|j| j.job_id);
+                // The worker took the sub job, it's processing until the first result
+                if let Some(job_details) = &job_details {
+                    self.state
+                        .acknowledged_sub_job_repo
+                        .record_acknowledged_sub_job(
+                            job_details.sub_job_id,
+                            &status_message.worker_name,
+                            job_details.run_id,
+                            status_message.timestamp,
+                        )
+                        .await?;
+                    if self
+                        .state
+                        .sub_job_repo
+                        .mark_sub_job_processing(&job_details.sub_job_id)
+                        .await?
+                    {
+                        self.state.job_events.publish(JobEvent::SubJob {
+                            job_id: job_details.job_id,
+                            sub_job_id: job_details.sub_job_id,
+                            status: SubJobStatus::Processing,
+                        });
+                    }
+                    self.state
+                        .job_repo
+                        .mark_job_processing(job_details.job_id)
+                        .await?;
+                }
+
+                let job = job_details.map(|j| (j.job_id, j.sub_job_id));
                 self.state
                     .worker_repo
-                    .update_worker_job(status_message.worker_name, job_id, status_message.timestamp)
+                    .update_worker_job(status_message.worker_name, job, status_message.timestamp)
                     .await?;
             }
-            WorkerStatusDetails::Heartbeat => {
+            // Only streamed to the job events, the result holds the complete logs
+            WorkerStatusDetails::Progress(progress) => {
+                self.state.job_events.publish(JobEvent::Progress {
+                    worker_name: status_message.worker_name,
+                    progress,
+                });
+            }
+            WorkerStatusDetails::Heartbeat(host_metrics) => {
                 self.state
                     .worker_repo
-                    .update_worker_heartbeat(status_message.worker_name, status_message.timestamp)
+                    .update_worker_heartbeat(
+                        status_message.worker_name,
+                        host_metrics,
+                        status_message.timestamp,
+                    )
                     .await?;
             }
         }
@@ -82,12 +137,35 @@ impl StatusConsumer {
         Ok(())
     }
 
-    async fn run(&self, content: Vec<u8>) -> Result<()> {
-        let content_str = String::from_utf8(content)?;
+    async fn run(&self, delivery: Delivery) -> Result<()> {
+        debug!(
+            "Received message: {} bytes of {:?}",
+            delivery.body.len(),
+            delivery.content_type
+        );
 
-        debug!("Received message: {}", content_str);
+        let status_message = self.parse_message(&delivery).await?;
 
-        let status_message = self.parse_message(&content_str).await?;
+        let signature_status =
+            verify_signature(&self.state, &status_message.worker_name, &delivery).await?;
+        if signature_status != SignatureStatus::Valid {
+            warn!(
+                "Status from worker {} failed signature verification: {:?}",
+                status_message.worker_name, signature_status
+            );
+            match self.state.signature_policy {
+                SignaturePolicy::Accept => {}
+                // Statuses aren't stored, so quarantine only skips them
+                SignaturePolicy::Quarantine => return Ok(()),
+                SignaturePolicy::Reject => {
+                    return Err(InvalidMessage(format!(
+                        "Rejected status with {:?} signature",
+                        signature_status
+                    ))
+                    .into())
+                }
+            }
+        }
 
         self.process_message(status_message).await?;
 
@@ -96,25 +174,17 @@ impl StatusConsumer {
 }
 
 #[async_trait]
-impl AsyncConsumer for StatusConsumer {
-    async fn consume(
-        &mut self,
-        channel: &Channel,
-        deliver: Deliver,
-        _basic_properties: BasicProperties,
-        content: Vec<u8>,
-    ) {
-        match self.run(content).await {
-            Ok(_) => {
-                debug!("Processed message successfully");
-                // Ack message only if processed successfully
-                let args = BasicAckArguments::new(deliver.delivery_tag(), false);
-                channel.basic_ack(args).await.unwrap();
-                debug!("Acked message");
-            }
-            Err(e) => {
-                error!("Error processing message: {:?}", e);
-            }
+impl MessageHandler for StatusConsumer {
+    async fn handle(&self, delivery: Delivery) -> Acknowledgement {
+        let message_id = delivery.message_id.clone();
+        let result = self.run(delivery).await;
+        if result.is_ok() {
+            debug!("Processed message successfully");
         }
+
+        // Ack message only if processed successfully
+        self.redeliveries
+            .acknowledge(message_id.as_deref(), result)
+            .await
     }
 }
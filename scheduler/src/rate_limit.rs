@@ -0,0 +1,292 @@
+use std::{env, str::FromStr, time::Duration};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use sqlx::PgConnection;
+use tracing::{error, info};
+
+use crate::{
+    api::api_response::*, api_key_repository::ApiKey, job_repository::JobUsage, state::AppState,
+};
+
+// Concurrent jobs stuck pending (e.g. no worker took them) stop counting after this time
+const ACTIVE_JOB_WINDOW: TimeDelta = TimeDelta::hours(1);
+const JOBS_WINDOW: TimeDelta = TimeDelta::hours(1);
+const BYTES_WINDOW: TimeDelta = TimeDelta::days(1);
+// A job takes about two minutes, ask the client to check again well before
+const ACTIVE_JOB_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Limits of the jobs created by an API key or targeting a host, `None` is unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    pub jobs_per_hour: Option<i64>,
+    pub bytes_per_day: Option<i64>,
+    pub concurrent_jobs: Option<i64>,
+}
+
+/// Default limits, API keys can override the per-key ones
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RateLimits {
+    pub per_key: Limits,
+    pub per_host: Limits,
+}
+
+/// Why a job was refused and when the client may try again
+#[derive(Debug)]
+pub struct LimitExceeded {
+    pub reason: String,
+    pub retry_after: Duration,
+}
+
+impl RateLimits {
+    /// Load the limits from the `RATE_LIMIT_KEY_*` and `RATE_LIMIT_HOST_*` env variables
+    pub fn from_env() -> Result<Self, String> {
+        Ok(Self {
+            per_key: Limits::from_env("RATE_LIMIT_KEY")?,
+            per_host: Limits::from_env("RATE_LIMIT_HOST")?,
+        })
+    }
+
+    /// Per-key limits, overridden by the limits set on the key
+    pub fn for_key(&self, api_key: &ApiKey) -> Limits {
+        Limits {
+            jobs_per_hour: api_key.jobs_per_hour.or(self.per_key.jobs_per_hour),
+            bytes_per_day: api_key.bytes_per_day.or(self.per_key.bytes_per_day),
+            concurrent_jobs: api_key.concurrent_jobs.or(self.per_key.concurrent_jobs),
+        }
+    }
+}
+
+impl Limits {
+    fn from_env(prefix: &str) -> Result<Self, String> {
+        Ok(Self {
+            jobs_per_hour: parse_env(&format!("{}_JOBS_PER_HOUR", prefix))?,
+            bytes_per_day: parse_env(&format!("{}_BYTES_PER_DAY", prefix))?,
+            concurrent_jobs: parse_env(&format!("{}_CONCURRENT_JOBS", prefix))?,
+        })
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        *self == Limits::default()
+    }
+
+    /// Check whether one more job downloading `estimated_bytes` fits in the limits
+    pub fn check(
+        &self,
+        usage: &JobUsage,
+        estimated_bytes: i64,
+        now: DateTime<Utc>,
+    ) -> Result<(), LimitExceeded> {
+        if let Some(limit) = self.concurrent_jobs {
+            if usage.active_jobs >= limit {
+                return Err(LimitExceeded {
+                    reason: format!("{} concurrent jobs", limit),
+                    retry_after: ACTIVE_JOB_RETRY_AFTER,
+                });
+            }
+        }
+        if let Some(limit) = self.jobs_per_hour {
+            if usage.jobs_last_hour >= limit {
+                return Err(LimitExceeded {
+                    reason: format!("{} jobs per hour", limit),
+                    retry_after: retry_after(usage.oldest_job_last_hour, JOBS_WINDOW, now),
+                });
+            }
+        }
+        if let Some(limit) = self.bytes_per_day {
+            if usage.bytes_last_day + estimated_bytes > limit {
+                return Err(LimitExceeded {
+                    reason: format!("{} bytes per day", limit),
+                    retry_after: retry_after(usage.oldest_job_last_day, BYTES_WINDOW, now),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Check the limits of the API key and of the target host before creating a job.
+/// Usage is counted from the jobs table, so the limits hold across restarts. The usage stays
+/// locked until the end of the transaction, the job must be created in it.
+pub async fn check_rate_limits(
+    state: &AppState,
+    conn: &mut PgConnection,
+    api_key: &ApiKey,
+    host: &str,
+    estimated_bytes: i64,
+) -> Result<(), ApiResponse<()>> {
+    let checks = [
+        (
+            "API key",
+            state.rate_limits.for_key(api_key),
+            Some(api_key.id),
+            None,
+        ),
+        ("host", state.rate_limits.per_host, None, Some(host)),
+    ];
+
+    for (subject, limits, api_key_id, host) in checks {
+        if limits.is_unlimited() {
+            continue;
+        }
+
+        state
+            .job_repo
+            .lock_job_usage(conn, api_key_id, host)
+            .await
+            .map_err(|e| {
+                error!("Failed to lock job usage: {:?}", e);
+                internal_server_error("Failed to check rate limits")
+            })?;
+
+        let now = Utc::now();
+        let usage = state
+            .job_repo
+            .get_job_usage(
+                conn,
+                api_key_id,
+                host,
+                now - JOBS_WINDOW,
+                now - BYTES_WINDOW,
+                now - ACTIVE_JOB_WINDOW,
+            )
+            .await
+            .map_err(|e| {
+                error!("Failed to get job usage from the database: {:?}", e);
+                internal_server_error("Failed to check rate limits")
+            })?;
+
+        limits
+            .check(&usage, estimated_bytes, now)
+            .map_err(|exceeded| {
+                info!(
+                    "Job of API key {} for host {} over the {} limit: {}",
+                    api_key.id,
+                    host.unwrap_or_default(),
+                    subject,
+                    exceeded.reason
+                );
+                too_many_requests(
+                    format!("Limit of {} per {} exceeded", exceeded.reason, subject),
+                    exceeded.retry_after,
+                )
+            })?;
+    }
+
+    Ok(())
+}
+
+/// Time until the oldest job leaves the window, its usage is freed then
+fn retry_after(
+    oldest_job: Option<DateTime<Utc>>,
+    window: TimeDelta,
+    now: DateTime<Utc>,
+) -> Duration {
+    let until_freed = match oldest_job {
+        // Negative once the job left the window
+        Some(oldest_job) => (oldest_job + window - now).to_std().unwrap_or_default(),
+        None => window.to_std().unwrap_or_default(),
+    };
+    until_freed.max(Duration::from_secs(1))
+}
+
+fn parse_env<T: FromStr>(name: &str) -> Result<Option<T>, String> {
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("Invalid {} value: {}", name, value)),
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 10, 23, 12, 0, 0).unwrap()
+    }
+
+    fn limits() -> Limits {
+        Limits {
+            jobs_per_hour: Some(10),
+            bytes_per_day: Some(1000),
+            concurrent_jobs: Some(2),
+        }
+    }
+
+    #[test]
+    fn accepts_jobs_within_limits() {
+        let usage = JobUsage {
+            jobs_last_hour: 9,
+            bytes_last_day: 900,
+            active_jobs: 1,
+            ..Default::default()
+        };
+
+        assert!(limits().check(&usage, 100, now()).is_ok());
+        assert!(Limits::default()
+            .check(&JobUsage::default(), i64::MAX, now())
+            .is_ok());
+    }
+
+    #[test]
+    fn rejects_concurrent_jobs() {
+        let usage = JobUsage {
+            active_jobs: 2,
+            ..Default::default()
+        };
+
+        let exceeded = limits().check(&usage, 0, now()).unwrap_err();
+        assert_eq!(exceeded.reason, "2 concurrent jobs");
+        assert_eq!(exceeded.retry_after, ACTIVE_JOB_RETRY_AFTER);
+    }
+
+    #[test]
+    fn rejects_jobs_per_hour_until_oldest_leaves_window() {
+        let usage = JobUsage {
+            jobs_last_hour: 10,
+            oldest_job_last_hour: Some(now() - TimeDelta::minutes(45)),
+            ..Default::default()
+        };
+
+        let exceeded = limits().check(&usage, 0, now()).unwrap_err();
+        assert_eq!(exceeded.reason, "10 jobs per hour");
+        assert_eq!(exceeded.retry_after, Duration::from_secs(15 * 60));
+    }
+
+    #[test]
+    fn rejects_bytes_per_day_including_new_job() {
+        let usage = JobUsage {
+            bytes_last_day: 900,
+            oldest_job_last_day: Some(now() - TimeDelta::hours(20)),
+            ..Default::default()
+        };
+
+        assert!(limits().check(&usage, 100, now()).is_ok());
+        let exceeded = limits().check(&usage, 101, now()).unwrap_err();
+        assert_eq!(exceeded.reason, "1000 bytes per day");
+        assert_eq!(exceeded.retry_after, Duration::from_secs(4 * 60 * 60));
+    }
+
+    #[test]
+    fn retry_after_is_bounded() {
+        // No job in the window, e.g. a single job over the bytes limit
+        assert_eq!(
+            retry_after(None, JOBS_WINDOW, now()),
+            Duration::from_secs(60 * 60)
+        );
+        // The oldest job just left the window
+        assert_eq!(
+            retry_after(Some(now() - JOBS_WINDOW), JOBS_WINDOW, now()),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            retry_after(Some(now() - TimeDelta::days(2)), JOBS_WINDOW, now()),
+            Duration::from_secs(1)
+        );
+    }
+}
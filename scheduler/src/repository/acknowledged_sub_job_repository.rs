@@ -0,0 +1,40 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct AcknowledgedSubJobRepository {
+    pool: PgPool,
+}
+
+impl AcknowledgedSubJobRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Record that the worker took the sub job, repeated records are ignored
+    pub async fn record_acknowledged_sub_job(
+        &self,
+        sub_job_id: Uuid,
+        worker_name: &str,
+        run_id: Uuid,
+        acknowledged_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO acknowledged_sub_jobs (sub_job_id, worker_name, run_id, acknowledged_at)
+            SELECT $1, $2, $3, $4
+            WHERE EXISTS (SELECT 1 FROM sub_jobs WHERE id = $1)
+            ON CONFLICT (sub_job_id, worker_name) DO NOTHING
+            "#,
+            sub_job_id,
+            worker_name,
+            run_id,
+            acknowledged_at,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{prelude::FromRow, PgPool};
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct ApiKeyRepository {
+    pool: PgPool,
+}
+
+#[derive(Serialize, Debug, Clone, FromRow)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub name: String,
+    pub key_prefix: String,
+    pub scopes: Vec<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// Limits overriding the defaults, `None` uses the default
+    pub jobs_per_hour: Option<i64>,
+    pub bytes_per_day: Option<i64>,
+    pub concurrent_jobs: Option<i64>,
+}
+
+impl ApiKeyRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn create_api_key(
+        &self,
+        name: &str,
+        key_hash: &str,
+        key_prefix: &str,
+        scopes: &[String],
+    ) -> Result<ApiKey, sqlx::Error> {
+        let api_key = sqlx::query_as!(
+            ApiKey,
+            r#"
+            INSERT INTO api_keys (name, key_hash, key_prefix, scopes)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, name, key_prefix, scopes, created_at, revoked_at, jobs_per_hour, bytes_per_day, concurrent_jobs
+            "#,
+            name,
+            key_hash,
+            key_prefix,
+            scopes,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(api_key)
+    }
+
+    /// Key with the hash, unless it was revoked
+    pub async fn get_active_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, sqlx::Error> {
+        let api_key = sqlx::query_as!(
+            ApiKey,
+            r#"
+            SELECT id, name, key_prefix, scopes, created_at, revoked_at, jobs_per_hour, bytes_per_day, concurrent_jobs
+            FROM api_keys
+            WHERE key_hash = $1 AND revoked_at IS NULL
+            "#,
+            key_hash
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(api_key)
+    }
+
+    /// Key with the id, unless it was revoked
+    pub async fn get_active_api_key_by_id(&self, id: Uuid) -> Result<Option<ApiKey>, sqlx::Error> {
+        let api_key = sqlx::query_as!(
+            ApiKey,
+            r#"
+            SELECT id, name, key_prefix, scopes, created_at, revoked_at, jobs_per_hour, bytes_per_day, concurrent_jobs
+            FROM api_keys
+            WHERE id = $1 AND revoked_at IS NULL
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(api_key)
+    }
+
+    pub async fn list_api_keys(&self) -> Result<Vec<ApiKey>, sqlx::Error> {
+        let api_keys = sqlx::query_as!(
+            ApiKey,
+            r#"
+            SELECT id, name, key_prefix, scopes, created_at, revoked_at, jobs_per_hour, bytes_per_day, concurrent_jobs
+            FROM api_keys
+            ORDER BY created_at
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(api_keys)
+    }
+
+    /// Revoke the key, the jobs it created keep referencing it.
+    /// Returns false if there is no active key with the id.
+    pub async fn revoke_api_key(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+        let revoked = sqlx::query!(
+            r#"
+            UPDATE api_keys
+            SET revoked_at = CURRENT_TIMESTAMP
+            WHERE id = $1 AND revoked_at IS NULL
+            "#,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(revoked.rows_affected() == 1)
+    }
+
+    /// Override the default limits of the key, `None` restores the default.
+    /// Returns false if there is no key with the id.
+    pub async fn update_limits(
+        &self,
+        id: Uuid,
+        jobs_per_hour: Option<i64>,
+        bytes_per_day: Option<i64>,
+        concurrent_jobs: Option<i64>,
+    ) -> Result<bool, sqlx::Error> {
+        let updated = sqlx::query!(
+            r#"
+            UPDATE api_keys
+            SET jobs_per_hour = $2, bytes_per_day = $3, concurrent_jobs = $4
+            WHERE id = $1
+            "#,
+            id,
+            jobs_per_hour,
+            bytes_per_day,
+            concurrent_jobs
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(updated.rows_affected() == 1)
+    }
+}
@@ -1,20 +1,48 @@
-use rabbitmq::ResultMessage;
+use rabbitmq::{HostMetrics, ResultMessage};
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
+use sqlx::{prelude::Type, PgConnection, PgPool};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
+// Thresholds above which the worker host is considered the bottleneck of the measurement
+const WORKER_BOUND_CPU_PERCENT: f64 = 90.0;
+const WORKER_BOUND_LINK_UTILISATION: f64 = 0.9;
+
 #[derive(Clone)]
 pub struct DataRepository {
+    // Results are saved in the transaction finishing their sub job, nothing uses the pool yet
+    #[allow(dead_code)]
     pool: PgPool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Outcome of checking the result signature against the key registered for the worker
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type, ToSchema)]
+#[sqlx(type_name = "signature_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureStatus {
+    Valid,
+    Invalid,
+    Unsigned,
+    UnknownKey,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct BmsData {
     pub id: Uuid,
     pub worker_name: Option<String>,
+    #[schema(value_type = Object)]
     pub download: serde_json::Value,
+    #[schema(value_type = Object)]
     pub ping: serde_json::Value,
+    #[schema(value_type = Object)]
     pub head: serde_json::Value,
+    #[schema(value_type = Option<Object>)]
+    pub host_metrics: Option<serde_json::Value>,
+    pub is_worker_bound: Option<bool>,
+    #[schema(value_type = Option<Object>)]
+    pub worker_calibration: Option<serde_json::Value>,
+    pub relative_download_speed: Option<f64>,
+    pub signature_status: Option<SignatureStatus>,
 }
 
 impl DataRepository {
@@ -32,8 +60,28 @@ impl DataRepository {
         }
     }
 
-    pub async fn save_data(&self, result: ResultMessage) -> Result<(), sqlx::Error> {
-        sqlx::query!(
+    /// Check if the worker host was saturated (CPU or NIC) during the measurement
+    fn is_worker_bound(host_metrics: &HostMetrics) -> bool {
+        let link_saturated = host_metrics.network.as_ref().is_some_and(|network| {
+            network.link_speed_mbps.is_some_and(|link_speed_mbps| {
+                let link_bytes_per_sec = link_speed_mbps as f64 * 1_000_000.0 / 8.0;
+                network.rx_bytes_per_sec >= link_bytes_per_sec * WORKER_BOUND_LINK_UTILISATION
+            })
+        });
+
+        host_metrics.cpu_usage_percent >= WORKER_BOUND_CPU_PERCENT || link_saturated
+    }
+
+    /// Save the result, returns false if the result with the same run_id was already saved
+    pub async fn save_data(
+        &self,
+        conn: &mut PgConnection,
+        result: ResultMessage,
+        signature_status: SignatureStatus,
+    ) -> Result<bool, sqlx::Error> {
+        let is_worker_bound = result.host_metrics.as_ref().map(Self::is_worker_bound);
+
+        let saved = sqlx::query!(
             r#"
             INSERT INTO worker_data (
                 id,
@@ -43,9 +91,17 @@ impl DataRepository {
                 is_success,
                 download,
                 ping,
-                head
+                head,
+                host_metrics,
+                is_worker_bound,
+                signature_status,
+                worker_calibration
+            )
+            VALUES (
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11,
+                (SELECT calibration FROM workers WHERE worker_name = $4::VARCHAR)
             )
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (id) DO NOTHING
             "#,
             result.run_id,
             result.job_id,
@@ -54,11 +110,16 @@ impl DataRepository {
             result.is_success,
             self.result_to_json(result.download_result),
             self.result_to_json(result.ping_result),
-            self.result_to_json(result.head_result)
+            self.result_to_json(result.head_result),
+            result
+                .host_metrics
+                .and_then(|m| serde_json::to_value(m).ok()),
+            is_worker_bound,
+            signature_status as SignatureStatus
         )
-        .execute(&self.pool)
+        .execute(conn)
         .await?;
 
-        Ok(())
+        Ok(saved.rows_affected() == 1)
     }
 }
@@ -1,17 +1,23 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::{
     prelude::{FromRow, Type},
     types::Json,
-    PgPool,
+    PgConnection, PgPool, Postgres, Transaction,
 };
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-use super::data_repository::BmsData;
+use super::{data_repository::BmsData, sub_job_repository::SubJobProgress};
 
-#[derive(Debug, Type)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize, ToSchema)]
 #[sqlx(type_name = "job_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
 pub enum JobStatus {
     Pending,
+    // Named running in the job_status enum
+    #[sqlx(rename = "running")]
+    #[serde(rename = "running")]
     Processing,
     Completed,
     Failed,
@@ -22,16 +28,20 @@ pub struct JobRepository {
     pool: PgPool,
 }
 
-#[derive(Serialize, Debug, FromRow, Type)]
+#[derive(Serialize, Debug, FromRow, Type, ToSchema)]
 pub struct JobWithData {
     pub id: Uuid,
     pub url: Option<String>,
     pub routing_key: Option<String>,
+    pub provider_id: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+    #[schema(value_type = Option<JobDetails>)]
     pub details: Option<serde_json::Value>,
+    #[schema(value_type = Vec<BmsData>)]
     pub data: Vec<Json<BmsData>>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct JobDetails {
     pub start_range: u64,
     pub end_range: u64,
@@ -50,6 +60,100 @@ pub struct Job {
     pub routing_key: String,
     pub status: JobStatus,
     pub details: JobDetails,
+    pub tags: Vec<String>,
+}
+
+/// Job to insert
+#[derive(Debug)]
+pub struct NewJob<'a> {
+    pub id: Uuid,
+    pub url: &'a str,
+    pub routing_key: &'a str,
+    pub status: JobStatus,
+    pub details: serde_json::Value,
+    pub tags: &'a [String],
+    /// Key that created the job
+    pub api_key_id: Uuid,
+    pub host: &'a str,
+    pub estimated_bytes: i64,
+    pub callback_url: Option<&'a str>,
+    pub callback_secret: Option<&'a str>,
+    pub batch_id: Option<Uuid>,
+    pub provider_id: Option<Uuid>,
+}
+
+#[derive(Serialize, Debug, FromRow, ToSchema)]
+pub struct JobProgress {
+    pub id: Uuid,
+    pub url: String,
+    pub routing_key: String,
+    pub status: JobStatus,
+    pub tags: Vec<String>,
+    pub details: JobDetails,
+    /// Key that created the job
+    pub api_key_id: Option<Uuid>,
+    /// Notified once the job is finished
+    pub callback_url: Option<String>,
+    /// Set when the job was created with POST /jobs/batch
+    pub batch_id: Option<Uuid>,
+    /// Registered provider serving the URL
+    pub provider_id: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    #[schema(value_type = Vec<SubJobProgress>)]
+    pub sub_jobs: Vec<Json<SubJobProgress>>,
+}
+
+/// Filters of the job listing, `None` matches every job
+#[derive(Debug, Default)]
+pub struct JobFilter {
+    pub status: Option<JobStatus>,
+    pub url: Option<String>,
+    pub host: Option<String>,
+    pub routing_key: Option<String>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    /// Jobs having all of the tags
+    pub tags: Option<Vec<String>>,
+    pub batch_id: Option<Uuid>,
+    pub provider_id: Option<Uuid>,
+}
+
+/// Jobs created by an API key or targeting a host, counted towards the rate limits
+#[derive(Debug, Default, FromRow)]
+pub struct JobUsage {
+    pub jobs_last_hour: i64,
+    pub oldest_job_last_hour: Option<DateTime<Utc>>,
+    pub bytes_last_day: i64,
+    pub oldest_job_last_day: Option<DateTime<Utc>>,
+    /// Pending or running jobs
+    pub active_jobs: i64,
+}
+
+/// Position of the last listed job, the next page starts after it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JobCursor {
+    pub created_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+#[derive(Serialize, Debug, FromRow, ToSchema)]
+pub struct JobSummary {
+    pub id: Uuid,
+    pub url: String,
+    pub routing_key: String,
+    pub status: JobStatus,
+    pub tags: Vec<String>,
+    pub batch_id: Option<Uuid>,
+    pub provider_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub sub_jobs_total: i64,
+    pub sub_jobs_pending: i64,
+    pub sub_jobs_running: i64,
+    pub sub_jobs_completed: i64,
+    pub sub_jobs_failed: i64,
+    /// Median download speed of the successful results
+    pub download_speed: Option<f64>,
 }
 
 impl JobRepository {
@@ -57,28 +161,83 @@ impl JobRepository {
         Self { pool }
     }
 
+    /// Start a transaction for the methods taking a connection
+    pub async fn begin(&self) -> Result<Transaction<'static, Postgres>, sqlx::Error> {
+        self.pool.begin().await
+    }
+
+    /// Lock the job until the end of the transaction, so its results are finished one at a time
+    /// and each sees the sub jobs finished by the others
+    pub async fn lock_job(&self, conn: &mut PgConnection, job_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            SELECT id FROM jobs WHERE id = $1 FOR UPDATE
+            "#,
+            job_id,
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Lock the usage of the API key or of the host until the end of the transaction,
+    /// so the jobs are checked against the rate limits one at a time
+    pub async fn lock_job_usage(
+        &self,
+        conn: &mut PgConnection,
+        api_key_id: Option<Uuid>,
+        host: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        // Keys and hosts are locked in separate spaces, so their hashes can't collide
+        let (space, key) = match (api_key_id, host) {
+            (Some(api_key_id), _) => (1, api_key_id.to_string()),
+            (None, host) => (2, host.unwrap_or_default().to_string()),
+        };
+
+        sqlx::query!(
+            r#"
+            SELECT 1 as "locked!" FROM pg_advisory_xact_lock($1, hashtext($2))
+            "#,
+            space,
+            key,
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(())
+    }
+
     pub async fn create_job(
         &self,
-        job_id: Uuid,
-        url: String,
-        routing_key: &String,
-        status: JobStatus,
-        details: serde_json::Value,
+        conn: &mut PgConnection,
+        job: NewJob<'_>,
     ) -> Result<Job, sqlx::Error> {
         let job = sqlx::query_as!(
             Job,
             r#"
-            INSERT INTO jobs (id, url, routing_key, status, details)
-            VALUES ($1, $2, $3, $4, $5)
-            RETURNING id, url, routing_key, status as "status!: JobStatus", details as "details!: serde_json::Value"
+            INSERT INTO jobs (
+                id, url, routing_key, status, details, tags, api_key_id, host, estimated_bytes,
+                callback_url, callback_secret, batch_id, provider_id
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+            RETURNING id, url, routing_key, status as "status!: JobStatus", details as "details!: serde_json::Value", tags
             "#,
-            job_id,
-            url,
-            routing_key,
-            status as JobStatus,
-            details,
+            job.id,
+            job.url,
+            job.routing_key,
+            job.status as JobStatus,
+            job.details,
+            job.tags,
+            job.api_key_id,
+            job.host,
+            job.estimated_bytes,
+            job.callback_url,
+            job.callback_secret,
+            job.batch_id,
+            job.provider_id,
         )
-        .fetch_one(&self.pool)
+        .fetch_one(conn)
         .await?;
 
         Ok(job)
@@ -92,6 +251,8 @@ impl JobRepository {
                 jobs.id,
                 jobs.url,
                 jobs.routing_key,
+                jobs.provider_id,
+                jobs.created_at,
                 jobs.details,
                 COALESCE(
                     ARRAY_AGG(
@@ -100,7 +261,14 @@ impl JobRepository {
                             'worker_name', d.worker_name,
                             'download', d.download,
                             'ping', d.ping,
-                            'head', d.head
+                            'head', d.head,
+                            'host_metrics', d.host_metrics,
+                            'is_worker_bound', d.is_worker_bound,
+                            'worker_calibration', d.worker_calibration,
+                            'relative_download_speed',
+                                (d.download->>'download_speed')::float8
+                                / NULLIF((d.worker_calibration->>'download_speed')::float8, 0),
+                            'signature_status', d.signature_status
                         )
                     ) FILTER (WHERE d.id IS NOT NULL),
                     ARRAY[]::json[]
@@ -118,8 +286,221 @@ impl JobRepository {
         Ok(job)
     }
 
+    /// Jobs of the provider with their data, the newest first, whatever URL they targeted
+    pub async fn get_provider_jobs_with_data(
+        &self,
+        provider_id: Uuid,
+        created_after: Option<DateTime<Utc>>,
+        created_before: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<JobWithData>, sqlx::Error> {
+        let jobs = sqlx::query_as!(
+            JobWithData,
+            r#"
+            SELECT
+                jobs.id,
+                jobs.url,
+                jobs.routing_key,
+                jobs.provider_id,
+                jobs.created_at,
+                jobs.details,
+                COALESCE(
+                    ARRAY_AGG(
+                        JSON_BUILD_OBJECT(
+                            'id', d.id,
+                            'worker_name', d.worker_name,
+                            'download', d.download,
+                            'ping', d.ping,
+                            'head', d.head,
+                            'host_metrics', d.host_metrics,
+                            'is_worker_bound', d.is_worker_bound,
+                            'worker_calibration', d.worker_calibration,
+                            'relative_download_speed',
+                                (d.download->>'download_speed')::float8
+                                / NULLIF((d.worker_calibration->>'download_speed')::float8, 0),
+                            'signature_status', d.signature_status
+                        )
+                    ) FILTER (WHERE d.id IS NOT NULL),
+                    ARRAY[]::json[]
+                ) AS "data!: Vec<Json<BmsData>>"
+            FROM jobs
+            LEFT JOIN worker_data as d ON jobs.id = d.job_id
+            WHERE jobs.provider_id = $1
+                AND ($2::timestamptz IS NULL OR jobs.created_at >= $2)
+                AND ($3::timestamptz IS NULL OR jobs.created_at < $3)
+            GROUP BY jobs.id
+            ORDER BY jobs.created_at DESC, jobs.id DESC
+            LIMIT $4
+            "#,
+            provider_id,
+            created_after,
+            created_before,
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(jobs)
+    }
+
+    pub async fn get_job_progress(&self, job_id: Uuid) -> Result<JobProgress, sqlx::Error> {
+        self.fetch_job_progress(&mut *self.pool.acquire().await?, job_id)
+            .await
+    }
+
+    /// Same as `get_job_progress`, on the connection of a transaction
+    pub async fn fetch_job_progress(
+        &self,
+        conn: &mut PgConnection,
+        job_id: Uuid,
+    ) -> Result<JobProgress, sqlx::Error> {
+        let job = sqlx::query_as!(
+            JobProgress,
+            r#"
+            SELECT
+                jobs.id,
+                jobs.url,
+                jobs.routing_key,
+                jobs.status as "status!: JobStatus",
+                jobs.tags,
+                jobs.details as "details!: serde_json::Value",
+                jobs.api_key_id,
+                jobs.callback_url,
+                jobs.batch_id,
+                jobs.provider_id,
+                jobs.created_at,
+                jobs.updated_at,
+                COALESCE(
+                    (
+                        SELECT ARRAY_AGG(
+                            JSON_BUILD_OBJECT(
+                                'id', s.id,
+                                'status', s.status,
+                                'start_time', s.details->'start_time',
+                                'download_start_time', s.details->'donwload_start_time',
+                                'updated_at', s.updated_at,
+                                'acknowledged', ARRAY(
+                                    SELECT a.worker_name FROM acknowledged_sub_jobs a
+                                    WHERE a.sub_job_id = s.id ORDER BY a.acknowledged_at
+                                ),
+                                'running', ARRAY(
+                                    SELECT w.worker_name FROM workers w
+                                    WHERE w.sub_job_id = s.id ORDER BY w.worker_name
+                                ),
+                                'reported', ARRAY(
+                                    SELECT d.worker_name FROM worker_data d
+                                    WHERE d.sub_job_id = s.id ORDER BY d.id
+                                ),
+                                'missed', ARRAY(
+                                    SELECT m.worker_name FROM missed_sub_jobs m
+                                    WHERE m.sub_job_id = s.id ORDER BY m.created_at
+                                )
+                            )
+                            ORDER BY s.details->>'start_time'
+                        )
+                        FROM sub_jobs s
+                        WHERE s.job_id = jobs.id
+                    ),
+                    ARRAY[]::json[]
+                ) AS "sub_jobs!: Vec<Json<SubJobProgress>>"
+            FROM jobs
+            WHERE jobs.id = $1
+            "#,
+            job_id
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(job)
+    }
+
+    /// Usage of the API key or the host, whichever is set, since the start of the rate limit windows
+    pub async fn get_job_usage(
+        &self,
+        conn: &mut PgConnection,
+        api_key_id: Option<Uuid>,
+        host: Option<&str>,
+        jobs_since: DateTime<Utc>,
+        bytes_since: DateTime<Utc>,
+        active_since: DateTime<Utc>,
+    ) -> Result<JobUsage, sqlx::Error> {
+        let usage = sqlx::query_as!(
+            JobUsage,
+            r#"
+            SELECT
+                COUNT(*) FILTER (WHERE created_at > $3) as "jobs_last_hour!",
+                MIN(created_at) FILTER (WHERE created_at > $3) as oldest_job_last_hour,
+                COALESCE(SUM(estimated_bytes) FILTER (WHERE created_at > $4), 0)::bigint as "bytes_last_day!",
+                MIN(created_at) FILTER (WHERE created_at > $4) as oldest_job_last_day,
+                COUNT(*) FILTER (
+                    WHERE status IN ('pending', 'running') AND created_at > $5
+                ) as "active_jobs!"
+            FROM jobs
+            WHERE created_at > LEAST($3, $4, $5)
+                AND ($1::uuid IS NULL OR api_key_id = $1)
+                AND ($2::text IS NULL OR host = $2)
+            "#,
+            api_key_id,
+            host,
+            jobs_since,
+            bytes_since,
+            active_since,
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(usage)
+    }
+
+    /// Move the job to processing once a worker took one of its sub jobs, finished jobs keep their status
+    pub async fn mark_job_processing(&self, job_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE jobs
+            SET status = 'running'
+            WHERE id = $1 AND status = 'pending'
+            "#,
+            job_id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Finish the job once none of its sub jobs is pending or processing: completed if any sub job
+    /// completed, failed otherwise. Returns the status only to the caller that finished the job.
+    pub async fn finish_job_if_done(
+        &self,
+        conn: &mut PgConnection,
+        job_id: Uuid,
+    ) -> Result<Option<JobStatus>, sqlx::Error> {
+        let job = sqlx::query!(
+            r#"
+            UPDATE jobs
+            SET status = CASE
+                WHEN EXISTS (SELECT 1 FROM sub_jobs WHERE job_id = $1 AND status = 'completed')
+                THEN 'completed'::job_status
+                ELSE 'failed'::job_status
+            END
+            WHERE id = $1
+                AND status IN ('pending', 'running')
+                AND NOT EXISTS (
+                    SELECT 1 FROM sub_jobs WHERE job_id = $1 AND status IN ('pending', 'running')
+                )
+            RETURNING status as "status!: JobStatus"
+            "#,
+            job_id,
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(job.map(|job| job.status))
+    }
+
     pub async fn update_job_status(
         &self,
+        conn: &mut PgConnection,
         job_id: Uuid,
         status: JobStatus,
     ) -> Result<(), sqlx::Error> {
@@ -132,8 +513,80 @@ impl JobRepository {
             status as JobStatus,
             job_id,
         )
-        .execute(&self.pool)
+        .execute(conn)
         .await?;
         Ok(())
     }
+
+    /// List the jobs matching the filter, ordered by creation time and id, starting after the cursor
+    pub async fn list_jobs(
+        &self,
+        filter: &JobFilter,
+        cursor: Option<JobCursor>,
+        ascending: bool,
+        limit: i64,
+    ) -> Result<Vec<JobSummary>, sqlx::Error> {
+        let jobs = sqlx::query_as!(
+            JobSummary,
+            r#"
+            SELECT
+                jobs.id,
+                jobs.url,
+                jobs.routing_key,
+                jobs.status as "status!: JobStatus",
+                jobs.tags,
+                jobs.batch_id,
+                jobs.provider_id,
+                jobs.created_at as "created_at!",
+                (SELECT COUNT(*) FROM sub_jobs s WHERE s.job_id = jobs.id) as "sub_jobs_total!",
+                (SELECT COUNT(*) FROM sub_jobs s WHERE s.job_id = jobs.id AND s.status = 'pending') as "sub_jobs_pending!",
+                (SELECT COUNT(*) FROM sub_jobs s WHERE s.job_id = jobs.id AND s.status = 'running') as "sub_jobs_running!",
+                (SELECT COUNT(*) FROM sub_jobs s WHERE s.job_id = jobs.id AND s.status = 'completed') as "sub_jobs_completed!",
+                (SELECT COUNT(*) FROM sub_jobs s WHERE s.job_id = jobs.id AND s.status = 'failed') as "sub_jobs_failed!",
+                (
+                    SELECT PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY (d.download->>'download_speed')::float8)
+                    FROM worker_data d
+                    WHERE d.job_id = jobs.id AND d.is_success
+                ) as "download_speed"
+            FROM jobs
+            WHERE ($1::job_status IS NULL OR jobs.status = $1)
+                AND ($2::text IS NULL OR jobs.url = $2)
+                AND ($3::text IS NULL OR jobs.host = LOWER($3))
+                AND ($4::text IS NULL OR jobs.routing_key = $4)
+                AND ($5::timestamptz IS NULL OR jobs.created_at >= $5)
+                AND ($6::timestamptz IS NULL OR jobs.created_at < $6)
+                AND ($7::text[] IS NULL OR jobs.tags @> $7)
+                AND ($8::uuid IS NULL OR jobs.batch_id = $8)
+                AND ($9::uuid IS NULL OR jobs.provider_id = $9)
+                AND (
+                    $10::timestamptz IS NULL
+                    OR ($12 AND (jobs.created_at, jobs.id) > ($10, $11::uuid))
+                    OR (NOT $12 AND (jobs.created_at, jobs.id) < ($10, $11::uuid))
+                )
+            ORDER BY
+                CASE WHEN $12 THEN jobs.created_at END ASC,
+                CASE WHEN $12 THEN jobs.id END ASC,
+                jobs.created_at DESC,
+                jobs.id DESC
+            LIMIT $13
+            "#,
+            filter.status as Option<JobStatus>,
+            filter.url,
+            filter.host,
+            filter.routing_key,
+            filter.created_after,
+            filter.created_before,
+            filter.tags.as_deref(),
+            filter.batch_id,
+            filter.provider_id,
+            cursor.map(|cursor| cursor.created_at),
+            cursor.map(|cursor| cursor.id),
+            ascending,
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(jobs)
+    }
 }
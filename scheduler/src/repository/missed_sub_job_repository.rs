@@ -0,0 +1,37 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct MissedSubJobRepository {
+    pool: PgPool,
+}
+
+impl MissedSubJobRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Record that the worker didn't take the sub job in time, repeated records are ignored
+    pub async fn record_missed_sub_job(
+        &self,
+        sub_job_id: Uuid,
+        worker_name: &str,
+        reason: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO missed_sub_jobs (sub_job_id, worker_name, reason)
+            SELECT $1, $2, $3
+            WHERE EXISTS (SELECT 1 FROM sub_jobs WHERE id = $1)
+            ON CONFLICT (sub_job_id, worker_name) DO NOTHING
+            "#,
+            sub_job_id,
+            worker_name,
+            reason,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
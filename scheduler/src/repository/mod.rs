@@ -1,11 +1,25 @@
+pub mod acknowledged_sub_job_repository;
+pub mod api_key_repository;
 pub mod data_repository;
 pub mod job_repository;
+pub mod missed_sub_job_repository;
+pub mod provider_repository;
+pub mod schedule_repository;
 pub mod sub_job_repository;
 pub mod topic_repository;
+pub mod webhook_delivery_repository;
+pub mod worker_key_repository;
 pub mod worker_repository;
 
+pub use self::acknowledged_sub_job_repository::AcknowledgedSubJobRepository;
+pub use self::api_key_repository::ApiKeyRepository;
 pub use self::data_repository::DataRepository;
 pub use self::job_repository::JobRepository;
+pub use self::missed_sub_job_repository::MissedSubJobRepository;
+pub use self::provider_repository::ProviderRepository;
+pub use self::schedule_repository::ScheduleRepository;
 pub use self::sub_job_repository::SubJobRepository;
 pub use self::topic_repository::TopicRepository;
+pub use self::webhook_delivery_repository::WebhookDeliveryRepository;
+pub use self::worker_key_repository::WorkerKeyRepository;
 pub use self::worker_repository::WorkerRepository;
@@ -0,0 +1,149 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{prelude::FromRow, PgPool};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct ProviderRepository {
+    pool: PgPool,
+}
+
+#[derive(Serialize, Debug, Clone, FromRow, ToSchema)]
+pub struct Provider {
+    pub id: Uuid,
+    /// Filecoin miner ID, e.g. f01234
+    pub miner_id: String,
+    pub organization: Option<String>,
+    /// URLs the provider serves retrievals from, may change over time
+    pub retrieval_endpoints: Vec<String>,
+    pub region: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Provider to insert or the new values of an existing one
+#[derive(Debug)]
+pub struct NewProvider<'a> {
+    pub miner_id: &'a str,
+    pub organization: Option<&'a str>,
+    pub retrieval_endpoints: &'a [String],
+    pub region: Option<&'a str>,
+}
+
+/// Filters of the provider listing, `None` matches every provider
+#[derive(Debug, Default)]
+pub struct ProviderFilter {
+    pub organization: Option<String>,
+    pub region: Option<String>,
+}
+
+impl ProviderRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn create_provider(
+        &self,
+        provider: NewProvider<'_>,
+    ) -> Result<Provider, sqlx::Error> {
+        let provider = sqlx::query_as!(
+            Provider,
+            r#"
+            INSERT INTO providers (miner_id, organization, retrieval_endpoints, region)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, miner_id, organization, retrieval_endpoints, region, created_at, updated_at
+            "#,
+            provider.miner_id,
+            provider.organization,
+            provider.retrieval_endpoints,
+            provider.region,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(provider)
+    }
+
+    /// Replace the provider, `None` when it doesn't exist. Its jobs keep referencing it.
+    pub async fn update_provider(
+        &self,
+        id: Uuid,
+        provider: NewProvider<'_>,
+    ) -> Result<Option<Provider>, sqlx::Error> {
+        let provider = sqlx::query_as!(
+            Provider,
+            r#"
+            UPDATE providers
+            SET miner_id = $2, organization = $3, retrieval_endpoints = $4, region = $5
+            WHERE id = $1
+            RETURNING id, miner_id, organization, retrieval_endpoints, region, created_at, updated_at
+            "#,
+            id,
+            provider.miner_id,
+            provider.organization,
+            provider.retrieval_endpoints,
+            provider.region,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(provider)
+    }
+
+    pub async fn get_provider(&self, id: Uuid) -> Result<Option<Provider>, sqlx::Error> {
+        let provider = sqlx::query_as!(
+            Provider,
+            r#"
+            SELECT id, miner_id, organization, retrieval_endpoints, region, created_at, updated_at
+            FROM providers
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(provider)
+    }
+
+    /// List the providers matching the filter, ordered by miner ID
+    pub async fn list_providers(
+        &self,
+        filter: &ProviderFilter,
+    ) -> Result<Vec<Provider>, sqlx::Error> {
+        let providers = sqlx::query_as!(
+            Provider,
+            r#"
+            SELECT id, miner_id, organization, retrieval_endpoints, region, created_at, updated_at
+            FROM providers
+            WHERE ($1::text IS NULL OR organization = $1)
+                AND ($2::text IS NULL OR region = $2)
+            ORDER BY miner_id
+            "#,
+            filter.organization,
+            filter.region,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(providers)
+    }
+
+    /// Delete the provider unless a job or a schedule references it, returns false otherwise
+    pub async fn delete_unreferenced_provider(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM providers
+            WHERE id = $1
+                AND NOT EXISTS (SELECT 1 FROM jobs WHERE provider_id = $1)
+                AND NOT EXISTS (SELECT 1 FROM schedules WHERE provider_id = $1)
+            "#,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
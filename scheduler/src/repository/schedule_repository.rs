@@ -0,0 +1,349 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    prelude::{FromRow, Type},
+    PgPool,
+};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct ScheduleRepository {
+    pool: PgPool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize, ToSchema)]
+#[sqlx(type_name = "schedule_run_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ScheduleRunStatus {
+    /// Job is being created
+    Pending,
+    Created,
+    Failed,
+}
+
+#[derive(Serialize, Debug, Clone, FromRow, ToSchema)]
+pub struct Schedule {
+    pub id: Uuid,
+    pub name: String,
+    pub url: String,
+    pub routing_key: String,
+    pub tags: Vec<String>,
+    pub callback_url: Option<String>,
+    #[serde(skip)]
+    pub callback_secret: Option<String>,
+    pub cron_expression: String,
+    pub enabled: bool,
+    /// Provider the jobs benchmark
+    pub provider_id: Option<Uuid>,
+    /// Key that created the schedule, the jobs are created with it
+    pub api_key_id: Uuid,
+    pub next_run_at: DateTime<Utc>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Schedule to insert or the new values of an existing one
+#[derive(Debug)]
+pub struct NewSchedule<'a> {
+    pub name: &'a str,
+    pub url: &'a str,
+    pub routing_key: &'a str,
+    pub tags: &'a [String],
+    pub callback_url: Option<&'a str>,
+    pub callback_secret: Option<&'a str>,
+    pub cron_expression: &'a str,
+    pub enabled: bool,
+    pub provider_id: Option<Uuid>,
+    pub next_run_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug, FromRow, ToSchema)]
+pub struct ScheduleRun {
+    pub id: Uuid,
+    /// Due time of the run
+    pub scheduled_for: DateTime<Utc>,
+    pub status: ScheduleRunStatus,
+    pub job_id: Option<Uuid>,
+    pub error: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl ScheduleRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn create_schedule(
+        &self,
+        schedule: NewSchedule<'_>,
+        api_key_id: Uuid,
+    ) -> Result<Schedule, sqlx::Error> {
+        let schedule = sqlx::query_as!(
+            Schedule,
+            r#"
+            INSERT INTO schedules (
+                name, url, routing_key, tags, callback_url, callback_secret, cron_expression,
+                enabled, next_run_at, api_key_id, provider_id
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            RETURNING id, name, url, routing_key, tags, callback_url, callback_secret,
+                cron_expression, enabled, provider_id, api_key_id, next_run_at, created_at, updated_at
+            "#,
+            schedule.name,
+            schedule.url,
+            schedule.routing_key,
+            schedule.tags,
+            schedule.callback_url,
+            schedule.callback_secret,
+            schedule.cron_expression,
+            schedule.enabled,
+            schedule.next_run_at,
+            api_key_id,
+            schedule.provider_id,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(schedule)
+    }
+
+    /// Replace the schedule, `None` when it doesn't exist or wasn't created by `api_key_id`
+    pub async fn update_schedule(
+        &self,
+        id: Uuid,
+        api_key_id: Option<Uuid>,
+        schedule: NewSchedule<'_>,
+    ) -> Result<Option<Schedule>, sqlx::Error> {
+        let schedule = sqlx::query_as!(
+            Schedule,
+            r#"
+            UPDATE schedules
+            SET name = $2, url = $3, routing_key = $4, tags = $5, callback_url = $6,
+                callback_secret = $7, cron_expression = $8, enabled = $9, next_run_at = $10,
+                provider_id = $11
+            WHERE id = $1 AND ($12::uuid IS NULL OR api_key_id = $12)
+            RETURNING id, name, url, routing_key, tags, callback_url, callback_secret,
+                cron_expression, enabled, provider_id, api_key_id, next_run_at, created_at, updated_at
+            "#,
+            id,
+            schedule.name,
+            schedule.url,
+            schedule.routing_key,
+            schedule.tags,
+            schedule.callback_url,
+            schedule.callback_secret,
+            schedule.cron_expression,
+            schedule.enabled,
+            schedule.next_run_at,
+            schedule.provider_id,
+            api_key_id,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(schedule)
+    }
+
+    /// Schedules created by `api_key_id`, all of them when it's `None`
+    pub async fn get_schedule(
+        &self,
+        id: Uuid,
+        api_key_id: Option<Uuid>,
+    ) -> Result<Option<Schedule>, sqlx::Error> {
+        let schedule = sqlx::query_as!(
+            Schedule,
+            r#"
+            SELECT id, name, url, routing_key, tags, callback_url, callback_secret,
+                cron_expression, enabled, provider_id, api_key_id, next_run_at, created_at, updated_at
+            FROM schedules
+            WHERE id = $1 AND ($2::uuid IS NULL OR api_key_id = $2)
+            "#,
+            id,
+            api_key_id,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(schedule)
+    }
+
+    pub async fn list_schedules(
+        &self,
+        api_key_id: Option<Uuid>,
+    ) -> Result<Vec<Schedule>, sqlx::Error> {
+        let schedules = sqlx::query_as!(
+            Schedule,
+            r#"
+            SELECT id, name, url, routing_key, tags, callback_url, callback_secret,
+                cron_expression, enabled, provider_id, api_key_id, next_run_at, created_at, updated_at
+            FROM schedules
+            WHERE $1::uuid IS NULL OR api_key_id = $1
+            ORDER BY created_at, id
+            "#,
+            api_key_id,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(schedules)
+    }
+
+    /// Delete the schedule with its history, the created jobs are kept
+    pub async fn delete_schedule(
+        &self,
+        id: Uuid,
+        api_key_id: Option<Uuid>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM schedules
+            WHERE id = $1 AND ($2::uuid IS NULL OR api_key_id = $2)
+            "#,
+            id,
+            api_key_id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Enabled schedules due at `now`, the most overdue first
+    pub async fn get_due_schedules(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<Schedule>, sqlx::Error> {
+        let schedules = sqlx::query_as!(
+            Schedule,
+            r#"
+            SELECT id, name, url, routing_key, tags, callback_url, callback_secret,
+                cron_expression, enabled, provider_id, api_key_id, next_run_at, created_at, updated_at
+            FROM schedules
+            WHERE enabled AND next_run_at <= $1
+            ORDER BY next_run_at
+            LIMIT $2
+            "#,
+            now,
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(schedules)
+    }
+
+    /// Move the schedule to its next due time, unless it was changed since it was due.
+    /// Schedules never due again are disabled.
+    pub async fn set_next_run(
+        &self,
+        id: Uuid,
+        due_at: DateTime<Utc>,
+        next_run_at: Option<DateTime<Utc>>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE schedules
+            SET next_run_at = COALESCE($3, next_run_at), enabled = enabled AND $3 IS NOT NULL
+            WHERE id = $1 AND next_run_at = $2
+            "#,
+            id,
+            due_at,
+            next_run_at,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Start the run of the schedule due at `scheduled_for`.
+    /// Returns `None` when the run already exists, so it isn't repeated.
+    pub async fn create_run(
+        &self,
+        schedule_id: Uuid,
+        scheduled_for: DateTime<Utc>,
+    ) -> Result<Option<Uuid>, sqlx::Error> {
+        let run = sqlx::query!(
+            r#"
+            INSERT INTO schedule_runs (schedule_id, scheduled_for)
+            VALUES ($1, $2)
+            ON CONFLICT (schedule_id, scheduled_for) DO NOTHING
+            RETURNING id
+            "#,
+            schedule_id,
+            scheduled_for,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(run.map(|run| run.id))
+    }
+
+    pub async fn finish_run(
+        &self,
+        id: Uuid,
+        status: ScheduleRunStatus,
+        job_id: Option<Uuid>,
+        error: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE schedule_runs
+            SET status = $2, job_id = $3, error = $4
+            WHERE id = $1
+            "#,
+            id,
+            status as ScheduleRunStatus,
+            job_id,
+            error,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Fail the runs left pending by a scheduler that stopped while creating their job
+    pub async fn fail_stale_runs(&self, started_before: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE schedule_runs
+            SET status = 'failed', error = 'Interrupted before the job was created'
+            WHERE status = 'pending' AND created_at < $1
+            "#,
+            started_before,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// History of the schedule, the latest runs first
+    pub async fn list_runs(
+        &self,
+        schedule_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<ScheduleRun>, sqlx::Error> {
+        let runs = sqlx::query_as!(
+            ScheduleRun,
+            r#"
+            SELECT id, scheduled_for, status as "status!: ScheduleRunStatus", job_id, error,
+                created_at, updated_at
+            FROM schedule_runs
+            WHERE schedule_id = $1
+            ORDER BY scheduled_for DESC
+            LIMIT $2
+            "#,
+            schedule_id,
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(runs)
+    }
+}
@@ -1,13 +1,22 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use sqlx::{
     prelude::{FromRow, Type},
-    PgPool,
+    PgConnection, PgPool,
 };
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-#[derive(Debug, Type)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize, ToSchema)]
 #[sqlx(type_name = "sub_job_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
 pub enum SubJobStatus {
     Pending,
+    // Named running in the sub_job_status enum
+    #[sqlx(rename = "running")]
+    #[serde(rename = "running")]
     Processing,
     Completed,
     Failed,
@@ -19,6 +28,24 @@ pub enum SubJobType {
     CombinedDHP,
 }
 
+/// Sub job with the workers that took it, are running it and reported its result
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct SubJobProgress {
+    pub id: Uuid,
+    pub status: SubJobStatus,
+    pub start_time: Option<DateTime<Utc>>,
+    pub download_start_time: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    /// Workers that took the sub job from their queue
+    pub acknowledged: Vec<String>,
+    /// Workers measuring right now
+    pub running: Vec<String>,
+    /// Workers whose result was saved
+    pub reported: Vec<String>,
+    /// Workers whose queue the sub job expired in
+    pub missed: Vec<String>,
+}
+
 #[derive(Clone)]
 pub struct SubJobRepository {
     pool: PgPool,
@@ -34,6 +61,13 @@ pub struct SubJob {
     pub details: serde_json::Value,
 }
 
+/// Sub job finished by the sweeper
+#[derive(Debug, FromRow)]
+pub struct FinishedSubJob {
+    pub id: Uuid,
+    pub status: SubJobStatus,
+}
+
 impl SubJobRepository {
     pub fn new(pool: PgPool) -> Self {
         Self { pool }
@@ -66,40 +100,180 @@ impl SubJobRepository {
         Ok(sub_job)
     }
 
-    pub async fn update_sub_job_status(
+    /// Start of the last pending or running sub job of each online worker, by the routing keys
+    /// the worker receives
+    pub async fn get_last_sub_job_start_by_worker(
         &self,
-        sub_job_id: &Uuid,
-        status: SubJobStatus,
-    ) -> Result<(), sqlx::Error> {
-        sqlx::query!(
+    ) -> Result<HashMap<String, DateTime<Utc>>, sqlx::Error> {
+        let workers = sqlx::query!(
+            r#"
+            SELECT
+                workers.worker_name,
+                MAX((sub_jobs.details->>'start_time')::timestamptz) as "last_start!"
+            FROM sub_jobs
+            JOIN jobs ON jobs.id = sub_jobs.job_id
+            JOIN workers ON workers.status = 'online' AND (
+                workers.worker_name = jobs.routing_key
+                OR EXISTS (
+                    SELECT 1 FROM worker_topics
+                    JOIN topics ON topics.id = worker_topics.topic_id
+                    WHERE worker_topics.worker_name = workers.worker_name
+                        AND topics.name = jobs.routing_key
+                )
+            )
+            WHERE sub_jobs.status IN ('pending', 'running')
+                AND sub_jobs.details->>'start_time' IS NOT NULL
+            GROUP BY workers.worker_name
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(workers
+            .into_iter()
+            .map(|worker| (worker.worker_name, worker.last_start))
+            .collect())
+    }
+
+    /// Fail the pending and running sub jobs of the job, returns their ids
+    pub async fn fail_unfinished_sub_jobs(
+        &self,
+        conn: &mut PgConnection,
+        job_id: Uuid,
+    ) -> Result<Vec<Uuid>, sqlx::Error> {
+        let sub_jobs = sqlx::query!(
             r#"
             UPDATE sub_jobs
-            SET status = $1
-            WHERE id = $2
+            SET status = 'failed'
+            WHERE job_id = $1 AND status IN ('pending', 'running')
+            RETURNING id
+            "#,
+            job_id,
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(sub_jobs.into_iter().map(|sub_job| sub_job.id).collect())
+    }
+
+    /// Move the sub job to processing once a worker took it, finished sub jobs keep their status.
+    /// Returns whether the status changed.
+    pub async fn mark_sub_job_processing(&self, sub_job_id: &Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE sub_jobs
+            SET status = 'running'
+            WHERE id = $1 AND status = 'pending'
             "#,
-            status as SubJobStatus,
             sub_job_id,
         )
         .execute(&self.pool)
         .await?;
 
-        Ok(())
+        Ok(result.rows_affected() > 0)
     }
 
-    pub async fn count_pending_sub_jobs(&self, job_id: Uuid) -> Result<i64, sqlx::Error> {
-        let sub_job_type = SubJobType::CombinedDHP;
-        let count = sqlx::query!(
+    /// Finish the sub job once every worker that took it reported: completed if any counted result
+    /// succeeded, failed otherwise. Unverified results count only when `count_unverified` is set.
+    /// Returns the status only to the caller that finished the sub job.
+    pub async fn finish_sub_job_if_reported(
+        &self,
+        conn: &mut PgConnection,
+        sub_job_id: &Uuid,
+        count_unverified: bool,
+    ) -> Result<Option<SubJobStatus>, sqlx::Error> {
+        let sub_job = sqlx::query!(
             r#"
-            SELECT COUNT(*) as count
+            UPDATE sub_jobs
+            SET status = CASE
+                WHEN EXISTS (
+                    SELECT 1 FROM worker_data d
+                    WHERE d.sub_job_id = $1
+                        AND d.is_success
+                        AND ($2 OR d.signature_status = 'valid')
+                )
+                THEN 'completed'::sub_job_status
+                ELSE 'failed'::sub_job_status
+            END
+            WHERE id = $1
+                AND status IN ('pending', 'running')
+                AND NOT EXISTS (
+                    SELECT 1 FROM acknowledged_sub_jobs a
+                    WHERE a.sub_job_id = $1
+                        AND NOT EXISTS (
+                            SELECT 1 FROM worker_data d
+                            WHERE d.sub_job_id = $1 AND d.worker_name = a.worker_name
+                        )
+                )
+            RETURNING status as "status!: SubJobStatus"
+            "#,
+            sub_job_id,
+            count_unverified,
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(sub_job.map(|sub_job| sub_job.status))
+    }
+
+    /// Jobs with sub jobs still pending or processing although they started before `started_before`
+    pub async fn get_jobs_with_stale_sub_jobs(
+        &self,
+        started_before: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<Uuid>, sqlx::Error> {
+        let jobs = sqlx::query!(
+            r#"
+            SELECT DISTINCT job_id
             FROM sub_jobs
-            WHERE job_id = $1 AND type = $2 AND status = 'pending'
+            WHERE status IN ('pending', 'running')
+                AND (details->>'start_time')::timestamptz < $1
+            LIMIT $2
+            "#,
+            started_before,
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(jobs.into_iter().map(|job| job.job_id).collect())
+    }
+
+    /// Finish the sub jobs of the job that started before `started_before` without every result,
+    /// e.g. the worker crashed: completed if any counted result succeeded, failed otherwise
+    pub async fn finish_stale_sub_jobs(
+        &self,
+        conn: &mut PgConnection,
+        job_id: Uuid,
+        started_before: DateTime<Utc>,
+        count_unverified: bool,
+    ) -> Result<Vec<FinishedSubJob>, sqlx::Error> {
+        let sub_jobs = sqlx::query_as!(
+            FinishedSubJob,
+            r#"
+            UPDATE sub_jobs
+            SET status = CASE
+                WHEN EXISTS (
+                    SELECT 1 FROM worker_data d
+                    WHERE d.sub_job_id = sub_jobs.id
+                        AND d.is_success
+                        AND ($3 OR d.signature_status = 'valid')
+                )
+                THEN 'completed'::sub_job_status
+                ELSE 'failed'::sub_job_status
+            END
+            WHERE job_id = $1
+                AND status IN ('pending', 'running')
+                AND (details->>'start_time')::timestamptz < $2
+            RETURNING id, status as "status!: SubJobStatus"
             "#,
             job_id,
-            sub_job_type as SubJobType,
+            started_before,
+            count_unverified,
         )
-        .fetch_one(&self.pool)
+        .fetch_all(conn)
         .await?;
 
-        Ok(count.count.unwrap())
+        Ok(sub_jobs)
     }
 }
@@ -44,6 +44,20 @@ impl TopicRepository {
         .execute(&self.pool)
         .await?;
 
+        // Remove topics the worker is no longer interested in
+        sqlx::query!(
+            r#"
+            DELETE FROM worker_topics
+            WHERE worker_name = $1 AND topic_id NOT IN (
+                SELECT id FROM topics WHERE name = ANY($2::text[])
+            )
+            "#,
+            worker_name,
+            &topics
+        )
+        .execute(&self.pool)
+        .await?;
+
         Ok(())
     }
 
@@ -61,4 +75,26 @@ impl TopicRepository {
 
         Ok(())
     }
+
+    /// Online workers receiving the jobs published with the routing key, a topic or a worker name
+    pub async fn get_workers_for_routing_key(
+        &self,
+        routing_key: &str,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let workers = sqlx::query!(
+            r#"
+            SELECT DISTINCT workers.worker_name
+            FROM workers
+            LEFT JOIN worker_topics ON worker_topics.worker_name = workers.worker_name
+            LEFT JOIN topics ON topics.id = worker_topics.topic_id
+            WHERE workers.status = 'online' AND (topics.name = $1 OR workers.worker_name = $1)
+            ORDER BY workers.worker_name
+            "#,
+            routing_key
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(workers.into_iter().map(|w| w.worker_name).collect())
+    }
 }
@@ -0,0 +1,138 @@
+use chrono::{DateTime, Utc};
+use sqlx::{prelude::FromRow, PgConnection, PgPool};
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct WebhookDeliveryRepository {
+    pool: PgPool,
+}
+
+/// Delivery claimed by the dispatcher, with the secret of its job
+#[derive(Debug, FromRow)]
+pub struct DueWebhookDelivery {
+    pub id: Uuid,
+    pub job_id: Uuid,
+    pub url: String,
+    pub payload: serde_json::Value,
+    pub attempts: i32,
+    pub secret: Option<String>,
+}
+
+impl WebhookDeliveryRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Queue the notification of the job, repeated notifications are ignored
+    pub async fn create_delivery(
+        &self,
+        conn: &mut PgConnection,
+        job_id: Uuid,
+        url: &str,
+        payload: serde_json::Value,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO webhook_deliveries (job_id, url, payload)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (job_id) DO NOTHING
+            "#,
+            job_id,
+            url,
+            payload,
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Claim the pending deliveries that are due. Claimed deliveries are postponed by the lease,
+    /// so they are retried if the scheduler stops before recording the attempt.
+    pub async fn claim_due_deliveries(
+        &self,
+        limit: i64,
+        lease_until: DateTime<Utc>,
+    ) -> Result<Vec<DueWebhookDelivery>, sqlx::Error> {
+        let deliveries = sqlx::query_as!(
+            DueWebhookDelivery,
+            r#"
+            UPDATE webhook_deliveries
+            SET next_attempt_at = $2
+            FROM jobs
+            WHERE jobs.id = webhook_deliveries.job_id
+                AND webhook_deliveries.id IN (
+                    SELECT id FROM webhook_deliveries
+                    WHERE status = 'pending' AND next_attempt_at <= NOW()
+                    ORDER BY next_attempt_at
+                    LIMIT $1
+                    FOR UPDATE SKIP LOCKED
+                )
+            RETURNING
+                webhook_deliveries.id,
+                webhook_deliveries.job_id,
+                webhook_deliveries.url,
+                webhook_deliveries.payload,
+                webhook_deliveries.attempts,
+                jobs.callback_secret as secret
+            "#,
+            limit,
+            lease_until,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(deliveries)
+    }
+
+    pub async fn mark_delivered(&self, id: Uuid, status_code: i32) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE webhook_deliveries
+            SET
+                status = 'delivered',
+                attempts = attempts + 1,
+                last_status_code = $2,
+                last_error = NULL,
+                delivered_at = NOW()
+            WHERE id = $1
+            "#,
+            id,
+            status_code,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record the failed attempt, the delivery is given up without a next attempt
+    pub async fn record_failed_attempt(
+        &self,
+        id: Uuid,
+        status_code: Option<i32>,
+        error: &str,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE webhook_deliveries
+            SET
+                status = CASE WHEN $4::timestamptz IS NULL THEN 'failed' ELSE 'pending' END::webhook_delivery_status,
+                attempts = attempts + 1,
+                last_status_code = $2,
+                last_error = $3,
+                next_attempt_at = COALESCE($4, next_attempt_at)
+            WHERE id = $1
+            "#,
+            id,
+            status_code,
+            error,
+            next_attempt_at,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
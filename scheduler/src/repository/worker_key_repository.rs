@@ -0,0 +1,68 @@
+use sqlx::PgPool;
+
+#[derive(Clone)]
+pub struct WorkerKeyRepository {
+    pool: PgPool,
+}
+
+impl WorkerKeyRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Base64 public key registered for the worker, if any
+    pub async fn get_public_key(&self, worker_name: &str) -> Result<Option<String>, sqlx::Error> {
+        let key = sqlx::query!(
+            r#"
+            SELECT public_key
+            FROM worker_keys
+            WHERE worker_name = $1
+            "#,
+            worker_name
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(key.map(|key| key.public_key))
+    }
+
+    /// Register the key, replaces the previous key of the worker
+    pub async fn upsert_public_key(
+        &self,
+        worker_name: &str,
+        public_key: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO worker_keys (worker_name, public_key)
+            VALUES ($1, $2)
+            ON CONFLICT (worker_name)
+            DO UPDATE SET
+                public_key = EXCLUDED.public_key,
+                updated_at = CURRENT_TIMESTAMP
+            "#,
+            worker_name,
+            public_key
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove the key, messages of the worker are no longer trusted.
+    /// Returns false if the worker had no key.
+    pub async fn delete_public_key(&self, worker_name: &str) -> Result<bool, sqlx::Error> {
+        let deleted = sqlx::query!(
+            r#"
+            DELETE FROM worker_keys
+            WHERE worker_name = $1
+            "#,
+            worker_name
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(deleted.rows_affected() == 1)
+    }
+}
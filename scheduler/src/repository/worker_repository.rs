@@ -1,5 +1,5 @@
 use chrono::{DateTime, Utc};
-use rabbitmq::WorkerStatus;
+use rabbitmq::{CalibrationResult, HostMetrics, WorkerStatus};
 use sqlx::PgPool;
 use uuid::Uuid;
 
@@ -13,6 +13,21 @@ impl WorkerRepository {
         Self { pool }
     }
 
+    pub async fn get_worker_status(&self, worker_name: &String) -> Result<String, sqlx::Error> {
+        let worker = sqlx::query!(
+            r#"
+            SELECT status
+            FROM workers
+            WHERE worker_name = $1
+            "#,
+            worker_name
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(worker.status.unwrap_or_default())
+    }
+
     pub async fn update_worker_status(
         &self,
         worker_name: &String,
@@ -21,15 +36,16 @@ impl WorkerRepository {
     ) -> Result<(), sqlx::Error> {
         sqlx::query!(
             r#"
-            INSERT INTO workers (worker_name, status, last_seen, job_id, started_at, shutdown_at)
-            VALUES ($1, $2, $3, $4, $5, $6)
+            INSERT INTO workers (worker_name, status, last_seen, job_id, sub_job_id, started_at, shutdown_at)
+            VALUES ($1, $2, $3, $4, $4, $5, $6)
             ON CONFLICT (worker_name)
             DO UPDATE SET
                 status = EXCLUDED.status,
                 last_seen = EXCLUDED.last_seen,
                 job_id = EXCLUDED.job_id,
+                sub_job_id = EXCLUDED.sub_job_id,
                 started_at = CASE
-                    WHEN EXCLUDED.status = 'online' THEN EXCLUDED.last_seen
+                    WHEN EXCLUDED.status = 'online' AND workers.status IS DISTINCT FROM 'online' THEN EXCLUDED.last_seen
                     ELSE workers.started_at
                 END,
                 shutdown_at = CASE
@@ -51,10 +67,32 @@ impl WorkerRepository {
         Ok(())
     }
 
+    pub async fn update_worker_calibration(
+        &self,
+        worker_name: &String,
+        calibration: CalibrationResult,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE workers
+            SET
+                calibration = $2
+            WHERE worker_name = $1
+            "#,
+            worker_name,
+            serde_json::to_value(calibration).ok()
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Set the job and sub job the worker is running, `None` once it finished
     pub async fn update_worker_job(
         &self,
         worker_name: String,
-        job_id: Option<Uuid>,
+        job: Option<(Uuid, Uuid)>,
         timestamp: DateTime<Utc>,
     ) -> Result<(), sqlx::Error> {
         sqlx::query!(
@@ -62,12 +100,14 @@ impl WorkerRepository {
             UPDATE workers
             SET
                 last_seen = $2,
-                job_id = $3
+                job_id = $3,
+                sub_job_id = $4
             WHERE worker_name = $1 AND workers.last_seen < $2
             "#,
             worker_name,
             timestamp,
-            job_id
+            job.map(|(job_id, _)| job_id),
+            job.map(|(_, sub_job_id)| sub_job_id),
         )
         .execute(&self.pool)
         .await?;
@@ -78,17 +118,20 @@ impl WorkerRepository {
     pub async fn update_worker_heartbeat(
         &self,
         worker_name: String,
+        host_metrics: Option<HostMetrics>,
         timestamp: DateTime<Utc>,
     ) -> Result<(), sqlx::Error> {
         sqlx::query!(
             r#"
             UPDATE workers
             SET
-                last_seen = $2
+                last_seen = $2,
+                host_metrics = $3
             WHERE worker_name = $1 AND workers.last_seen < $2
             "#,
             worker_name,
-            timestamp
+            timestamp,
+            host_metrics.and_then(|m| serde_json::to_value(m).ok())
         )
         .execute(&self.pool)
         .await?;
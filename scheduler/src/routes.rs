@@ -1,12 +1,215 @@
-use crate::api::{create_job, get_data, healthcheck};
+use crate::api::openapi::{ApiDoc, API_PREFIX};
+use crate::api::{
+    create_batch, create_job, create_provider, create_schedule, delete_provider, delete_schedule,
+    delete_worker_key, get_data, get_job, get_job_events, get_provider, get_schedule, healthcheck,
+    list_jobs, list_providers, list_schedule_runs, list_schedules, update_provider,
+    update_schedule, update_worker_key, update_worker_topics,
+};
+use crate::auth::{self, require_scope, ApiScope};
 use crate::state::AppState;
-use axum::routing::{get, post};
+use axum::middleware::from_fn_with_state;
+use axum::routing::{get, post, put};
 use axum::Router;
 use std::sync::Arc;
+use tower::ServiceBuilder;
+use tower_http::trace::TraceLayer;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
 
+/// The API under its prefix, with the unversioned healthcheck and the OpenAPI document
+pub fn create_app(state: Arc<AppState>) -> Router {
+    // Healthcheck stays unversioned for the load balancer and container probes
+    Router::new()
+        .nest(API_PREFIX, create_routes())
+        .route("/healthcheck", get(healthcheck::handle))
+        .merge(SwaggerUi::new("/swagger-ui").url("/openapi.json", ApiDoc::openapi()))
+        .layer(
+            ServiceBuilder::new()
+                .layer(TraceLayer::new_for_http())
+                // Routes check the scopes of the resolved API key
+                .layer(from_fn_with_state(state.clone(), auth::authenticate)),
+        )
+        .with_state(state)
+}
+
+/// Routes of the API, every one of them must be in `ApiDoc`
 pub fn create_routes() -> Router<Arc<AppState>> {
     Router::new()
+        // Also under the prefix, so the API and its OpenAPI document are complete on their own
         .route("/healthcheck", get(healthcheck::handle))
-        .route("/data", get(get_data::handle))
-        .route("/job", post(create_job::handle))
+        .route(
+            "/data",
+            get(get_data::handle)
+                .route_layer(from_fn_with_state(ApiScope::DataRead, require_scope)),
+        )
+        .route(
+            "/job",
+            post(create_job::handle)
+                .route_layer(from_fn_with_state(ApiScope::JobsCreate, require_scope)),
+        )
+        .route(
+            "/job/:job_id",
+            get(get_job::handle).route_layer(from_fn_with_state(ApiScope::JobsRead, require_scope)),
+        )
+        .route(
+            "/job/:job_id/events",
+            get(get_job_events::handle)
+                .route_layer(from_fn_with_state(ApiScope::JobsRead, require_scope)),
+        )
+        .route(
+            "/jobs",
+            get(list_jobs::handle)
+                .route_layer(from_fn_with_state(ApiScope::JobsRead, require_scope)),
+        )
+        .route(
+            "/jobs/batch",
+            post(create_batch::handle)
+                .route_layer(from_fn_with_state(ApiScope::JobsCreate, require_scope)),
+        )
+        .route(
+            "/providers",
+            get(list_providers::handle)
+                .route_layer(from_fn_with_state(ApiScope::JobsRead, require_scope))
+                .merge(
+                    post(create_provider::handle)
+                        .route_layer(from_fn_with_state(ApiScope::Admin, require_scope)),
+                ),
+        )
+        .route(
+            "/providers/:provider_id",
+            get(get_provider::handle)
+                .route_layer(from_fn_with_state(ApiScope::JobsRead, require_scope))
+                .merge(
+                    put(update_provider::handle)
+                        .delete(delete_provider::handle)
+                        .route_layer(from_fn_with_state(ApiScope::Admin, require_scope)),
+                ),
+        )
+        .route(
+            "/schedules",
+            get(list_schedules::handle)
+                .route_layer(from_fn_with_state(ApiScope::JobsRead, require_scope))
+                .merge(
+                    post(create_schedule::handle)
+                        .route_layer(from_fn_with_state(ApiScope::JobsCreate, require_scope)),
+                ),
+        )
+        .route(
+            "/schedules/:schedule_id",
+            get(get_schedule::handle)
+                .route_layer(from_fn_with_state(ApiScope::JobsRead, require_scope))
+                .merge(
+                    put(update_schedule::handle)
+                        .delete(delete_schedule::handle)
+                        .route_layer(from_fn_with_state(ApiScope::JobsCreate, require_scope)),
+                ),
+        )
+        .route(
+            "/schedules/:schedule_id/runs",
+            get(list_schedule_runs::handle)
+                .route_layer(from_fn_with_state(ApiScope::JobsRead, require_scope)),
+        )
+        .route(
+            "/worker/:worker_name/topics",
+            put(update_worker_topics::handle)
+                .route_layer(from_fn_with_state(ApiScope::Admin, require_scope)),
+        )
+        .route(
+            "/worker/:worker_name/key",
+            put(update_worker_key::handle)
+                .delete(delete_worker_key::handle)
+                .route_layer(from_fn_with_state(ApiScope::Admin, require_scope)),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use rabbitmq::InMemoryBroker;
+    use sqlx::PgPool;
+    use tower::Service;
+    use utoipa::openapi::PathItemType;
+
+    use super::*;
+    use crate::{
+        events::JobEvents, queue::signature::SignaturePolicy, rate_limit::RateLimits, repository::*,
+    };
+
+    // Requests without an API key are rejected before any query, so the pool never connects
+    fn state() -> Arc<AppState> {
+        let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        let job_queue =
+            InMemoryBroker::new().bus("job_exchange", "scheduler", "scheduler", "scheduler");
+
+        Arc::new(AppState::new(
+            Arc::new(job_queue),
+            Arc::new(DataRepository::new(pool.clone())),
+            Arc::new(WorkerRepository::new(pool.clone())),
+            Arc::new(JobRepository::new(pool.clone())),
+            Arc::new(TopicRepository::new(pool.clone())),
+            Arc::new(SubJobRepository::new(pool.clone())),
+            Arc::new(MissedSubJobRepository::new(pool.clone())),
+            Arc::new(AcknowledgedSubJobRepository::new(pool.clone())),
+            Arc::new(WorkerKeyRepository::new(pool.clone())),
+            Arc::new(ApiKeyRepository::new(pool.clone())),
+            Arc::new(WebhookDeliveryRepository::new(pool.clone())),
+            Arc::new(ScheduleRepository::new(pool.clone())),
+            Arc::new(ProviderRepository::new(pool)),
+            SignaturePolicy::Accept,
+            RateLimits::default(),
+            JobEvents::new(),
+        ))
+    }
+
+    #[test]
+    fn openapi_document_builds() {
+        let openapi = ApiDoc::openapi();
+        let servers = openapi.servers.as_ref().unwrap();
+        assert_eq!(servers[0].url, API_PREFIX);
+        assert!(serde_json::to_string(&openapi).is_ok());
+    }
+
+    #[tokio::test]
+    async fn documented_paths_are_routed() {
+        let mut app = create_app(state());
+
+        for (path, item) in ApiDoc::openapi().paths.paths {
+            // Path parameters in `{name}` form, any value reaches the route
+            let uri = path
+                .split('/')
+                .map(|segment| match segment.starts_with('{') {
+                    true => "00000000-0000-0000-0000-000000000000",
+                    false => segment,
+                })
+                .collect::<Vec<_>>()
+                .join("/");
+
+            for method in item.operations.keys() {
+                let method = match method {
+                    PathItemType::Get => "GET",
+                    PathItemType::Post => "POST",
+                    PathItemType::Put => "PUT",
+                    PathItemType::Delete => "DELETE",
+                    _ => panic!("Unexpected method of {}", path),
+                };
+                let request = Request::builder()
+                    .method(method)
+                    .uri(format!("{}{}", API_PREFIX, uri))
+                    .body(Body::empty())
+                    .unwrap();
+
+                let status = app.call(request).await.unwrap().status();
+                assert!(
+                    status != StatusCode::NOT_FOUND && status != StatusCode::METHOD_NOT_ALLOWED,
+                    "{} {} is documented but not routed: {}",
+                    method,
+                    path,
+                    status
+                );
+            }
+        }
+    }
 }
@@ -0,0 +1,287 @@
+//! Recurring jobs created from the schedules when their cron expression is due.
+//!
+//! Every due time gets one run in `schedule_runs`, unique per schedule, so a run isn't repeated
+//! after a restart or by another scheduler instance. Due times missed while the scheduler was
+//! down are skipped, only the latest one runs.
+
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use cron::Schedule as CronSchedule;
+use tokio::time::interval;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    api::api_response::ApiResponse,
+    job_service::{self, JobProfile, SYNC_DELAY_SECS},
+    schedule_repository::{Schedule, ScheduleRunStatus},
+    state::AppState,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+const SCHEDULES_PER_POLL: i64 = 20;
+// Runs still pending after this were interrupted, creating a job takes seconds
+const STALE_RUN_AGE: TimeDelta = TimeDelta::minutes(10);
+
+/// Parse the standard 5 field cron expression (minute, hour, day of month, month, day of week)
+/// or a shortcut like `@weekly`, in UTC
+pub fn parse_cron(expression: &str) -> Result<CronSchedule, String> {
+    let expression = expression.trim();
+    let expression = if expression.starts_with('@') {
+        expression.to_string()
+    } else {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day_of_month, month, day_of_week] = fields[..] else {
+            return Err("Cron expression must have 5 fields".to_string());
+        };
+        // The cron crate expects seconds first
+        format!(
+            "0 {} {} {} {} {}",
+            minute,
+            hour,
+            day_of_month,
+            month,
+            translate_day_of_week(day_of_week)?
+        )
+    };
+
+    CronSchedule::from_str(&expression).map_err(|e| format!("Invalid cron expression: {}", e))
+}
+
+/// Translate the numeric days of week from the standard 0-7 (0 and 7 are Sunday) to the 1-7
+/// (1 is Sunday) of the cron crate. Ranges and steps are expanded, names and `*` are kept.
+fn translate_day_of_week(field: &str) -> Result<String, String> {
+    let invalid = || format!("Invalid day of week: {}", field);
+
+    let mut items = Vec::new();
+    for item in field.split(',') {
+        let (base, step) = match item.split_once('/') {
+            Some((base, step)) => (base, Some(step.parse::<usize>().map_err(|_| invalid())?)),
+            None => (item, None),
+        };
+        if base == "*" || base.chars().any(|c| c.is_ascii_alphabetic()) {
+            items.push(item.to_string());
+            continue;
+        }
+
+        let (start, end) = match base.split_once('-') {
+            Some((start, end)) => (start, end),
+            // `1/2` steps from the day to the end of the week
+            None if step.is_some() => (base, "7"),
+            None => (base, base),
+        };
+        let start: u32 = start.parse().map_err(|_| invalid())?;
+        let end: u32 = end.parse().map_err(|_| invalid())?;
+        if start > end || end > 7 || step == Some(0) {
+            return Err(invalid());
+        }
+
+        let mut days: Vec<u32> = (start..=end)
+            .step_by(step.unwrap_or(1))
+            .map(|day| day % 7 + 1)
+            .collect();
+        days.sort_unstable();
+        days.dedup();
+        items.extend(days.iter().map(u32::to_string));
+    }
+
+    Ok(items.join(","))
+}
+
+/// First due time after `after`, `None` when the expression is never due again
+pub fn next_run_after(cron: &CronSchedule, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    cron.after(&after).next()
+}
+
+/// Create the jobs of the due schedules until the scheduler stops
+pub async fn run_schedules(state: Arc<AppState>) {
+    match state
+        .schedule_repo
+        .fail_stale_runs(Utc::now() - STALE_RUN_AGE)
+        .await
+    {
+        Ok(0) => {}
+        Ok(failed) => warn!("Failed {} interrupted schedule runs", failed),
+        Err(e) => error!("Failed to fail interrupted schedule runs: {:?}", e),
+    }
+
+    let mut interval = interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let schedules = match state
+            .schedule_repo
+            .get_due_schedules(Utc::now(), SCHEDULES_PER_POLL)
+            .await
+        {
+            Ok(schedules) => schedules,
+            Err(e) => {
+                error!("Failed to get due schedules: {:?}", e);
+                continue;
+            }
+        };
+
+        for schedule in schedules {
+            if let Err(e) = run_schedule(&state, &schedule).await {
+                error!("Failed to run schedule {}: {:?}", schedule.id, e);
+            }
+        }
+    }
+}
+
+async fn run_schedule(state: &AppState, schedule: &Schedule) -> Result<(), sqlx::Error> {
+    let due_at = schedule.next_run_at;
+    let run_id = state.schedule_repo.create_run(schedule.id, due_at).await?;
+
+    let next_run_at = parse_cron(&schedule.cron_expression)
+        .ok()
+        .and_then(|cron| next_run_after(&cron, Utc::now()));
+    state
+        .schedule_repo
+        .set_next_run(schedule.id, due_at, next_run_at)
+        .await?;
+
+    // Another instance or a previous run of this one already took it
+    let Some(run_id) = run_id else {
+        return Ok(());
+    };
+
+    match create_scheduled_job(state, schedule).await {
+        Ok(job_id) => {
+            info!("Schedule {} created job {}", schedule.id, job_id);
+            state
+                .schedule_repo
+                .finish_run(run_id, ScheduleRunStatus::Created, Some(job_id), None)
+                .await
+        }
+        Err(e) => {
+            warn!("Schedule {} failed to create a job: {}", schedule.id, e);
+            state
+                .schedule_repo
+                .finish_run(run_id, ScheduleRunStatus::Failed, None, Some(&e))
+                .await
+        }
+    }
+}
+
+/// Create the job with the key that created the schedule, subject to its rate limits
+async fn create_scheduled_job(state: &AppState, schedule: &Schedule) -> Result<Uuid, String> {
+    let api_key = state
+        .api_key_repo
+        .get_active_api_key_by_id(schedule.api_key_id)
+        .await
+        .map_err(|e| format!("Failed to get API key: {}", e))?
+        .ok_or_else(|| "API key of the schedule was revoked".to_string())?;
+
+    let profile = JobProfile {
+        routing_key: schedule.routing_key.clone(),
+        tags: schedule.tags.clone(),
+        callback_url: schedule.callback_url.clone(),
+        callback_secret: schedule.callback_secret.clone(),
+        provider_id: schedule.provider_id,
+    };
+    let prepared = job_service::prepare_job(state, &api_key, &schedule.url, profile)
+        .await
+        .map_err(error_message)?;
+
+    let start_time = Utc::now() + Duration::from_secs(SYNC_DELAY_SECS);
+    let job = job_service::create_job(state, &api_key, &prepared, start_time, None)
+        .await
+        .map_err(|e| error_message(e.response))?;
+
+    Ok(job.job_id)
+}
+
+fn error_message(response: ApiResponse<()>) -> String {
+    response
+        .error_message()
+        .unwrap_or("Failed to create job")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Datelike, TimeZone, Weekday};
+
+    use super::*;
+
+    // A Wednesday
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 10, 23, 12, 0, 0).unwrap()
+    }
+
+    fn next_weekdays(expression: &str, count: usize) -> Vec<Weekday> {
+        let cron = parse_cron(expression).unwrap();
+        cron.after(&now())
+            .take(count)
+            .map(|time| time.weekday())
+            .collect()
+    }
+
+    #[test]
+    fn uses_standard_days_of_week() {
+        assert_eq!(next_weekdays("0 9 * * 1", 2), [Weekday::Mon, Weekday::Mon]);
+        assert_eq!(next_weekdays("0 9 * * 0", 1), [Weekday::Sun]);
+        assert_eq!(next_weekdays("0 9 * * 7", 1), [Weekday::Sun]);
+        assert_eq!(next_weekdays("0 9 * * MON", 1), [Weekday::Mon]);
+        assert_eq!(
+            next_weekdays("0 9 * * 1-5", 5),
+            [
+                Weekday::Thu,
+                Weekday::Fri,
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed
+            ]
+        );
+        assert_eq!(
+            next_weekdays("0 9 * * 5-7", 3),
+            [Weekday::Fri, Weekday::Sat, Weekday::Sun]
+        );
+        assert_eq!(
+            next_weekdays("0 9 * * 0,6", 2),
+            [Weekday::Sat, Weekday::Sun]
+        );
+        assert_eq!(
+            next_weekdays("0 9 * * 1/2", 4),
+            [Weekday::Fri, Weekday::Sun, Weekday::Mon, Weekday::Wed]
+        );
+        assert!(parse_cron("* * * * 0").is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        // Seconds are not part of the standard format
+        assert!(parse_cron("0 0 9 * * 1").is_err());
+        assert!(parse_cron("0 9 * *").is_err());
+        assert!(parse_cron("not a cron").is_err());
+        assert!(parse_cron("61 9 * * *").is_err());
+        assert!(parse_cron("0 9 * * 8").is_err());
+        assert!(parse_cron("0 9 * * 5-1").is_err());
+        assert!(parse_cron("0 9 * * 1/0").is_err());
+        assert!(parse_cron("@sometimes").is_err());
+    }
+
+    #[test]
+    fn finds_next_run() {
+        let cron = parse_cron("30 2 * * *").unwrap();
+        assert_eq!(
+            next_run_after(&cron, now()),
+            Some(Utc.with_ymd_and_hms(2024, 10, 24, 2, 30, 0).unwrap())
+        );
+
+        // Strictly after, so the due time that just ran isn't returned again
+        let due = Utc.with_ymd_and_hms(2024, 10, 24, 2, 30, 0).unwrap();
+        assert_eq!(
+            next_run_after(&cron, due),
+            Some(Utc.with_ymd_and_hms(2024, 10, 25, 2, 30, 0).unwrap())
+        );
+
+        let weekly = parse_cron("@weekly").unwrap();
+        assert_eq!(
+            next_run_after(&weekly, now()).map(|time| time.weekday()),
+            Some(Weekday::Sun)
+        );
+    }
+}
@@ -1,27 +1,49 @@
 use std::sync::Arc;
 
-use rabbitmq::QueueHandler;
-use tokio::sync::Mutex;
+use rabbitmq::MessageBus;
 
-use crate::repository::*;
+use crate::{
+    events::JobEvents, queue::signature::SignaturePolicy, rate_limit::RateLimits, repository::*,
+};
 
 pub struct AppState {
-    pub job_queue: Arc<Mutex<QueueHandler>>,
+    pub job_queue: Arc<dyn MessageBus>,
     pub data_repo: Arc<DataRepository>,
     pub worker_repo: Arc<WorkerRepository>,
     pub job_repo: Arc<JobRepository>,
     pub topic_repo: Arc<TopicRepository>,
     pub sub_job_repo: Arc<SubJobRepository>,
+    pub missed_sub_job_repo: Arc<MissedSubJobRepository>,
+    pub acknowledged_sub_job_repo: Arc<AcknowledgedSubJobRepository>,
+    pub worker_key_repo: Arc<WorkerKeyRepository>,
+    pub api_key_repo: Arc<ApiKeyRepository>,
+    pub webhook_delivery_repo: Arc<WebhookDeliveryRepository>,
+    pub schedule_repo: Arc<ScheduleRepository>,
+    pub provider_repo: Arc<ProviderRepository>,
+    pub signature_policy: SignaturePolicy,
+    pub rate_limits: RateLimits,
+    pub job_events: JobEvents,
 }
 
 impl AppState {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        job_queue: Arc<Mutex<QueueHandler>>,
+        job_queue: Arc<dyn MessageBus>,
         data_repo: Arc<DataRepository>,
         worker_repo: Arc<WorkerRepository>,
         job_repo: Arc<JobRepository>,
         topic_repo: Arc<TopicRepository>,
         sub_job_repo: Arc<SubJobRepository>,
+        missed_sub_job_repo: Arc<MissedSubJobRepository>,
+        acknowledged_sub_job_repo: Arc<AcknowledgedSubJobRepository>,
+        worker_key_repo: Arc<WorkerKeyRepository>,
+        api_key_repo: Arc<ApiKeyRepository>,
+        webhook_delivery_repo: Arc<WebhookDeliveryRepository>,
+        schedule_repo: Arc<ScheduleRepository>,
+        provider_repo: Arc<ProviderRepository>,
+        signature_policy: SignaturePolicy,
+        rate_limits: RateLimits,
+        job_events: JobEvents,
     ) -> Self {
         AppState {
             job_queue,
@@ -30,6 +52,16 @@ impl AppState {
             job_repo,
             topic_repo,
             sub_job_repo,
+            missed_sub_job_repo,
+            acknowledged_sub_job_repo,
+            worker_key_repo,
+            api_key_repo,
+            webhook_delivery_repo,
+            schedule_repo,
+            provider_repo,
+            signature_policy,
+            rate_limits,
+            job_events,
         }
     }
 }
@@ -0,0 +1,87 @@
+//! Finishes the sub jobs whose results will never all arrive, e.g. a worker crashed after
+//! taking the sub job, so their jobs finish and the clients are notified.
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use chrono::{TimeDelta, Utc};
+use tokio::time::interval;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::{
+    events::JobEvent,
+    job_service::{self, SUB_JOB_DURATION},
+    queue::signature::SignaturePolicy,
+    state::AppState,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+const JOBS_PER_POLL: i64 = 50;
+// Results are published right after the measurement, some are retried from the worker outbox
+const RESULT_GRACE: TimeDelta = TimeDelta::minutes(5);
+
+/// Finish the stale sub jobs until the scheduler stops
+pub async fn run_sweeper(state: Arc<AppState>) {
+    let mut interval = interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        // Sub jobs started before this had time to run and report
+        let started_before =
+            Utc::now() - TimeDelta::from_std(SUB_JOB_DURATION).unwrap_or_default() - RESULT_GRACE;
+        let jobs = match state
+            .sub_job_repo
+            .get_jobs_with_stale_sub_jobs(started_before, JOBS_PER_POLL)
+            .await
+        {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                error!("Failed to get stale sub jobs: {:?}", e);
+                continue;
+            }
+        };
+
+        for job_id in jobs {
+            if let Err(e) = finish_stale_sub_jobs(&state, job_id, started_before).await {
+                error!("Failed to finish stale sub jobs of job {}: {:?}", job_id, e);
+            }
+        }
+    }
+}
+
+async fn finish_stale_sub_jobs(
+    state: &AppState,
+    job_id: Uuid,
+    started_before: chrono::DateTime<Utc>,
+) -> Result<()> {
+    // Same as a result, quarantined results don't count
+    let count_unverified = state.signature_policy != SignaturePolicy::Quarantine;
+
+    let mut tx = state.job_repo.begin().await?;
+    state.job_repo.lock_job(&mut tx, job_id).await?;
+    let sub_jobs = state
+        .sub_job_repo
+        .finish_stale_sub_jobs(&mut tx, job_id, started_before, count_unverified)
+        .await?;
+    let job_status = job_service::finish_job_if_done(state, &mut tx, job_id).await?;
+    tx.commit().await?;
+
+    for sub_job in sub_jobs {
+        info!(
+            "Stale sub job {} of job {} finished: {:?}",
+            sub_job.id, job_id, sub_job.status
+        );
+        state.job_events.publish(JobEvent::SubJob {
+            job_id,
+            sub_job_id: sub_job.id,
+            status: sub_job.status,
+        });
+    }
+    if let Some(status) = job_status {
+        info!("Job {} finished: {:?}", job_id, status);
+        state.job_events.publish(JobEvent::Job { job_id, status });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,300 @@
+//! Webhook notifications of finished jobs.
+//!
+//! The notification is queued in `webhook_deliveries` when the job reaches a terminal state and
+//! POSTed by the dispatcher, retried with backoff until the client answers with a 2xx status.
+//! With a callback secret the body is signed: `X-BMS-Signature: sha256=<hex>` is the HMAC-SHA256
+//! of `<X-BMS-Timestamp>.<body>`. Callback URLs resolving to non-public addresses aren't contacted.
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Result;
+use chrono::{TimeDelta, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::{redirect::Policy, Client};
+use sha2::Sha256;
+use sqlx::PgConnection;
+use tokio::{net::lookup_host, time::interval};
+use tracing::{debug, error, info, warn};
+use url::{Host, Url};
+use uuid::Uuid;
+
+use crate::{state::AppState, webhook_delivery_repository::DueWebhookDelivery};
+
+const EVENT_JOB_FINISHED: &str = "job.finished";
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// Longer than the request timeout, so a claimed delivery isn't sent twice
+const CLAIM_LEASE: TimeDelta = TimeDelta::seconds(60);
+const DELIVERIES_PER_POLL: i64 = 20;
+// 30s, 1m, 2m, ... capped at 1h, the last attempt is about 5h after the first
+const MAX_ATTEMPTS: i32 = 12;
+const INITIAL_BACKOFF_SECS: i64 = 30;
+const MAX_BACKOFF_SECS: i64 = 3600;
+
+/// Queue the notification of the finished job, if the job has a callback URL.
+/// The payload is the job progress as returned by `GET /job/{job_id}`.
+/// Called in the transaction finishing the job, so the notification is queued with the status.
+pub async fn enqueue_job_finished(
+    state: &AppState,
+    conn: &mut PgConnection,
+    job_id: Uuid,
+) -> Result<()> {
+    let job = state.job_repo.fetch_job_progress(conn, job_id).await?;
+    let Some(url) = job.callback_url.clone() else {
+        return Ok(());
+    };
+
+    let payload = serde_json::json!({
+        "event": EVENT_JOB_FINISHED,
+        "job": job,
+    });
+    state
+        .webhook_delivery_repo
+        .create_delivery(conn, job_id, &url, payload)
+        .await?;
+    debug!("Queued webhook of job {} to {}", job_id, url);
+
+    Ok(())
+}
+
+/// Deliver the due notifications until the scheduler stops
+pub async fn run_dispatcher(state: Arc<AppState>) {
+    let mut interval = interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let deliveries = match state
+            .webhook_delivery_repo
+            .claim_due_deliveries(DELIVERIES_PER_POLL, Utc::now() + CLAIM_LEASE)
+            .await
+        {
+            Ok(deliveries) => deliveries,
+            Err(e) => {
+                error!("Failed to claim webhook deliveries: {:?}", e);
+                continue;
+            }
+        };
+
+        for delivery in deliveries {
+            deliver(&state, delivery).await;
+        }
+    }
+}
+
+async fn deliver(state: &AppState, delivery: DueWebhookDelivery) {
+    let client = match public_client(&delivery.url).await {
+        Ok(client) => client,
+        Err(error) => {
+            record_failure(state, &delivery, None, error).await;
+            return;
+        }
+    };
+
+    let body = delivery.payload.to_string();
+    let timestamp = Utc::now().timestamp().to_string();
+
+    let mut request = client
+        .post(&delivery.url)
+        .header("Content-Type", "application/json")
+        .header("X-BMS-Event", EVENT_JOB_FINISHED)
+        .header("X-BMS-Delivery", delivery.id.to_string())
+        .header("X-BMS-Timestamp", &timestamp);
+    if let Some(secret) = &delivery.secret {
+        request = request.header("X-BMS-Signature", sign(secret, &timestamp, &body));
+    }
+
+    let (status_code, error) = match request.body(body).send().await {
+        Ok(response) if response.status().is_success() => {
+            info!(
+                "Delivered webhook of job {} to {}",
+                delivery.job_id, delivery.url
+            );
+            let recorded = state
+                .webhook_delivery_repo
+                .mark_delivered(delivery.id, response.status().as_u16().into())
+                .await;
+            if let Err(e) = recorded {
+                error!("Failed to record webhook delivery {}: {:?}", delivery.id, e);
+            }
+            return;
+        }
+        Ok(response) => (
+            Some(response.status().as_u16().into()),
+            format!("Unexpected status {}", response.status()),
+        ),
+        Err(e) => (None, e.to_string()),
+    };
+
+    record_failure(state, &delivery, status_code, error).await;
+}
+
+async fn record_failure(
+    state: &AppState,
+    delivery: &DueWebhookDelivery,
+    status_code: Option<i32>,
+    error: String,
+) {
+    let attempts = delivery.attempts + 1;
+    let next_attempt_at = (attempts < MAX_ATTEMPTS).then(|| Utc::now() + backoff(attempts));
+    warn!(
+        "Webhook of job {} to {} failed (attempt {}): {}",
+        delivery.job_id, delivery.url, attempts, error
+    );
+
+    let recorded = state
+        .webhook_delivery_repo
+        .record_failed_attempt(delivery.id, status_code, &error, next_attempt_at)
+        .await;
+    if let Err(e) = recorded {
+        error!("Failed to record webhook attempt {}: {:?}", delivery.id, e);
+    }
+}
+
+/// Client connecting only to the addresses the host resolves to now, when all of them are public.
+/// Redirects aren't followed, they could lead to the internal network as well.
+async fn public_client(url: &str) -> Result<Client, String> {
+    let url = Url::parse(url).map_err(|e| format!("Invalid callback URL: {}", e))?;
+    let port = url
+        .port_or_known_default()
+        .ok_or("Callback URL has no port")?;
+
+    let mut builder = Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .redirect(Policy::none());
+    let addrs: Vec<SocketAddr> = match url.host() {
+        Some(Host::Domain(domain)) => {
+            let addrs: Vec<SocketAddr> = lookup_host((domain, port))
+                .await
+                .map_err(|e| format!("Failed to resolve {}: {}", domain, e))?
+                .collect();
+            // Pinned, so a DNS answer changing after the check isn't used
+            builder = builder.resolve_to_addrs(domain, &addrs);
+            addrs
+        }
+        Some(Host::Ipv4(ip)) => vec![SocketAddr::new(ip.into(), port)],
+        Some(Host::Ipv6(ip)) => vec![SocketAddr::new(ip.into(), port)],
+        None => return Err("Callback URL has no host".to_string()),
+    };
+    if let Some(addr) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+        return Err(format!(
+            "Callback URL resolves to the non-public address {}",
+            addr.ip()
+        ));
+    }
+
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// Whether the address is reachable on the internet, i.e. not loopback, private, link local,
+/// shared, reserved or documentation
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                // Shared address space of carrier-grade NAT
+                || (a == 100 && (b & 0xc0) == 64)
+                // Benchmarking
+                || (a == 198 && (b & 0xfe) == 18)
+                // Reserved and broadcast
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(ip.into());
+            }
+            let [first, second, ..] = ip.segments();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                // Unique local
+                || (first & 0xfe00) == 0xfc00
+                // Link local
+                || (first & 0xffc0) == 0xfe80
+                // Documentation
+                || (first == 0x2001 && second == 0x0db8))
+        }
+    }
+}
+
+/// Delay after the failed attempt, doubled with every attempt
+fn backoff(attempts: i32) -> TimeDelta {
+    let exponent = (attempts - 1).clamp(0, 16) as u32;
+    TimeDelta::seconds((INITIAL_BACKOFF_SECS << exponent).min(MAX_BACKOFF_SECS))
+}
+
+/// `sha256=` followed by the hex HMAC-SHA256 of `<timestamp>.<body>`
+fn sign(secret: &str, timestamp: &str, body: &str) -> String {
+    // HMAC accepts keys of any length
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC key");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_timestamp_and_body() {
+        assert_eq!(
+            sign("secret", "1700000000", r#"{"event":"job.finished"}"#),
+            "sha256=7e727e7d2b8027b25bb0412971e76f7f2fcb5ededae3cebb9b1595daeb971b35"
+        );
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        assert_eq!(backoff(0), TimeDelta::seconds(30));
+        assert_eq!(backoff(1), TimeDelta::seconds(30));
+        assert_eq!(backoff(2), TimeDelta::seconds(60));
+        assert_eq!(backoff(7), TimeDelta::seconds(1920));
+        assert_eq!(backoff(8), TimeDelta::seconds(MAX_BACKOFF_SECS));
+        assert_eq!(backoff(MAX_ATTEMPTS), TimeDelta::seconds(MAX_BACKOFF_SECS));
+        assert_eq!(backoff(i32::MAX), TimeDelta::seconds(MAX_BACKOFF_SECS));
+    }
+
+    #[test]
+    fn rejects_non_public_addresses() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["1.1.1.1", "93.184.216.34", "2606:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_clients_for_internal_urls() {
+        assert!(public_client("http://127.0.0.1:8080/hook").await.is_err());
+        assert!(public_client("http://[::1]/hook").await.is_err());
+        assert!(public_client("http://localhost/hook").await.is_err());
+        assert!(public_client("https://93.184.216.34/hook").await.is_ok());
+    }
+}
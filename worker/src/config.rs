@@ -1,40 +1,282 @@
-use std::{collections::HashSet, env};
+use std::{collections::HashSet, env, fs, net::IpAddr, path::PathBuf};
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
 use once_cell::sync::Lazy;
+use rabbitmq::BrokerConfig;
+use serde::Deserialize;
 
-pub static CONFIG: Lazy<Config> = Lazy::new(|| Config::new_from_env().unwrap());
+pub static CONFIG: Lazy<Config> = Lazy::new(|| Config::load().unwrap());
 
-#[derive(Debug)]
+/// Worker configuration, loaded from the TOML file pointed by `WORKER_CONFIG_FILE`.
+/// Environment variables take precedence over the values from the file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
+    #[serde(rename = "name", default)]
     pub worker_name: String,
+    #[serde(rename = "topics", default)]
     pub worker_topics: Vec<String>,
+    #[serde(default = "default_log_level")]
     pub log_level: String,
+    #[serde(default = "default_heartbeat_interval_sec")]
     pub heartbeat_interval_sec: u64,
+    #[serde(default)]
+    pub network: NetworkConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    #[serde(default)]
+    pub measurement: MeasurementConfig,
+    #[serde(default)]
+    pub calibration: CalibrationConfig,
+    #[serde(default)]
+    pub outbox: OutboxConfig,
+    #[serde(default)]
+    pub signing: SigningConfig,
+    #[serde(default)]
+    pub broker: BrokerConfig,
 }
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// Interface reported in host metrics, defaults to the interface of the default route
+    pub metrics_interface: Option<String>,
+    /// Local address the measurement requests are sent from
+    pub source_address: Option<IpAddr>,
+    /// Local interface the measurement requests are sent from
+    pub source_interface: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct LimitsConfig {
+    /// Download deadline, job will succeed but won't download for longer than this
+    pub max_download_duration_sec: u64,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_download_duration_sec: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct MeasurementConfig {
+    pub head_request_count: usize,
+    pub ping_count: u16,
+    /// Publish the bytes downloaded every second, for the live job events of the scheduler
+    pub report_progress: bool,
+}
+
+impl Default for MeasurementConfig {
+    fn default() -> Self {
+        Self {
+            head_request_count: 10,
+            ping_count: 10,
+            report_progress: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct CalibrationConfig {
+    /// Reference URL, calibration is disabled when not set
+    pub url: Option<String>,
+    pub interval_sec: u64,
+    pub duration_sec: u64,
+}
+
+impl Default for CalibrationConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            interval_sec: 3600,
+            duration_sec: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct OutboxConfig {
+    /// Directory for results not yet published, should be on persistent storage
+    pub dir: PathBuf,
+    /// Interval between attempts to publish stored results
+    pub flush_interval_sec: u64,
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        Self {
+            // Next to the signing key, temporary directories may be cleared on reboot
+            dir: PathBuf::from("outbox"),
+            flush_interval_sec: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct SigningConfig {
+    /// Ed25519 key signing the results and statuses, generated when the file doesn't exist
+    pub key_file: PathBuf,
+}
+
+impl Default for SigningConfig {
+    fn default() -> Self {
+        Self {
+            key_file: PathBuf::from("worker.key"),
+        }
+    }
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_heartbeat_interval_sec() -> u64 {
+    5
+}
+
 impl Config {
-    pub fn new_from_env() -> Result<Self, anyhow::Error> {
-        let mut worker_topics: Vec<String> = env::var("WORKER_TOPICS")
-            .unwrap_or_else(|_| "all".to_string())
-            .split(',')
-            .map(|s| s.to_string())
-            .collect::<HashSet<_>>()
-            .into_iter()
-            .collect();
-
-        // Ensure "all" is included in the vector
-        if !worker_topics.contains(&"all".to_string()) {
-            worker_topics.push("all".to_string());
-        }
-
-        Ok(Self {
-            worker_name: env::var("WORKER_NAME").expect("WORKER_NAME is not set"),
-            worker_topics,
-            heartbeat_interval_sec: env::var("HEARTBEAT_INTERVAL_SEC")
-                .unwrap_or_else(|_| "5".to_string())
+    /// Load the configuration from the file (if any) and apply the env overrides
+    pub fn load() -> Result<Self> {
+        let mut config = match env::var("WORKER_CONFIG_FILE") {
+            Ok(path) => Self::from_file(&path)?,
+            Err(_) => Self::default(),
+        };
+
+        config.apply_env()?;
+
+        if config.worker_name.is_empty() {
+            bail!("WORKER_NAME is not set");
+        }
+        config.worker_topics = normalize_topics(config.worker_topics)?;
+
+        Ok(config)
+    }
+
+    fn from_file(path: &str) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+
+        toml::from_str(&content).with_context(|| format!("Failed to parse {}", path))
+    }
+
+    fn apply_env(&mut self) -> Result<()> {
+        if let Ok(worker_name) = env::var("WORKER_NAME") {
+            self.worker_name = worker_name;
+        }
+        if let Ok(worker_topics) = env::var("WORKER_TOPICS") {
+            self.worker_topics = worker_topics.split(',').map(|s| s.to_string()).collect();
+        }
+        if let Ok(log_level) = env::var("LOG_LEVEL") {
+            self.log_level = log_level;
+        }
+        if let Ok(interval) = env::var("HEARTBEAT_INTERVAL_SEC") {
+            self.heartbeat_interval_sec = interval
                 .parse::<u64>()
-                .expect("Invalid HEARTBEAT_INTERVAL value"),
-            log_level: env::var("LOG_LEVEL").unwrap_or_else(|_| "info".to_string()),
-        })
+                .context("Invalid HEARTBEAT_INTERVAL_SEC value")?;
+        }
+        if let Ok(interface) = env::var("METRICS_INTERFACE") {
+            self.network.metrics_interface = Some(interface);
+        }
+        if let Ok(report_progress) = env::var("REPORT_PROGRESS") {
+            self.measurement.report_progress = report_progress
+                .parse::<bool>()
+                .context("Invalid REPORT_PROGRESS value, use true or false")?;
+        }
+        if let Ok(url) = env::var("CALIBRATION_URL") {
+            self.calibration.url = Some(url);
+        }
+        if let Ok(interval) = env::var("CALIBRATION_INTERVAL_SEC") {
+            self.calibration.interval_sec = interval
+                .parse::<u64>()
+                .context("Invalid CALIBRATION_INTERVAL_SEC value")?;
+        }
+        if let Ok(duration) = env::var("CALIBRATION_DURATION_SEC") {
+            self.calibration.duration_sec = duration
+                .parse::<u64>()
+                .context("Invalid CALIBRATION_DURATION_SEC value")?;
+        }
+        if let Ok(dir) = env::var("OUTBOX_DIR") {
+            self.outbox.dir = PathBuf::from(dir);
+        }
+        if let Ok(key_file) = env::var("SIGNING_KEY_FILE") {
+            self.signing.key_file = PathBuf::from(key_file);
+        }
+        self.broker.apply_env().map_err(|e| anyhow!(e))?;
+
+        Ok(())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            worker_name: String::new(),
+            worker_topics: Vec::new(),
+            log_level: default_log_level(),
+            heartbeat_interval_sec: default_heartbeat_interval_sec(),
+            network: NetworkConfig::default(),
+            limits: LimitsConfig::default(),
+            measurement: MeasurementConfig::default(),
+            calibration: CalibrationConfig::default(),
+            outbox: OutboxConfig::default(),
+            signing: SigningConfig::default(),
+            broker: BrokerConfig::default(),
+        }
+    }
+}
+
+/// Remove duplicates and empty topics, every worker is interested in the "all" topic.
+/// Topics are bound as routing key patterns, so wildcards and dots are refused.
+pub fn normalize_topics(topics: Vec<String>) -> Result<Vec<String>> {
+    let mut seen = HashSet::new();
+    let mut worker_topics: Vec<String> = topics
+        .into_iter()
+        .map(|topic| topic.trim().to_string())
+        .filter(|topic| !topic.is_empty() && seen.insert(topic.clone()))
+        .collect();
+
+    if let Some(topic) = worker_topics.iter().find(|topic| {
+        !topic
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }) {
+        bail!("Invalid topic: {}, use letters, digits, - and _", topic);
+    }
+
+    // Ensure "all" is included in the vector
+    if !worker_topics.contains(&"all".to_string()) {
+        worker_topics.push("all".to_string());
+    }
+
+    Ok(worker_topics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_topics() {
+        let topics = vec![" europe".to_string(), "".to_string(), "europe".to_string()];
+        assert_eq!(normalize_topics(topics).unwrap(), vec!["europe", "all"]);
+    }
+
+    #[test]
+    fn rejects_invalid_topics() {
+        for topic in ["#", "a.*", "*", "eu.west"] {
+            assert!(
+                normalize_topics(vec![topic.to_string()]).is_err(),
+                "topic: {}",
+                topic
+            );
+        }
     }
 }
@@ -0,0 +1,89 @@
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use rabbitmq::{CalibrationError, CalibrationResult};
+use reqwest::header::{ACCEPT, USER_AGENT};
+use tokio::time::timeout;
+use tracing::{debug, info};
+
+use super::http_client;
+
+/// Benchmark the download speed of the reference URL to get the worker baseline
+#[tracing::instrument]
+pub async fn process(
+    url: &str,
+    max_duration: Duration,
+) -> Result<CalibrationResult, CalibrationError> {
+    info!("Processing calibration");
+
+    let client = http_client().map_err(|e| CalibrationError {
+        error: format!("ClientError: {}", e),
+    })?;
+
+    // The reference host may accept the connection and never answer
+    let request = client
+        .get(url)
+        .header(USER_AGENT, "curl/7.68.0")
+        .header(ACCEPT, "*/*")
+        .send();
+    let mut response = timeout(max_duration.to_std().unwrap_or_default(), request)
+        .await
+        .map_err(|_| CalibrationError {
+            error: "RequestTimeout".to_string(),
+        })?
+        .map_err(|e| CalibrationError {
+            error: format!("RequestError: {}", e),
+        })?;
+
+    if !response.status().is_success() {
+        return Err(CalibrationError {
+            error: format!("RequestFailed: {}", response.status()),
+        });
+    }
+
+    let start_time = Utc::now();
+    let deadline = start_time + max_duration;
+    let mut total_bytes: usize = 0;
+
+    loop {
+        let remaining = (deadline - Utc::now()).to_std().unwrap_or_default();
+
+        match timeout(remaining, response.chunk()).await {
+            Ok(Ok(Some(chunk))) => total_bytes += chunk.len(),
+            Ok(Ok(None)) => break,
+            Ok(Err(e)) => {
+                return Err(CalibrationError {
+                    error: format!("ChunkError: {}", e),
+                })
+            }
+            Err(_) => {
+                debug!("Reached maximum calibration duration, stopping download");
+                break;
+            }
+        }
+    }
+
+    let end_time = Utc::now();
+    let elapsed_secs = (end_time - start_time).num_milliseconds() as f64 / 1000.0;
+
+    if total_bytes == 0 || elapsed_secs <= 0.0 {
+        return Err(CalibrationError {
+            error: "Downloaded 0 bytes".to_string(),
+        });
+    }
+
+    // Same unit as the download job result (Mbps)
+    let download_speed = (total_bytes as f64 * 8.0) / (elapsed_secs * 1024.0 * 1024.0);
+
+    info!(
+        "Calibration downloaded {} bytes in {:.2} seconds ({:.2} Mbps)",
+        total_bytes, elapsed_secs, download_speed
+    );
+
+    Ok(CalibrationResult {
+        url: url.to_string(),
+        total_bytes,
+        elapsed_secs,
+        download_speed,
+        calibrated_at: end_time,
+    })
+}
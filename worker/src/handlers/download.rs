@@ -1,28 +1,80 @@
 use anyhow::{bail, Result};
 use bytes::Bytes;
 use chrono::{DateTime, Duration, Utc};
-use rabbitmq::{AccumulatingBytes, DownloadError, DownloadResult, IntervalBytes, JobMessage};
+use rabbitmq::{
+    AccumulatingBytes, DownloadError, DownloadProgress, DownloadResult, IntervalBytes, JobMessage,
+};
 use reqwest::{
     header::{ACCEPT, RANGE, USER_AGENT},
-    Client, Response,
+    Response,
+};
+use tokio::{
+    sync::mpsc,
+    time::{sleep, timeout},
 };
-use tokio::time::{sleep, timeout};
 use tracing::{debug, error, info};
 use uuid::Uuid;
 
+use super::http_client;
+use crate::CONFIG;
+
 // Download deadline, job will succeed but won't work/download more than this duration
-const MAX_DOWNLOAD_DURATION: Duration = Duration::seconds(60);
+fn max_download_duration() -> Duration {
+    Duration::seconds(CONFIG.limits.max_download_duration_sec as i64)
+}
+
+/// Sends the progress of the download every interval to the status publisher
+pub struct ProgressReporter {
+    pub run_id: Uuid,
+    pub sender: mpsc::Sender<DownloadProgress>,
+}
+
+impl ProgressReporter {
+    fn report(
+        &self,
+        payload: &JobMessage,
+        download_start_time: DateTime<Utc>,
+        timestamp: DateTime<Utc>,
+        interval_bytes: usize,
+        total_bytes: usize,
+    ) {
+        let elapsed_secs = (timestamp - download_start_time).num_milliseconds() as f64 / 1000.0;
+        let progress = DownloadProgress {
+            run_id: self.run_id,
+            job_id: payload.job_id,
+            sub_job_id: payload.sub_job_id,
+            timestamp,
+            interval_bytes,
+            total_bytes,
+            elapsed_secs,
+            download_speed: (total_bytes as f64 * 8.0) / (elapsed_secs * 1024.0 * 1024.0),
+        };
+
+        // The download doesn't wait for the publisher, progress is dropped when it falls behind
+        if self.sender.try_send(progress).is_err() {
+            debug!("Dropped download progress");
+        }
+    }
+}
 
 /// Prepare the HTTP request
-fn prepare_request(url: &str, range_start: u64, range_end: u64) -> reqwest::RequestBuilder {
+fn prepare_request(
+    url: &str,
+    range_start: u64,
+    range_end: u64,
+) -> Result<reqwest::RequestBuilder, DownloadError> {
     const USER_AGENT_STR: &str = "curl/7.68.0";
     const ACCEPT_TYPE: &str = "*/*";
 
-    Client::new()
+    let client = http_client().map_err(|e| DownloadError {
+        error: format!("ClientError: {}", e),
+    })?;
+
+    Ok(client
         .get(url)
         .header(RANGE, format!("bytes={}-{}", range_start, range_end))
         .header(USER_AGENT, USER_AGENT_STR)
-        .header(ACCEPT, ACCEPT_TYPE)
+        .header(ACCEPT, ACCEPT_TYPE))
 }
 
 /// Calculates the next even second from the given time.
@@ -60,7 +112,7 @@ async fn wait_for_start_time(payload: &JobMessage) -> Result<()> {
 }
 
 async fn download_chunk(response: &mut Response) -> Result<Option<Bytes>, DownloadError> {
-    match timeout(MAX_DOWNLOAD_DURATION.to_std().unwrap(), response.chunk()).await {
+    match timeout(max_download_duration().to_std().unwrap(), response.chunk()).await {
         Ok(Ok(chunk)) => Ok(chunk),
         Ok(Err(e)) => Err(DownloadError {
             error: format!("ChunkError: {}", e),
@@ -70,11 +122,15 @@ async fn download_chunk(response: &mut Response) -> Result<Option<Bytes>, Downlo
 }
 
 /// Benchmark the download speed of the given URL
-#[tracing::instrument(skip(payload))]
-pub async fn process(job_id: Uuid, payload: JobMessage) -> Result<DownloadResult, DownloadError> {
+#[tracing::instrument(skip(payload, progress))]
+pub async fn process(
+    job_id: Uuid,
+    payload: JobMessage,
+    progress: Option<ProgressReporter>,
+) -> Result<DownloadResult, DownloadError> {
     info!("Processing Download job");
 
-    let request = prepare_request(&payload.url, payload.start_range, payload.end_range);
+    let request = prepare_request(&payload.url, payload.start_range, payload.end_range)?;
 
     let job_start_time = Utc::now();
     let mut bytes: usize = 0;
@@ -118,10 +174,10 @@ pub async fn process(job_id: Uuid, payload: JobMessage) -> Result<DownloadResult
 
         let current_time = Utc::now();
         let elapsed_time = current_time - download_start_time;
-        if elapsed_time >= MAX_DOWNLOAD_DURATION {
+        if elapsed_time >= max_download_duration() {
             info!(
                 "Reached maximum download duration of {:?}, stopping download",
-                MAX_DOWNLOAD_DURATION
+                max_download_duration()
             );
             break;
         }
@@ -137,6 +193,15 @@ pub async fn process(job_id: Uuid, payload: JobMessage) -> Result<DownloadResult
                 "Time: {:?}, Bytes downloaded: {}",
                 current_time, total_bytes
             );
+            if let Some(progress) = &progress {
+                progress.report(
+                    &payload,
+                    download_start_time,
+                    current_time,
+                    bytes,
+                    total_bytes,
+                );
+            }
 
             // Reset the interval byte counter
             bytes = 0;
@@ -1,17 +1,21 @@
 use anyhow::Result;
 use chrono::{Duration, Utc};
 use rabbitmq::{HeadError, HeadResult, JobMessage};
-use reqwest::Client;
 use tokio::time::Instant;
 use tracing::{debug, info};
 use uuid::Uuid;
 
+use super::http_client;
+use crate::CONFIG;
+
 #[tracing::instrument(skip(payload))]
 pub async fn process(job_id: Uuid, payload: JobMessage) -> Result<HeadResult, HeadError> {
     info!("Processing HEAD job");
 
-    let client = Client::new();
-    let num_requests = 10; // Number of times to send the HEAD request
+    let client = http_client().map_err(|e| HeadError {
+        error: format!("ClientError: {}", e),
+    })?;
+    let num_requests = CONFIG.measurement.head_request_count; // Number of times to send the HEAD request
     let mut latencies: Vec<f64> = Vec::with_capacity(num_requests);
 
     // Calculate deadline
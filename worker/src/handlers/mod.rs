@@ -1,3 +1,19 @@
+use reqwest::Client;
+
+use crate::CONFIG;
+
+pub mod calibration;
 pub mod download;
 pub mod head;
 pub mod ping;
+
+/// HTTP client sending the measurement requests from the configured source address/interface
+fn http_client() -> reqwest::Result<Client> {
+    let mut builder = Client::builder().local_address(CONFIG.network.source_address);
+
+    if let Some(interface) = &CONFIG.network.source_interface {
+        builder = builder.interface(interface);
+    }
+
+    builder.build()
+}
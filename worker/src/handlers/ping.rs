@@ -1,4 +1,4 @@
-use std::net::{IpAddr, ToSocketAddrs};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
 
 use anyhow::Result;
 use chrono::{Duration, Utc};
@@ -9,6 +9,8 @@ use tracing::{debug, error, info};
 use url::Url;
 use uuid::Uuid;
 
+use crate::CONFIG;
+
 #[tracing::instrument(skip(payload))]
 pub async fn process(job_id: Uuid, payload: JobMessage) -> Result<PingResult, PingError> {
     info!("Processing PING job");
@@ -40,18 +42,25 @@ pub async fn process(job_id: Uuid, payload: JobMessage) -> Result<PingResult, Pi
             error: "Failed to extract IP address from socket addr".to_string(),
         })?;
 
-    let config = match ip_address {
-        IpAddr::V4(_) => Config::default(),
-        IpAddr::V6(_) => Config::builder().kind(ICMP::V6).build(),
+    let mut config_builder = match ip_address {
+        IpAddr::V4(_) => Config::builder(),
+        IpAddr::V6(_) => Config::builder().kind(ICMP::V6),
     };
+    if let Some(source_address) = CONFIG.network.source_address {
+        config_builder = config_builder.bind(SocketAddr::new(source_address, 0));
+    }
+    if let Some(interface) = &CONFIG.network.source_interface {
+        config_builder = config_builder.interface(interface);
+    }
+    let config = config_builder.build();
     let client = Client::new(&config).map_err(|e| PingError {
         error: format!("SurgePingClientError: {}", e),
     })?;
     let mut pinger = client.pinger(ip_address, PingIdentifier(random())).await;
 
     let mut latencies: Vec<f64> = Vec::new();
-    let seq_max = 10;
-    let packets_threshold = seq_max / 2;
+    let seq_max = CONFIG.measurement.ping_count;
+    let packets_threshold = (seq_max / 2).max(1);
 
     for seq in 0..seq_max {
         // Check deadline
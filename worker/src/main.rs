@@ -1,19 +1,32 @@
-use std::error::Error;
+use std::{error::Error, sync::Arc};
 
 use anyhow::Result;
-use config::CONFIG;
+use config::{Config, CONFIG};
+use handlers::calibration;
+use metrics::HostMetricsSampler;
+use outbox::Outbox;
 use queue::{job_consumer::JobConsumer, status_sender::StatusSender};
 use rabbitmq::*;
-use tokio::time::{interval, Duration};
-use tracing::{error, info};
+use signing::load_or_generate_key;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::Mutex,
+    time::{interval, Duration, MissedTickBehavior},
+};
+use topics::{apply_topics, TopicManager};
+use tracing::{debug, error, info, warn};
 use tracing_subscriber::EnvFilter;
 
 mod config;
 mod handlers;
+mod metrics;
+mod outbox;
 mod queue;
+mod signing;
+mod topics;
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     // Load .env
     dotenvy::dotenv()
         .inspect_err(|_| eprintln!("Failed to read .env file, ignoring."))
@@ -26,37 +39,108 @@ async fn main() -> Result<(), Box<dyn Error>> {
         )
         .init();
 
+    // Invalid namespaces fail here rather than on the first queue setup
+    let namespace_prefix = CONFIG.broker.namespace_prefix()?;
     info!(
-        "Worker started, name: {} topics: {:?}",
+        "Worker started, name: {} topics: {:?} namespace: {}",
         CONFIG.worker_name.to_string(),
         CONFIG.worker_topics,
+        namespace_prefix.strip_suffix('.').unwrap_or("none"),
     );
 
-    let mut job_queue = QueueHandler::clone(&CONFIG_QUEUE_JOB);
+    // Results and statuses are signed, so the scheduler can tell they come from this worker
+    let signing_key = load_or_generate_key(&CONFIG.signing.key_file)?;
+
+    // Exchanges are declared by the scheduler, setup fails if it doesn't run in the same namespace.
+    // Jobs are measured one at a time, the rest wait in the queue where they can expire.
+    let job_queue_config = CONFIG_QUEUE_JOB
+        .with_existing_exchange()
+        .with_queue(CONFIG.worker_name.clone(), CONFIG.worker_name.clone())
+        .with_topics(CONFIG.worker_topics.clone())
+        .with_prefetch_count(1)
+        .with_consumer_tag(&CONFIG.worker_name)
+        .with_broker(CONFIG.broker.clone());
+    let mut job_queue = QueueHandler::new(job_queue_config).with_sender(&CONFIG.worker_name);
     job_queue.setup().await?;
     info!("Successfully set up job queue");
+    let topic_manager =
+        TopicManager::new(Arc::new(job_queue.clone()), CONFIG.worker_topics.clone());
 
-    let mut data_queue = QueueHandler::clone(&CONFIG_QUEUE_RESULT);
+    let mut data_queue = QueueHandler::new(
+        CONFIG_QUEUE_RESULT
+            .with_existing_exchange()
+            .with_broker(CONFIG.broker.clone()),
+    )
+    .with_sender(&CONFIG.worker_name)
+    .with_signing_key(signing_key.clone());
     data_queue.setup().await?;
     info!("Successfully set up data queue");
 
-    let mut status_queue = QueueHandler::clone(&CONFIG_QUEUE_STATUS);
+    let mut status_queue = QueueHandler::new(
+        CONFIG_QUEUE_STATUS
+            .with_existing_exchange()
+            .with_broker(CONFIG.broker.clone()),
+    )
+    .with_sender(&CONFIG.worker_name)
+    .with_signing_key(signing_key);
     status_queue.setup().await?;
     info!("Successfully set up status queue");
-    let status_sender = StatusSender::new(status_queue.clone());
+    let status_sender = StatusSender::new(
+        Arc::new(status_queue.clone()),
+        topic_manager.clone(),
+        CONFIG.worker_name.clone(),
+    );
+
+    // Jobs and calibration must not run at the same time, otherwise they skew each other
+    let measurement_lock = Arc::new(Mutex::new(()));
 
+    // Calibration runs in the background, so startup doesn't wait on the reference host
     status_sender
-        .send_lifecycle_status(WorkerStatus::Online)
+        .send_lifecycle_status(WorkerStatus::Online, None)
         .await?;
 
     // Spawn the background task to send heartbeat status
     tokio::spawn(send_heartbeat_status(status_sender.clone()));
 
-    let consumer = JobConsumer::new(data_queue.clone(), status_sender.clone());
-    job_queue.subscribe(consumer).await?;
+    // Results stored while the broker was unreachable (possibly before a restart) are published first
+    let outbox = Outbox::new(
+        CONFIG.outbox.dir.clone(),
+        Arc::new(data_queue.clone()),
+        RESULT_ROUTING_KEY,
+        &CONFIG.worker_name,
+    )
+    .await?;
+    tokio::spawn(flush_outbox(outbox.clone()));
+
+    // Spawn the background task to calibrate the worker now and then every interval
+    tokio::spawn(calibrate_periodically(
+        status_sender.clone(),
+        measurement_lock.clone(),
+    ));
+
+    let consumer = JobConsumer::new(
+        &CONFIG,
+        outbox.clone(),
+        status_sender.clone(),
+        topic_manager.clone(),
+        measurement_lock.clone(),
+    );
+    job_queue.subscribe(Arc::new(consumer)).await?;
     info!("Successfully started job queue consumer");
 
-    tokio::signal::ctrl_c().await?;
+    let mut sighup = signal(SignalKind::hangup())?;
+    loop {
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => {
+                result?;
+                break;
+            }
+            _ = sighup.recv() => {
+                info!("Received SIGHUP signal, reloading configuration...");
+                reload_topics(&topic_manager, &status_sender).await;
+            }
+        }
+    }
     info!("Received SIGINT signal, Shutting down...");
 
     // TODO: do not accept new jobs and wait for execution of existing ones
@@ -65,7 +149,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     job_queue.close().await?;
     data_queue.close().await?;
     status_sender
-        .send_lifecycle_status(WorkerStatus::Offline)
+        .send_lifecycle_status(WorkerStatus::Offline, None)
         .await?;
     status_queue.close().await?;
     info!("Worker shut down gracefully");
@@ -79,10 +163,111 @@ async fn send_heartbeat_status(status_sender: StatusSender) {
 
     let mut interval = interval(Duration::from_secs(interval_secs));
 
+    let mut sampler = HostMetricsSampler::new()
+        .inspect_err(|e| error!("Host metrics are not available: {}", e))
+        .ok();
+
     loop {
         interval.tick().await;
-        if let Err(e) = status_sender.send_heartbeat_status().await {
+
+        let host_metrics = sampler.as_mut().and_then(|sampler| {
+            sampler
+                .sample()
+                .inspect_err(|e| debug!("Failed to sample host metrics: {}", e))
+                .ok()
+        });
+
+        if let Err(e) = status_sender.send_heartbeat_status(host_metrics).await {
             error!("Error sending heartbeat status: {}", e);
         }
     }
 }
+
+/// Publishes results stored in the outbox every interval
+async fn flush_outbox(outbox: Outbox) {
+    let mut interval = interval(Duration::from_secs(CONFIG.outbox.flush_interval_sec));
+
+    loop {
+        interval.tick().await;
+        if let Err(e) = outbox.flush().await {
+            error!("Error flushing outbox: {}", e);
+        }
+    }
+}
+
+/// Reload the config file and apply topic changes without restarting the worker
+async fn reload_topics(topic_manager: &TopicManager, status_sender: &StatusSender) {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Failed to reload configuration: {:?}", e);
+            return;
+        }
+    };
+
+    // Only topics can be changed at runtime
+    let mut static_config = config.clone();
+    static_config.worker_topics = CONFIG.worker_topics.clone();
+    if static_config != *CONFIG {
+        warn!("Configuration changes other than topics require a restart, ignoring them");
+    }
+
+    if let Err(e) = apply_topics(topic_manager, status_sender, config.worker_topics).await {
+        error!("Failed to update worker topics: {}", e);
+    }
+}
+
+/// Measure the worker baseline against the reference URL, if configured
+async fn calibrate() -> Option<CalibrationResult> {
+    let url = CONFIG.calibration.url.as_ref()?;
+    let max_duration = chrono::Duration::seconds(CONFIG.calibration.duration_sec as i64);
+
+    calibration::process(url, max_duration)
+        .await
+        .inspect_err(|e| error!("Calibration failed: {}", e.error))
+        .ok()
+}
+
+/// Calibrates the worker at startup and then every interval, reporting the new baseline to scheduler
+async fn calibrate_periodically(status_sender: StatusSender, measurement_lock: Arc<Mutex<()>>) {
+    if CONFIG.calibration.url.is_none() {
+        return;
+    }
+
+    // The startup calibration waits for a job already being measured instead of skipping
+    {
+        let _guard = measurement_lock.lock().await;
+        send_calibration(&status_sender).await;
+    }
+
+    let mut interval = interval(Duration::from_secs(CONFIG.calibration.interval_sec));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // First tick completes immediately, startup calibration already happened
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        // Skip the round when a job is being measured, it will be retried on the next tick
+        let Ok(_guard) = measurement_lock.try_lock() else {
+            warn!("Job in progress, skipping calibration");
+            continue;
+        };
+
+        send_calibration(&status_sender).await;
+    }
+}
+
+/// Calibrate and report the new baseline, failures keep the previous one
+async fn send_calibration(status_sender: &StatusSender) {
+    let Some(calibration) = calibrate().await else {
+        return;
+    };
+
+    if let Err(e) = status_sender
+        .send_lifecycle_status(WorkerStatus::Online, Some(calibration))
+        .await
+    {
+        error!("Error sending calibration status: {}", e);
+    }
+}
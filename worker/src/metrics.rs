@@ -0,0 +1,261 @@
+use std::fs;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use rabbitmq::{HostMetrics, NetworkMetrics};
+use tokio::time::Instant;
+
+use crate::CONFIG;
+
+/// Samples host resource usage from /proc and /sys.
+/// CPU and NIC rates are averaged between two consecutive calls to `sample`.
+pub struct HostMetricsSampler {
+    interface: Option<String>,
+    last_cpu: CpuTimes,
+    last_net: Option<NetCounters>,
+    last_sample: Instant,
+}
+
+#[derive(Clone, Copy)]
+struct CpuTimes {
+    busy: u64,
+    total: u64,
+}
+
+#[derive(Clone, Copy)]
+struct NetCounters {
+    rx_bytes: u64,
+    tx_bytes: u64,
+}
+
+impl HostMetricsSampler {
+    pub fn new() -> Result<Self> {
+        let interface = match &CONFIG.network.metrics_interface {
+            Some(interface) => Some(interface.clone()),
+            None => default_route_interface().ok(),
+        };
+        let last_net = interface
+            .as_deref()
+            .and_then(|interface| read_net_counters(interface).ok());
+
+        Ok(Self {
+            last_cpu: read_cpu_times()?,
+            last_net,
+            last_sample: Instant::now(),
+            interface,
+        })
+    }
+
+    /// Take a new sample, rates are calculated since the previous sample
+    pub fn sample(&mut self) -> Result<HostMetrics> {
+        let now = Instant::now();
+        let window_secs = (now - self.last_sample).as_secs_f64();
+
+        let cpu = read_cpu_times()?;
+        let cpu_total = cpu.total.saturating_sub(self.last_cpu.total);
+        let cpu_usage_percent = if cpu_total == 0 {
+            0.0
+        } else {
+            cpu.busy.saturating_sub(self.last_cpu.busy) as f64 * 100.0 / cpu_total as f64
+        };
+
+        let (memory_total_bytes, memory_available_bytes) = read_memory()?;
+
+        let network = match (&self.interface, self.last_net) {
+            (Some(interface), Some(last_net)) if window_secs > 0.0 => {
+                let net = read_net_counters(interface)?;
+                let metrics = NetworkMetrics {
+                    interface: interface.clone(),
+                    rx_bytes_per_sec: net.rx_bytes.saturating_sub(last_net.rx_bytes) as f64
+                        / window_secs,
+                    tx_bytes_per_sec: net.tx_bytes.saturating_sub(last_net.tx_bytes) as f64
+                        / window_secs,
+                    link_speed_mbps: read_link_speed(interface),
+                };
+                self.last_net = Some(net);
+
+                Some(metrics)
+            }
+            _ => None,
+        };
+
+        self.last_cpu = cpu;
+        self.last_sample = now;
+
+        Ok(HostMetrics {
+            sampled_at: Utc::now(),
+            window_secs,
+            cpu_usage_percent,
+            memory_total_bytes,
+            memory_available_bytes,
+            network,
+        })
+    }
+}
+
+/// Read aggregated CPU times from /proc/stat
+fn read_cpu_times() -> Result<CpuTimes> {
+    parse_cpu_times(&fs::read_to_string("/proc/stat").context("Failed to read /proc/stat")?)
+}
+
+/// Aggregated CPU times from the `cpu` line of /proc/stat
+fn parse_cpu_times(stat: &str) -> Result<CpuTimes> {
+    let line = stat
+        .lines()
+        .find(|line| line.starts_with("cpu "))
+        .ok_or_else(|| anyhow!("Missing cpu line in /proc/stat"))?;
+
+    let values: Vec<u64> = line
+        .split_whitespace()
+        .skip(1)
+        .map(|v| v.parse::<u64>())
+        .collect::<Result<_, _>>()?;
+
+    // user nice system idle iowait irq softirq steal (guest time is already included in user)
+    let total: u64 = values.iter().take(8).sum();
+    let idle = values.get(3).copied().unwrap_or(0) + values.get(4).copied().unwrap_or(0);
+
+    Ok(CpuTimes {
+        busy: total.saturating_sub(idle),
+        total,
+    })
+}
+
+/// Read total and available memory in bytes from /proc/meminfo
+fn read_memory() -> Result<(u64, u64)> {
+    parse_memory(&fs::read_to_string("/proc/meminfo").context("Failed to read /proc/meminfo")?)
+}
+
+/// Total and available memory in bytes from /proc/meminfo
+fn parse_memory(meminfo: &str) -> Result<(u64, u64)> {
+    let read_kb = |key: &str| -> Result<u64> {
+        meminfo
+            .lines()
+            .find_map(|line| line.strip_prefix(key))
+            .and_then(|rest| rest.strip_prefix(':'))
+            .and_then(|rest| rest.split_whitespace().next())
+            .ok_or_else(|| anyhow!("Missing {} in /proc/meminfo", key))?
+            .parse::<u64>()
+            .map(|kb| kb * 1024)
+            .map_err(Into::into)
+    };
+
+    Ok((read_kb("MemTotal")?, read_kb("MemAvailable")?))
+}
+
+/// Read rx and tx byte counters of the interface from /proc/net/dev
+fn read_net_counters(interface: &str) -> Result<NetCounters> {
+    let dev = fs::read_to_string("/proc/net/dev").context("Failed to read /proc/net/dev")?;
+    parse_net_counters(&dev, interface)
+}
+
+/// Rx and tx byte counters of the interface from /proc/net/dev
+fn parse_net_counters(dev: &str, interface: &str) -> Result<NetCounters> {
+    let values: Vec<u64> = dev
+        .lines()
+        .filter_map(|line| line.trim_start().split_once(':'))
+        .find(|(name, _)| *name == interface)
+        .ok_or_else(|| anyhow!("Interface {} not found in /proc/net/dev", interface))?
+        .1
+        .split_whitespace()
+        .map(|v| v.parse::<u64>())
+        .collect::<Result<_, _>>()?;
+
+    // Receive columns come first (8 of them), followed by transmit columns
+    match (values.first(), values.get(8)) {
+        (Some(&rx_bytes), Some(&tx_bytes)) => Ok(NetCounters { rx_bytes, tx_bytes }),
+        _ => Err(anyhow!("Malformed /proc/net/dev entry for {}", interface)),
+    }
+}
+
+/// Link speed in Mbps, virtual interfaces usually don't report it
+fn read_link_speed(interface: &str) -> Option<u64> {
+    parse_link_speed(&fs::read_to_string(format!("/sys/class/net/{}/speed", interface)).ok()?)
+}
+
+/// Link speed in Mbps, unknown speeds are reported as -1
+fn parse_link_speed(speed: &str) -> Option<u64> {
+    speed
+        .trim()
+        .parse::<i64>()
+        .ok()
+        .filter(|speed| *speed > 0)
+        .map(|speed| speed as u64)
+}
+
+/// Find the interface of the default route in /proc/net/route
+fn default_route_interface() -> Result<String> {
+    let routes = fs::read_to_string("/proc/net/route").context("Failed to read /proc/net/route")?;
+    parse_default_route_interface(&routes)
+}
+
+/// Interface of the default route, the one with destination 0.0.0.0
+fn parse_default_route_interface(routes: &str) -> Result<String> {
+    routes
+        .lines()
+        .skip(1)
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .find(|columns| columns.get(1) == Some(&"00000000"))
+        .and_then(|columns| columns.first().map(|iface| iface.to_string()))
+        .ok_or_else(|| anyhow!("No default route found"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cpu_times() {
+        let stat = "cpu  100 5 50 800 20 3 2 10 7 0\ncpu0 50 2 25 400 10 1 1 5 3 0\nintr 12345\n";
+        let cpu = parse_cpu_times(stat).unwrap();
+
+        // Guest columns are left out, idle and iowait are not busy
+        assert_eq!(cpu.total, 990);
+        assert_eq!(cpu.busy, 170);
+
+        assert!(parse_cpu_times("cpu0 1 2 3 4\n").is_err());
+        assert!(parse_cpu_times("cpu  1 x 3 4\n").is_err());
+    }
+
+    #[test]
+    fn parses_memory() {
+        let meminfo = "MemTotal:        8000000 kB\nMemFree:         1000000 kB\nMemAvailable:    4000000 kB\n";
+        assert_eq!(
+            parse_memory(meminfo).unwrap(),
+            (8_000_000 * 1024, 4_000_000 * 1024)
+        );
+
+        assert!(parse_memory("MemTotal:        8000000 kB\n").is_err());
+    }
+
+    #[test]
+    fn parses_net_counters() {
+        let dev = "Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo:    5000      50    0    0    0     0          0         0     5000      50    0    0    0     0       0          0
+  eth0: 1000000    900    0    0    0     0          0         0   250000     400    0    0    0     0       0          0
+";
+        let eth0 = parse_net_counters(dev, "eth0").unwrap();
+        assert_eq!((eth0.rx_bytes, eth0.tx_bytes), (1_000_000, 250_000));
+
+        assert!(parse_net_counters(dev, "eth1").is_err());
+        assert!(parse_net_counters("eth0: 1 2 3\n", "eth0").is_err());
+    }
+
+    #[test]
+    fn parses_link_speed() {
+        assert_eq!(parse_link_speed("1000\n"), Some(1000));
+        assert_eq!(parse_link_speed("-1\n"), None);
+        assert_eq!(parse_link_speed(""), None);
+    }
+
+    #[test]
+    fn finds_default_route_interface() {
+        let routes = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask
+docker0\t000011AC\t00000000\t0001\t0\t0\t0\t0000FFFF
+eth0\t00000000\t0100A8C0\t0003\t0\t0\t100\t00000000
+";
+        assert_eq!(parse_default_route_interface(routes).unwrap(), "eth0");
+        assert!(parse_default_route_interface("Iface\tDestination\n").is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use rabbitmq::{Encoding, Envelope, Message, MessageBus, MESSAGE_VERSION};
+use tokio::{fs, io::AsyncWriteExt, sync::Mutex};
+use tracing::{debug, error, info};
+use uuid::Uuid;
+
+/// Results waiting to be published, one file per result.
+/// They are kept on disk until the broker accepts them, so they survive broker outages and restarts.
+#[derive(Clone)]
+pub struct Outbox {
+    dir: PathBuf,
+    data_queue: Arc<dyn MessageBus>,
+    routing_key: String,
+    // Sender of the stored envelopes
+    worker_name: String,
+    // Prevents publishing the same file twice from concurrent flushes
+    flush_lock: Arc<Mutex<()>>,
+}
+
+impl Outbox {
+    pub async fn new(
+        dir: PathBuf,
+        data_queue: Arc<dyn MessageBus>,
+        routing_key: &str,
+        worker_name: &str,
+    ) -> Result<Self> {
+        fs::create_dir_all(&dir)
+            .await
+            .with_context(|| format!("Failed to create outbox directory {}", dir.display()))?;
+        let dir = fs::canonicalize(&dir).await.unwrap_or(dir);
+        info!("Results are stored in the outbox {}", dir.display());
+
+        Ok(Self {
+            dir,
+            data_queue,
+            routing_key: routing_key.to_string(),
+            worker_name: worker_name.to_string(),
+            flush_lock: Arc::new(Mutex::new(())),
+        })
+    }
+
+    /// Persist the message, it's durable once this returns
+    pub async fn store(&self, run_id: Uuid, message: &Message) -> Result<()> {
+        // Wrap now, so the message id stays the same across publish attempts
+        let envelope = Envelope::new(message.clone(), &self.worker_name);
+        let content = envelope.encode(MESSAGE_VERSION, Encoding::Json)?;
+
+        // Prefix with the timestamp, so results are published in the order they were created
+        let file_name = format!("{}-{}.json", Utc::now().timestamp_millis(), run_id);
+        let tmp_path = self.dir.join(format!("{}.tmp", file_name));
+        let path = self.dir.join(file_name);
+
+        // Write to a temporary file first, a crash can't leave a partially written result behind
+        let mut file = fs::File::create(&tmp_path).await?;
+        file.write_all(&content).await?;
+        file.sync_all().await?;
+        fs::rename(&tmp_path, &path).await?;
+
+        debug!("Stored result in outbox: {}", path.display());
+
+        Ok(())
+    }
+
+    /// Publish stored results in order, stops at the first failure.
+    /// Returns the number of published results.
+    pub async fn flush(&self) -> Result<usize> {
+        let _guard = self.flush_lock.lock().await;
+
+        let mut paths = Vec::new();
+        let mut entries = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let mut published = 0;
+        for path in paths {
+            let content = fs::read(&path).await?;
+
+            // Files written before the envelope was introduced are up-converted
+            let envelope = match Envelope::decode(&content, Encoding::Json) {
+                Ok(envelope) => envelope,
+                Err(e) => {
+                    // Keep it for manual inspection, but don't block the rest of the outbox
+                    error!("Invalid message in outbox {}: {}", path.display(), e);
+                    fs::rename(&path, path.with_extension("invalid")).await?;
+                    continue;
+                }
+            };
+
+            self.data_queue
+                .publish_envelope(&envelope, &self.routing_key)
+                .await
+                .map_err(|e| anyhow!("Error publishing result: {}", e))?;
+
+            fs::remove_file(&path).await?;
+            published += 1;
+        }
+
+        if published > 0 {
+            info!("Published {} results from outbox", published);
+        }
+
+        Ok(published)
+    }
+}
@@ -1,37 +1,65 @@
-use amqprs::{
-    channel::{BasicAckArguments, Channel},
-    consumer::AsyncConsumer,
-    BasicProperties, Deliver,
-};
+use std::{sync::Arc, time::Duration};
+
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use chrono::Utc;
-use rabbitmq::{JobMessage, Message, QueueHandler, ResultMessage, WorkerStatusJobDetails};
-use serde_json;
-use tokio::time::sleep;
+use rabbitmq::{
+    Acknowledgement, ControlMessage, Delivery, Envelope, JobMessage, Message, MessageHandler,
+    ResultMessage, WorkerStatusJobDetails,
+};
+use tokio::{
+    sync::{mpsc, Mutex},
+    time::{sleep, timeout},
+};
 use tracing::{debug, error, info};
 use uuid::Uuid;
 
-use crate::{handlers::*, CONFIG};
+use crate::{
+    config::Config,
+    handlers::{download::ProgressReporter, *},
+    metrics::HostMetricsSampler,
+    outbox::Outbox,
+    topics::{apply_topics, TopicManager},
+};
 
 use super::status_sender::StatusSender;
 
+// Progress of a minute long download, in case the broker is slow
+const PROGRESS_BUFFER: usize = 64;
+// Progress still unpublished after this time is dropped, it must not delay the result
+const PROGRESS_FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub struct JobConsumer {
-    data_queue: QueueHandler,
+    worker_name: String,
+    report_progress: bool,
+    outbox: Outbox,
     status_sender: StatusSender,
+    topic_manager: TopicManager,
+    measurement_lock: Arc<Mutex<()>>,
 }
 
 impl JobConsumer {
-    pub fn new(data_queue: QueueHandler, status_sender: StatusSender) -> Self {
+    pub fn new(
+        config: &Config,
+        outbox: Outbox,
+        status_sender: StatusSender,
+        topic_manager: TopicManager,
+        measurement_lock: Arc<Mutex<()>>,
+    ) -> Self {
         Self {
-            data_queue,
+            worker_name: config.worker_name.clone(),
+            report_progress: config.measurement.report_progress,
+            outbox,
             status_sender,
+            topic_manager,
+            measurement_lock,
         }
     }
 
-    async fn parse_message(&self, content_str: &str) -> Result<(Uuid, JobMessage)> {
-        match serde_json::from_str::<Message>(content_str) {
-            Ok(Message::WorkerJob { job_id, payload }) => Ok((job_id, payload)),
+    async fn parse_message(&self, delivery: &Delivery) -> Result<Message> {
+        match Envelope::from_delivery(delivery).map(|envelope| envelope.message) {
+            Ok(message @ Message::WorkerJob { .. }) => Ok(message),
+            Ok(message @ Message::WorkerControl { .. }) => Ok(message),
             Ok(_) => Err(anyhow!("Received unexpected message")),
             Err(e) => {
                 error!("Error parsing message: {:?}", e);
@@ -40,6 +68,29 @@ impl JobConsumer {
         }
     }
 
+    #[tracing::instrument(skip(self))]
+    async fn process_control_message(
+        &self,
+        worker_name: String,
+        command: ControlMessage,
+    ) -> Result<()> {
+        info!("Handling control message");
+
+        if worker_name != self.worker_name {
+            return Err(anyhow!("Control message addressed to another worker"));
+        }
+
+        match command {
+            ControlMessage::SetTopics(topics) => {
+                apply_topics(&self.topic_manager, &self.status_sender, topics)
+                    .await
+                    .map_err(|e| anyhow!("Failed to update worker topics: {}", e))?;
+            }
+        }
+
+        Ok(())
+    }
+
     #[tracing::instrument(skip(self, job_message), fields(sub_job_id = %job_message.sub_job_id))]
     async fn process_message(
         &self,
@@ -56,9 +107,12 @@ impl JobConsumer {
             run_id,
             job_id,
             sub_job_id,
-            worker_name: CONFIG.worker_name.to_string(),
+            worker_name: self.worker_name.clone(),
         };
 
+        // Wait for a running calibration to finish before measuring
+        let _guard = self.measurement_lock.lock().await;
+
         if job_message.start_time < Utc::now() {
             error!(
                 "Start time is in the past, start_time: {}",
@@ -68,7 +122,7 @@ impl JobConsumer {
                 run_id,
                 job_id,
                 sub_job_id,
-                CONFIG.worker_name.to_string(),
+                self.worker_name.clone(),
                 "Start time is in the past".to_string(),
             ));
         }
@@ -85,12 +139,56 @@ impl JobConsumer {
         // Delay the execution to sync the time on every worker
         sleep(sleep_duration.to_std()?).await;
 
+        // Track host resources during the measurement to tell apart worker-bound results
+        let mut sampler = HostMetricsSampler::new()
+            .inspect_err(|e| debug!("Host metrics are not available: {}", e))
+            .ok();
+
+        // Progress is published in the background, so the download doesn't wait for the broker
+        let (progress, progress_publisher) = if self.report_progress {
+            let (sender, mut receiver) = mpsc::channel(PROGRESS_BUFFER);
+            let status_sender = self.status_sender.clone();
+            let publisher = tokio::spawn(async move {
+                while let Some(progress) = receiver.recv().await {
+                    status_sender
+                        .send_progress_status(progress)
+                        .await
+                        .inspect_err(|e| debug!("Error sending download progress: {}", e))
+                        .ok();
+                }
+            });
+            (Some(ProgressReporter { run_id, sender }), Some(publisher))
+        } else {
+            (None, None)
+        };
+
         let (download_result, ping_result, head_result) = tokio::join!(
-            download::process(job_id, job_message.clone()),
+            download::process(job_id, job_message.clone(), progress),
             ping::process(job_id, job_message.clone()),
             head::process(job_id, job_message.clone()),
         );
 
+        let host_metrics = sampler.as_mut().and_then(|sampler| {
+            sampler
+                .sample()
+                .inspect_err(|e| debug!("Failed to sample host metrics: {}", e))
+                .ok()
+        });
+
+        // The reporter was dropped with the download, publish the rest before the final status
+        if let Some(mut publisher) = progress_publisher {
+            if timeout(PROGRESS_FLUSH_TIMEOUT, &mut publisher)
+                .await
+                .is_err()
+            {
+                debug!(
+                    "Dropping progress not published within {:?}",
+                    PROGRESS_FLUSH_TIMEOUT
+                );
+                publisher.abort();
+            }
+        }
+
         debug!(
             "Results: {:#?} {:#?} {:#?}",
             ping_result, head_result, download_result,
@@ -106,31 +204,37 @@ impl JobConsumer {
             run_id,
             job_id,
             sub_job_id,
-            worker_name: CONFIG.worker_name.to_string(),
+            worker_name: self.worker_name.clone(),
             // download result is the most important one and determines the success of the job (at least for now)
             is_success: download_result.is_ok(),
             download_result,
             ping_result,
             head_result,
+            host_metrics,
         })
     }
 
-    pub async fn run(&self, content: Vec<u8>) -> Result<()> {
-        let content_str = String::from_utf8(content)?;
-
+    pub async fn run(&self, delivery: Delivery) -> Result<()> {
         // Parse the received message
-        let (job_id, job_message) = self.parse_message(&content_str).await?;
+        let (job_id, job_message) = match self.parse_message(&delivery).await? {
+            Message::WorkerJob { job_id, payload } => (job_id, payload),
+            Message::WorkerControl {
+                worker_name,
+                command,
+            } => return self.process_control_message(worker_name, command).await,
+            _ => return Err(anyhow!("Received unexpected message")),
+        };
 
         // React to the received data
         let result = self.process_message(job_id, job_message).await?;
+        let run_id = result.run_id;
         let result_message = Message::WorkerResult { job_id, result };
 
-        // Publish the result
-        if let Err(e) = self
-            .data_queue
-            .publish(&result_message, self.data_queue.routing_key.unwrap())
-            .await
-        {
+        // Persist the result before the job is acked, so it's not lost when the broker is unreachable
+        self.outbox.store(run_id, &result_message).await?;
+
+        // Publish the result, on failure it stays in the outbox and will be retried in the background
+        if let Err(e) = self.outbox.flush().await {
             error!("Error publishing result: {:?}", e);
         }
 
@@ -139,15 +243,9 @@ impl JobConsumer {
 }
 
 #[async_trait]
-impl AsyncConsumer for JobConsumer {
-    async fn consume(
-        &mut self,
-        channel: &Channel,
-        deliver: Deliver,
-        _basic_properties: BasicProperties,
-        content: Vec<u8>,
-    ) {
-        match self.run(content).await {
+impl MessageHandler for JobConsumer {
+    async fn handle(&self, delivery: Delivery) -> Acknowledgement {
+        match self.run(delivery).await {
             Ok(_) => {
                 info!("Message processed successfully");
             }
@@ -157,8 +255,112 @@ impl AsyncConsumer for JobConsumer {
         }
 
         // Ack the message in any case. The result will be relevant only when its immediately processed.
-        let args = BasicAckArguments::new(deliver.delivery_tag(), false);
-        channel.basic_ack(args).await.unwrap();
-        debug!("Acked message");
+        Acknowledgement::Ack
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use chrono::TimeDelta;
+    use rabbitmq::{InMemoryBroker, MessageBus, RESULT_ROUTING_KEY};
+    use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+    use super::*;
+
+    struct Collector(UnboundedSender<Message>);
+
+    #[async_trait]
+    impl MessageHandler for Collector {
+        async fn handle(&self, delivery: Delivery) -> Acknowledgement {
+            let envelope = Envelope::from_delivery(&delivery).unwrap();
+            self.0.send(envelope.message).unwrap();
+            Acknowledgement::Ack
+        }
+    }
+
+    #[tokio::test]
+    async fn publishes_result_of_delivered_job() {
+        let config: Config = toml::from_str(r#"name = "worker-1""#).unwrap();
+        let broker = InMemoryBroker::new();
+
+        let scheduler_jobs = broker.bus("job_exchange", "scheduler", "scheduler", "scheduler");
+        let scheduler_results = broker.bus(
+            "result_exchange",
+            "result_queue",
+            RESULT_ROUTING_KEY,
+            "scheduler",
+        );
+        let (results, mut received) = unbounded_channel();
+        scheduler_results
+            .subscribe(Arc::new(Collector(results)))
+            .await
+            .unwrap();
+
+        let job_queue = Arc::new(broker.bus("job_exchange", "worker-1", "worker-1", "worker-1"));
+        let topic_manager = TopicManager::new(job_queue.clone(), vec!["all".to_string()]);
+        job_queue.bind_topic("all").await.unwrap();
+        let status_queue = broker.bus(
+            "status_exchange",
+            "status_queue",
+            "worker_status",
+            "worker-1",
+        );
+        let data_queue = broker.bus(
+            "result_exchange",
+            "result_queue",
+            RESULT_ROUTING_KEY,
+            "worker-1",
+        );
+        let outbox_dir = env::temp_dir().join(format!("outbox-{}", Uuid::new_v4()));
+        let outbox = Outbox::new(
+            outbox_dir.clone(),
+            Arc::new(data_queue),
+            RESULT_ROUTING_KEY,
+            &config.worker_name,
+        )
+        .await
+        .unwrap();
+        let consumer = JobConsumer::new(
+            &config,
+            outbox,
+            StatusSender::new(
+                Arc::new(status_queue),
+                topic_manager.clone(),
+                config.worker_name.clone(),
+            ),
+            topic_manager,
+            Arc::new(Mutex::new(())),
+        );
+        job_queue.subscribe(Arc::new(consumer)).await.unwrap();
+
+        // Already started, so the worker reports it as aborted without measuring
+        let job_id = Uuid::new_v4();
+        let sub_job_id = Uuid::new_v4();
+        let start_time = Utc::now() - TimeDelta::seconds(10);
+        let job = Message::WorkerJob {
+            job_id,
+            payload: JobMessage {
+                job_id,
+                sub_job_id,
+                url: "http://example.com/file".to_string(),
+                start_time,
+                download_start_time: start_time,
+                start_range: 0,
+                end_range: 1024,
+            },
+        };
+        scheduler_jobs.publish(&job, "all").await.unwrap();
+
+        let Some(Message::WorkerResult { result, .. }) = received.recv().await else {
+            panic!("Expected a worker result");
+        };
+        assert_eq!(result.job_id, job_id);
+        assert_eq!(result.sub_job_id, sub_job_id);
+        assert_eq!(result.worker_name, "worker-1");
+        assert!(!result.is_success);
+
+        fs::remove_dir_all(outbox_dir).ok();
     }
 }
@@ -1,33 +1,47 @@
+use std::sync::Arc;
+
 use chrono::Utc;
 use rabbitmq::{
-    Message, QueueHandler, StatusMessage, WorkerDetails, WorkerStatus, WorkerStatusDetails,
-    WorkerStatusJobDetails, CONFIG_QUEUE_STATUS,
+    CalibrationResult, DownloadProgress, HostMetrics, Message, MessageBus, StatusMessage,
+    WorkerDetails, WorkerStatus, WorkerStatusDetails, WorkerStatusJobDetails, STATUS_ROUTING_KEY,
 };
 
-use crate::CONFIG;
+use crate::topics::TopicManager;
 
 #[derive(Clone)]
 pub struct StatusSender {
-    status_queue: QueueHandler,
+    status_queue: Arc<dyn MessageBus>,
+    topic_manager: TopicManager,
+    worker_name: String,
 }
 
 impl StatusSender {
-    pub fn new(status_queue: QueueHandler) -> Self {
-        StatusSender { status_queue }
+    pub fn new(
+        status_queue: Arc<dyn MessageBus>,
+        topic_manager: TopicManager,
+        worker_name: String,
+    ) -> Self {
+        StatusSender {
+            status_queue,
+            topic_manager,
+            worker_name,
+        }
     }
 
     pub async fn send_lifecycle_status(
         &self,
         status: WorkerStatus,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+        calibration: Option<CalibrationResult>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let message = Message::WorkerStatus {
             status: StatusMessage {
                 status: WorkerStatusDetails::Lifecycle(WorkerDetails {
-                    worker_topics: CONFIG.worker_topics.clone(),
+                    worker_topics: self.topic_manager.topics().await,
                     worker_status: status,
+                    calibration,
                 }),
                 timestamp: Utc::now(),
-                worker_name: CONFIG.worker_name.to_string(),
+                worker_name: self.worker_name.clone(),
             },
         };
 
@@ -39,12 +53,12 @@ impl StatusSender {
     pub async fn send_job_status(
         &self,
         job_details: Option<WorkerStatusJobDetails>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let message = Message::WorkerStatus {
             status: StatusMessage {
                 status: WorkerStatusDetails::Job(job_details),
                 timestamp: Utc::now(),
-                worker_name: CONFIG.worker_name.to_string(),
+                worker_name: self.worker_name.clone(),
             },
         };
 
@@ -53,12 +67,15 @@ impl StatusSender {
         Ok(())
     }
 
-    pub async fn send_heartbeat_status(&self) -> Result<(), Box<dyn std::error::Error>> {
+    pub async fn send_heartbeat_status(
+        &self,
+        host_metrics: Option<HostMetrics>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let message = Message::WorkerStatus {
             status: StatusMessage {
-                status: WorkerStatusDetails::Heartbeat,
+                status: WorkerStatusDetails::Heartbeat(host_metrics),
                 timestamp: Utc::now(),
-                worker_name: CONFIG.worker_name.to_string(),
+                worker_name: self.worker_name.clone(),
             },
         };
 
@@ -67,9 +84,29 @@ impl StatusSender {
         Ok(())
     }
 
-    async fn send_status(&self, message: Message) -> Result<(), Box<dyn std::error::Error>> {
+    pub async fn send_progress_status(
+        &self,
+        progress: DownloadProgress,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let message = Message::WorkerStatus {
+            status: StatusMessage {
+                status: WorkerStatusDetails::Progress(progress),
+                timestamp: Utc::now(),
+                worker_name: self.worker_name.clone(),
+            },
+        };
+
+        self.send_status(message).await?;
+
+        Ok(())
+    }
+
+    async fn send_status(
+        &self,
+        message: Message,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         self.status_queue
-            .publish(&message, CONFIG_QUEUE_STATUS.routing_key.unwrap())
+            .publish(&message, STATUS_ROUTING_KEY)
             .await?;
 
         Ok(())
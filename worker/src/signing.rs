@@ -0,0 +1,46 @@
+use std::{fs, io::Write, os::unix::fs::OpenOptionsExt, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use rabbitmq::signing::{decode_signing_key, encode_public_key, encode_signing_key, SigningKey};
+use tracing::info;
+
+/// Load the key signing the worker messages, a new key is generated on the first start.
+/// The public key has to be registered with the scheduler, otherwise the messages are not trusted.
+pub fn load_or_generate_key(path: &Path) -> Result<SigningKey> {
+    let signing_key = if path.exists() {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read signing key {}", path.display()))?;
+        decode_signing_key(&content)
+            .map_err(|e| anyhow!("Invalid signing key {}: {}", path.display(), e))?
+    } else {
+        let signing_key = SigningKey::from_bytes(&rand::random::<[u8; 32]>());
+        write_key(path, &signing_key)
+            .with_context(|| format!("Failed to write signing key {}", path.display()))?;
+        info!("Generated new signing key: {}", path.display());
+        signing_key
+    };
+
+    info!(
+        "Worker public key: {}",
+        encode_public_key(&signing_key.verifying_key())
+    );
+
+    Ok(signing_key)
+}
+
+fn write_key(path: &Path, signing_key: &SigningKey) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+
+    // Readable only by the worker user, fails if another process created the file meanwhile
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(encode_signing_key(signing_key).as_bytes())?;
+    file.sync_all()?;
+
+    Ok(())
+}
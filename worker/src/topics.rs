@@ -0,0 +1,86 @@
+use std::sync::Arc;
+
+use rabbitmq::{MessageBus, WorkerStatus};
+use tokio::sync::Mutex;
+use tracing::info;
+
+use crate::{config::normalize_topics, queue::status_sender::StatusSender};
+
+/// Keeps the job queue bindings in sync with the topics the worker is interested in
+#[derive(Clone)]
+pub struct TopicManager {
+    job_queue: Arc<dyn MessageBus>,
+    topics: Arc<Mutex<Vec<String>>>,
+}
+
+impl TopicManager {
+    pub fn new(job_queue: Arc<dyn MessageBus>, topics: Vec<String>) -> Self {
+        Self {
+            job_queue,
+            topics: Arc::new(Mutex::new(topics)),
+        }
+    }
+
+    /// Topics the job queue is currently bound to
+    pub async fn topics(&self) -> Vec<String> {
+        self.topics.lock().await.clone()
+    }
+
+    /// Bind the new topics and unbind the removed ones.
+    /// Returns true if the bindings have changed.
+    pub async fn set_topics(
+        &self,
+        topics: Vec<String>,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let topics = normalize_topics(topics)?;
+        let mut current = self.topics.lock().await;
+
+        let added: Vec<String> = topics
+            .iter()
+            .filter(|topic| !current.contains(topic))
+            .cloned()
+            .collect();
+        let removed: Vec<String> = current
+            .iter()
+            .filter(|topic| !topics.contains(topic))
+            .cloned()
+            .collect();
+
+        if added.is_empty() && removed.is_empty() {
+            return Ok(false);
+        }
+
+        // Bind first, so the worker doesn't miss jobs for topics it keeps
+        for topic in &added {
+            self.job_queue.bind_topic(topic).await?;
+            current.push(topic.clone());
+        }
+        for topic in &removed {
+            self.job_queue.unbind_topic(topic).await?;
+            current.retain(|t| t != topic);
+        }
+
+        info!(
+            "Worker topics changed, added: {:?} removed: {:?}",
+            added, removed
+        );
+
+        Ok(true)
+    }
+}
+
+/// Apply the topics and announce them to the scheduler, so it knows where to route jobs
+pub async fn apply_topics(
+    topic_manager: &TopicManager,
+    status_sender: &StatusSender,
+    topics: Vec<String>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if !topic_manager.set_topics(topics).await? {
+        info!("Worker topics unchanged");
+        return Ok(());
+    }
+
+    status_sender
+        .send_lifecycle_status(WorkerStatus::Online, None)
+        .await
+}